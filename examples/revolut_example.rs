@@ -17,6 +17,7 @@ async fn main() -> Result<()> {
         api_version: "2024-09-01".to_string(),
         token: args().nth(1).unwrap(),
         public_key: "your_public_key".to_string(),
+        webhook_secret: None,
     };
 
     // Create the Revolut API client
@@ -44,6 +45,7 @@ async fn main() -> Result<()> {
             metadata: None,
             tax_amount: Some(1000), // £10.00 VAT (20% on £50)
             tax_name: Some("20% VAT".to_string()),
+            tax_rates: None,
         },
         LineItem {
             name: "Standard Gadget".to_string(),
@@ -55,6 +57,7 @@ async fn main() -> Result<()> {
             metadata: None,
             tax_amount: Some(200), // £2.00 VAT (20% on £10)
             tax_name: Some("20% VAT".to_string()),
+            tax_rates: None,
         },
     ];
 
@@ -96,12 +99,7 @@ async fn main() -> Result<()> {
     println!("Webhooks: {:?}", webhooks);
 
     // Example 6: Advanced line items with Revolut-specific features
-    // Note: To use advanced features like discounts and taxes, you would need to
-    // create a wrapper method in RevolutApi or use the builder pattern directly
-    println!("\nAdvanced Revolut line items example:");
-    println!(
-        "You can create line items with discounts, taxes, and more using RevolutLineItem::simple()"
-    );
+    println!("\nCreating an order with advanced Revolut line items...");
 
     let advanced_item = RevolutLineItem::simple("Example item".to_string(), 2, 100)
         .with_type(RevolutLineItemType::Physical)
@@ -119,11 +117,18 @@ async fn main() -> Result<()> {
         .with_url("https://www.example.com".to_string())
         .with_external_id("external_id_123".to_string());
 
-    println!("Advanced line item created: {:?}", advanced_item);
     println!(
         "Total amount (with discounts and taxes): {}",
         advanced_item.total_amount
     );
+    let advanced_order = revolut
+        .create_order_with_items(
+            Currency::GBP,
+            Some("Order with discounts".to_string()),
+            vec![advanced_item],
+        )
+        .await?;
+    println!("Advanced order created: {:?}", advanced_order);
 
     Ok(())
 }
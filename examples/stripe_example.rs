@@ -16,6 +16,7 @@ async fn main() -> Result<()> {
         url: Some("https://api.stripe.com".to_string()),
         api_key: args().nth(1).unwrap(),
         webhook_secret: Some("your_webhook_secret".to_string()),
+        proxy: None,
     };
 
     // Create the Stripe API client
@@ -25,7 +26,7 @@ async fn main() -> Result<()> {
     println!("Creating a payment intent...");
     let amount = CurrencyAmount::from_f32(Currency::USD, 20.00);
     let payment_intent = stripe
-        .create_payment_intent(amount, Some("Test payment".to_string()))
+        .create_payment_intent(amount, Some("Test payment".to_string()), None)
         .await?;
     println!("Payment Intent created: {:?}", payment_intent);
     println!("Client Secret: {:?}", payment_intent.client_secret);
@@ -63,9 +64,14 @@ async fn main() -> Result<()> {
         client_reference_id: Some("order_123".to_string()),
         metadata: None,
         expires_at: None,
+        discounts: None,
+        allow_promotion_codes: None,
+        connect: None,
     };
 
-    let checkout_session = stripe.create_checkout_session(checkout_request).await?;
+    let checkout_session = stripe
+        .create_checkout_session(checkout_request, None)
+        .await?;
     println!("Checkout Session created: {:?}", checkout_session);
     println!("Checkout URL: {:?}", checkout_session.url);
 
@@ -120,6 +126,7 @@ async fn main() -> Result<()> {
             metadata: None,
             tax_amount: Some(500), // $5.00 tax (10% VAT on $50)
             tax_name: Some("10% VAT".to_string()),
+            tax_rates: None,
         },
         LineItem {
             name: "Standard Gadget".to_string(),
@@ -131,6 +138,7 @@ async fn main() -> Result<()> {
             metadata: None,
             tax_amount: Some(100), // $1.00 tax (10% VAT on $10)
             tax_name: Some("10% VAT".to_string()),
+            tax_rates: None,
         },
     ];
 
@@ -0,0 +1,175 @@
+//! Provider-agnostic checkout.
+//!
+//! [`Checkout`] is a single entry point for applications that accept both
+//! Bitcoin and fiat: it routes crypto amounts to a [`LightningNode`] and fiat
+//! amounts to a [`FiatPaymentService`].
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use payments_rs::checkout::{ChargeResult, Checkout};
+//! use payments_rs::currency::{Currency, CurrencyAmount};
+//! use std::sync::Arc;
+//!
+//! let checkout = Checkout::new()
+//!     .with_lightning(Arc::new(lnd))
+//!     .with_fiat(Arc::new(stripe));
+//!
+//! match checkout.charge(CurrencyAmount::from_f32(Currency::USD, 5.0), "Coffee").await? {
+//!     ChargeResult::Lightning(invoice) => println!("Pay {}", invoice.pr()),
+//!     ChargeResult::Fiat(info) => println!("Pay at {:?}", info.checkout_url),
+//! }
+//! ```
+
+use crate::currency::CurrencyAmount;
+use crate::fiat::{FiatPaymentInfo, FiatPaymentService};
+use crate::lightning::{AddInvoiceRequest, AddInvoiceResponse, LightningNode};
+use anyhow::{Result, bail};
+use std::sync::Arc;
+
+/// Result of [`Checkout::charge`].
+#[derive(Debug)]
+pub enum ChargeResult {
+    /// A crypto amount was charged with a Lightning invoice
+    Lightning(AddInvoiceResponse),
+    /// A fiat amount was charged with a provider order
+    Fiat(FiatPaymentInfo),
+}
+
+impl ChargeResult {
+    /// External ID of the invoice or order, if the provider returned one.
+    pub fn external_id(&self) -> Option<&str> {
+        match self {
+            ChargeResult::Lightning(r) => r.external_id.as_deref(),
+            ChargeResult::Fiat(r) => Some(&r.external_id),
+        }
+    }
+
+    /// What the customer should be sent to in order to pay: the BOLT11
+    /// payment request for Lightning or the hosted checkout URL for fiat.
+    pub fn payment_target(&self) -> Option<String> {
+        match self {
+            ChargeResult::Lightning(r) => Some(r.pr()),
+            ChargeResult::Fiat(r) => r.checkout_url.clone(),
+        }
+    }
+}
+
+/// Routes charges to a Lightning or fiat backend based on the currency.
+#[derive(Clone, Default)]
+pub struct Checkout {
+    lightning: Option<Arc<dyn LightningNode>>,
+    fiat: Option<Arc<dyn FiatPaymentService>>,
+}
+
+impl Checkout {
+    /// Create a checkout with no backends configured.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `node` for crypto (BTC) charges.
+    pub fn with_lightning(mut self, node: Arc<dyn LightningNode>) -> Self {
+        self.lightning = Some(node);
+        self
+    }
+
+    /// Use `service` for fiat charges.
+    pub fn with_fiat(mut self, service: Arc<dyn FiatPaymentService>) -> Self {
+        self.fiat = Some(service);
+        self
+    }
+
+    /// Charge `amount`, creating a Lightning invoice for crypto currencies or a
+    /// fiat order otherwise.
+    ///
+    /// Returns an error if the backend required for the currency is not
+    /// configured.
+    pub async fn charge(&self, amount: CurrencyAmount, description: &str) -> Result<ChargeResult> {
+        if amount.currency().is_crypto() {
+            let Some(node) = &self.lightning else {
+                bail!(
+                    "No lightning backend configured to charge {}",
+                    amount.currency()
+                );
+            };
+            let rsp = node
+                .add_invoice(AddInvoiceRequest {
+                    amount: amount.value(),
+                    memo: Some(description.to_string()),
+                    expire: None,
+                    idempotency_key: None,
+                })
+                .await?;
+            Ok(ChargeResult::Lightning(rsp))
+        } else {
+            let Some(fiat) = &self.fiat else {
+                bail!("No fiat backend configured to charge {}", amount.currency());
+            };
+            let rsp = fiat.create_order(description, amount, None).await?;
+            Ok(ChargeResult::Fiat(rsp))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::currency::Currency;
+    use crate::fiat::test_util::FakeFiat;
+    use crate::lightning::MockLightningNode;
+
+    fn checkout() -> Checkout {
+        Checkout::new()
+            .with_lightning(Arc::new(MockLightningNode::new()))
+            .with_fiat(Arc::new(FakeFiat))
+    }
+
+    #[tokio::test]
+    async fn test_charge_btc_routes_to_lightning() {
+        let res = checkout()
+            .charge(CurrencyAmount::millisats(21_000), "Coffee")
+            .await
+            .unwrap();
+        let ChargeResult::Lightning(invoice) = &res else {
+            panic!("Expected Lightning result, got {:?}", res);
+        };
+        assert_eq!(invoice.parsed_invoice.amount_milli_satoshis(), Some(21_000));
+        assert_eq!(res.external_id(), None);
+        assert!(res.payment_target().unwrap().starts_with("lnbcrt"));
+    }
+
+    #[tokio::test]
+    async fn test_charge_usd_routes_to_fiat() {
+        let res = checkout()
+            .charge(CurrencyAmount::from_u64(Currency::USD, 500), "Coffee")
+            .await
+            .unwrap();
+        let ChargeResult::Fiat(info) = &res else {
+            panic!("Expected Fiat result, got {:?}", res);
+        };
+        assert_eq!(info.raw_data, "Coffee USD 5.00");
+        assert_eq!(res.external_id(), Some("order_1"));
+        assert_eq!(
+            res.payment_target().as_deref(),
+            Some("https://pay.example.com/order_1")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_charge_missing_backend() {
+        let err = Checkout::new()
+            .with_fiat(Arc::new(FakeFiat))
+            .charge(CurrencyAmount::millisats(1000), "Coffee")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("No lightning backend"));
+
+        let err = Checkout::new()
+            .with_lightning(Arc::new(MockLightningNode::new()))
+            .charge(CurrencyAmount::from_u64(Currency::EUR, 100), "Coffee")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("No fiat backend"));
+    }
+}
@@ -3,7 +3,8 @@
 //! This module provides types for representing currencies and monetary amounts
 //! in a type-safe manner.
 
-use anyhow::{Result, ensure};
+use anyhow::{Result, anyhow, bail, ensure};
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::ops::Sub;
 use std::str::FromStr;
@@ -44,6 +45,72 @@ impl Display for Currency {
     }
 }
 
+impl Currency {
+    /// Every supported currency, in declaration order.
+    pub fn all() -> &'static [Currency] {
+        &[
+            Currency::EUR,
+            Currency::BTC,
+            Currency::USD,
+            Currency::GBP,
+            Currency::CAD,
+            Currency::CHF,
+            Currency::AUD,
+            Currency::JPY,
+        ]
+    }
+
+    /// English name of the currency, e.g. `"US Dollar"`.
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            Currency::EUR => "Euro",
+            Currency::BTC => "Bitcoin",
+            Currency::USD => "US Dollar",
+            Currency::GBP => "British Pound Sterling",
+            Currency::CAD => "Canadian Dollar",
+            Currency::CHF => "Swiss Franc",
+            Currency::AUD => "Australian Dollar",
+            Currency::JPY => "Japanese Yen",
+        }
+    }
+
+    /// Number of decimal places between the standard unit and the smallest
+    /// unit stored in a [`CurrencyAmount`].
+    ///
+    /// Most fiat currencies use cents (2), the Japanese Yen has no minor unit
+    /// (0) and Bitcoin is stored in milli-satoshis (11).
+    pub fn exponent(&self) -> u32 {
+        match self {
+            Currency::BTC => 11,
+            Currency::JPY => 0,
+            _ => 2,
+        }
+    }
+
+    /// Whether this currency is a cryptocurrency (settled over Lightning or
+    /// on-chain) rather than a fiat currency.
+    pub fn is_crypto(&self) -> bool {
+        matches!(self, Currency::BTC)
+    }
+
+    /// Symbol shown next to amounts in UIs, e.g. `"$"` or `"€"`.
+    ///
+    /// Currencies sharing a common symbol with others use a disambiguated
+    /// form (`"CA$"`, `"A$"`), the Swiss Franc has no symbol and uses its code.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Currency::EUR => "€",
+            Currency::BTC => "₿",
+            Currency::USD => "$",
+            Currency::GBP => "£",
+            Currency::CAD => "CA$",
+            Currency::CHF => "CHF",
+            Currency::AUD => "A$",
+            Currency::JPY => "¥",
+        }
+    }
+}
+
 /// Error returned when parsing an invalid currency string.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ParseCurrencyError(String);
@@ -111,16 +178,169 @@ impl CurrencyAmount {
     ///
     /// For fiat currencies, this expects the standard unit (e.g., 20.00 for $20).
     /// For Bitcoin, this expects the BTC amount (e.g., 0.001 for 0.001 BTC).
+    /// The value is scaled by [`Currency::exponent`] and truncated, so
+    /// `500.0` JPY is 500 yen.
     pub fn from_f32(currency: Currency, amount: f32) -> Self {
         CurrencyAmount(
             currency,
             match currency {
                 Currency::BTC => (amount as f64 * Self::MILLI_SATS) as u64, // milli-sats
-                _ => (amount * 100.0) as u64,                               // cents
+                _ => (amount * 10f32.powi(currency.exponent() as i32)) as u64,
             },
         )
     }
 
+    /// Parse an exact decimal amount in the standard unit, e.g. `"10.50"` for
+    /// $10.50 or `"0.0001"` for 0.0001 BTC.
+    ///
+    /// Unlike [`CurrencyAmount::from_f32`] there is no rounding: more decimal
+    /// places than the currency's minor unit allows (any for JPY) is an error.
+    pub fn from_decimal_str(currency: Currency, amount: &str) -> Result<Self> {
+        let exp = currency.exponent() as usize;
+        let (whole, frac) = amount.split_once('.').unwrap_or((amount, ""));
+        ensure!(
+            !whole.is_empty() || !frac.is_empty(),
+            "Invalid amount: {:?}",
+            amount
+        );
+        ensure!(
+            whole
+                .bytes()
+                .chain(frac.bytes())
+                .all(|b| b.is_ascii_digit()),
+            "Invalid amount: {:?}",
+            amount
+        );
+        ensure!(
+            frac.len() <= exp,
+            "{} supports at most {} decimal places: {:?}",
+            currency,
+            exp,
+            amount
+        );
+        let overflow = || anyhow!("Amount too large: {:?}", amount);
+        let whole: u64 = if whole.is_empty() {
+            0
+        } else {
+            whole.parse().map_err(|_| overflow())?
+        };
+        let frac: u64 = if frac.is_empty() {
+            0
+        } else {
+            frac.parse::<u64>().map_err(|_| overflow())? * 10u64.pow((exp - frac.len()) as u32)
+        };
+        let value = whole
+            .checked_mul(10u64.pow(exp as u32))
+            .and_then(|v| v.checked_add(frac))
+            .ok_or_else(overflow)?;
+        Ok(CurrencyAmount(currency, value))
+    }
+
+    /// Format the amount in the standard unit with exactly as many decimals
+    /// as the currency has minor units, e.g. `"20.00"` EUR or `"500"` JPY.
+    ///
+    /// The inverse of [`CurrencyAmount::from_decimal_str`].
+    pub fn to_decimal_string(&self) -> String {
+        let exp = self.0.exponent();
+        let scale = 10u64.pow(exp);
+        let (whole, frac) = (self.1 / scale, self.1 % scale);
+        if exp == 0 {
+            whole.to_string()
+        } else {
+            format!("{}.{:0width$}", whole, frac, width = exp as usize)
+        }
+    }
+
+    /// Format the amount with the currency symbol in front, e.g. `"$19.99"`
+    /// or `"¥500"`, with as many decimals as the currency has minor units.
+    ///
+    /// Symbols made of letters are separated by a space (`"CHF 19.99"`).
+    pub fn format_symbol(&self) -> String {
+        self.format_locale("en")
+    }
+
+    /// Format the amount with the currency symbol placed as is usual for
+    /// `locale` (a BCP 47 tag such as `"en-US"` or `"de-DE"`).
+    ///
+    /// Only the language is considered: most continental European languages
+    /// put the symbol after the amount with a decimal comma (`"19,99 €"`),
+    /// everything else puts it in front with a decimal point (`"€19.99"`).
+    /// No digit grouping is applied.
+    pub fn format_locale(&self, locale: &str) -> String {
+        let (whole, frac) = self.standard_unit_parts();
+        let lang = locale
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let suffix = matches!(
+            lang.as_str(),
+            "de" | "fr" | "es" | "it" | "pt" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "da"
+        );
+        let sep = if suffix { "," } else { "." };
+        let number = if frac.is_empty() {
+            whole.to_string()
+        } else {
+            format!("{}{}{}", whole, sep, frac)
+        };
+        let symbol = self.0.symbol();
+        if suffix {
+            format!("{} {}", number, symbol)
+        } else if symbol.ends_with(|c: char| c.is_ascii_alphabetic()) {
+            format!("{} {}", symbol, number)
+        } else {
+            format!("{}{}", symbol, number)
+        }
+    }
+
+    /// Whole standard units and the fractional digits, Bitcoin is shown to
+    /// 8 decimal places unless there is a milli-satoshi remainder. The
+    /// fraction is empty for currencies without minor units.
+    fn standard_unit_parts(&self) -> (u64, String) {
+        let exp = self.0.exponent();
+        let scale = 10u64.pow(exp);
+        let (whole, frac) = (self.1 / scale, self.1 % scale);
+        if exp == 0 {
+            return (whole, String::new());
+        }
+        let mut frac = format!("{:0width$}", frac, width = exp as usize);
+        if self.0 == Currency::BTC {
+            let trimmed = frac.trim_end_matches('0').len().max(8);
+            frac.truncate(trimmed);
+        }
+        (whole, frac)
+    }
+
+    /// The given percentage of this amount, in basis points (`2000` = 20%).
+    ///
+    /// Rounded half up to the currency's minor unit, so 20% of GBP 0.99 is
+    /// GBP 0.20. Saturates at `u64::MAX` minor units.
+    pub fn apply_percent(&self, bps: u32) -> CurrencyAmount {
+        let share = (self.1 as u128 * bps as u128 + 5_000) / 10_000;
+        CurrencyAmount(self.0, share.min(u64::MAX as u128) as u64)
+    }
+
+    /// This net amount plus tax at `bps` basis points, see
+    /// [`CurrencyAmount::apply_percent`] for rounding.
+    pub fn with_tax(&self, bps: u32) -> CurrencyAmount {
+        CurrencyAmount(self.0, self.1.saturating_add(self.apply_percent(bps).1))
+    }
+
+    /// Split into `n` shares that sum exactly to this amount.
+    ///
+    /// The remainder of the division is given out one minor unit at a time to
+    /// the earliest shares, so 100 cents split 3 ways is `[34, 33, 33]`.
+    /// Returns no shares when `n` is zero.
+    pub fn split(&self, n: usize) -> Vec<CurrencyAmount> {
+        if n == 0 {
+            return vec![];
+        }
+        let (share, rem) = (self.1 / n as u64, self.1 % n as u64);
+        (0..n as u64)
+            .map(|i| CurrencyAmount(self.0, share + u64::from(i < rem)))
+            .collect()
+    }
+
     /// Get the raw value in the smallest unit.
     pub fn value(&self) -> u64 {
         self.1
@@ -130,7 +350,7 @@ impl CurrencyAmount {
     pub fn value_f32(&self) -> f32 {
         match self.0 {
             Currency::BTC => (self.1 as f64 / Self::MILLI_SATS) as f32,
-            _ => self.1 as f32 / 100.0,
+            _ => self.1 as f32 / 10f32.powi(self.0.exponent() as i32),
         }
     }
 
@@ -138,6 +358,44 @@ impl CurrencyAmount {
     pub fn currency(&self) -> Currency {
         self.0
     }
+
+    /// Convert into `to` at the exact rate `rate_numerator / rate_denominator`
+    /// (one standard unit of this currency in standard units of `to`), using
+    /// integer arithmetic only.
+    ///
+    /// The converted amount is truncated to whole minor units of `to`. The
+    /// second value is the remainder in minor units of this currency that was
+    /// not converted: converting `self - remainder` gives the same amount with
+    /// no remainder, and the remainder itself is worth less than one minor
+    /// unit of `to`.
+    pub fn convert_exact(
+        &self,
+        to: Currency,
+        rate_numerator: u64,
+        rate_denominator: u64,
+    ) -> Result<(CurrencyAmount, u64)> {
+        ensure!(
+            rate_numerator > 0 && rate_denominator > 0,
+            "Invalid exchange rate for {} -> {}: {}/{}",
+            self.0,
+            to,
+            rate_numerator,
+            rate_denominator
+        );
+        let overflow = || anyhow!("Converted amount overflows: {} -> {}", self, to);
+        // target minor units per source minor unit = num / den
+        let num = (rate_numerator as u128)
+            .checked_mul(10u128.pow(to.exponent()))
+            .ok_or_else(overflow)?;
+        let den = (rate_denominator as u128)
+            .checked_mul(10u128.pow(self.0.exponent()))
+            .ok_or_else(overflow)?;
+        let converted = (self.1 as u128).checked_mul(num).ok_or_else(overflow)? / den;
+        // smallest source amount which still converts to `converted`
+        let used = (converted * den).div_ceil(num);
+        let converted = u64::try_from(converted).map_err(|_| overflow())?;
+        Ok((CurrencyAmount(to, converted), self.1 - used as u64))
+    }
 }
 
 impl Sub for CurrencyAmount {
@@ -155,10 +413,95 @@ impl Sub for CurrencyAmount {
 
 impl Display for CurrencyAmount {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        match self.0 {
-            Currency::BTC => write!(f, "BTC {:.8}", self.value_f32()),
-            _ => write!(f, "{} {:.2}", self.0, self.value_f32()),
+        let (whole, frac) = self.standard_unit_parts();
+        if frac.is_empty() {
+            write!(f, "{} {}", self.0, whole)
+        } else {
+            write!(f, "{} {}.{}", self.0, whole, frac)
+        }
+    }
+}
+
+impl FromStr for CurrencyAmount {
+    type Err = anyhow::Error;
+
+    /// Parse `"<code> <amount>"`, e.g. `"USD 10.50"` or `"BTC 0.0001"`, with
+    /// [`CurrencyAmount::from_decimal_str`].
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parts = s.split_whitespace();
+        let (Some(code), Some(amount), None) = (parts.next(), parts.next(), parts.next()) else {
+            bail!("Expected \"<currency> <amount>\", got {:?}", s);
+        };
+        let currency: Currency = code.parse().map_err(|e| anyhow!("{}", e))?;
+        CurrencyAmount::from_decimal_str(currency, amount)
+    }
+}
+
+/// Converts amounts between currencies, e.g. using rates from an exchange feed.
+pub trait CurrencyConverter {
+    /// Convert `amount` into the currency `to`.
+    fn convert(&self, amount: CurrencyAmount, to: Currency) -> Result<CurrencyAmount>;
+}
+
+/// A [`CurrencyConverter`] backed by a fixed table of exchange rates.
+///
+/// Rates are expressed in standard units: a rate of `50_000.0` from
+/// [`Currency::BTC`] to [`Currency::USD`] means 1 BTC = 50,000 USD. The inverse
+/// direction is derived automatically.
+#[derive(Debug, Clone, Default)]
+pub struct StaticRateConverter {
+    rates: HashMap<(Currency, Currency), f64>,
+}
+
+impl StaticRateConverter {
+    /// Create a converter with no rates.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builder-style method to add the rate for one unit of `from` in `to`.
+    pub fn with_rate(mut self, from: Currency, to: Currency, rate: f64) -> Self {
+        self.rates.insert((from, to), rate);
+        self
+    }
+
+    /// Get the rate for one unit of `from` in `to`, if known.
+    pub fn rate(&self, from: Currency, to: Currency) -> Option<f64> {
+        if from == to {
+            return Some(1.0);
         }
+        self.rates
+            .get(&(from, to))
+            .copied()
+            .or_else(|| self.rates.get(&(to, from)).map(|r| 1.0 / r))
+    }
+}
+
+impl CurrencyConverter for StaticRateConverter {
+    fn convert(&self, amount: CurrencyAmount, to: Currency) -> Result<CurrencyAmount> {
+        let from = amount.currency();
+        if from == to {
+            return Ok(amount);
+        }
+        let rate = self
+            .rate(from, to)
+            .ok_or_else(|| anyhow!("No exchange rate for {} -> {}", from, to))?;
+        ensure!(
+            rate.is_finite() && rate > 0.0,
+            "Invalid exchange rate for {} -> {}: {}",
+            from,
+            to,
+            rate
+        );
+        let scale = 10f64.powi(to.exponent() as i32 - from.exponent() as i32);
+        let value = (amount.value() as f64 * rate * scale).round();
+        ensure!(
+            value <= u64::MAX as f64,
+            "Converted amount overflows: {} -> {}",
+            amount,
+            to
+        );
+        Ok(CurrencyAmount::from_u64(to, value as u64))
     }
 }
 
@@ -166,6 +509,24 @@ impl Display for CurrencyAmount {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_to_decimal_string() {
+        let eur = CurrencyAmount::from_u64(Currency::EUR, 123_405);
+        assert_eq!(eur.to_decimal_string(), "1234.05");
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::JPY, 500).to_decimal_string(),
+            "500"
+        );
+        assert_eq!(
+            CurrencyAmount::millisats(1).to_decimal_string(),
+            "0.00000000001"
+        );
+        assert_eq!(
+            CurrencyAmount::from_decimal_str(Currency::EUR, &eur.to_decimal_string()).unwrap(),
+            eur
+        );
+    }
+
     #[test]
     fn test_currency_display() {
         assert_eq!(Currency::EUR.to_string(), "EUR");
@@ -281,9 +642,358 @@ mod tests {
         assert_eq!(amount.to_string(), "USD 20.00");
     }
 
+    #[test]
+    fn test_currency_amount_format_symbol() {
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::USD, 1999).format_symbol(),
+            "$19.99"
+        );
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::EUR, 1999).format_symbol(),
+            "€19.99"
+        );
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::GBP, 500).format_symbol(),
+            "£5.00"
+        );
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::JPY, 500).format_symbol(),
+            "¥500"
+        );
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::CHF, 1999).format_symbol(),
+            "CHF 19.99"
+        );
+        // Display is unchanged
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::USD, 1999).to_string(),
+            "USD 19.99"
+        );
+    }
+
+    #[test]
+    fn test_currency_amount_format_locale() {
+        let eur = CurrencyAmount::from_u64(Currency::EUR, 1999);
+        assert_eq!(eur.format_locale("de-DE"), "19,99 €");
+        assert_eq!(eur.format_locale("fr_FR"), "19,99 €");
+        assert_eq!(eur.format_locale("en-IE"), "€19.99");
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::JPY, 500).format_locale("de"),
+            "500 ¥"
+        );
+    }
+
+    #[test]
+    fn test_currency_amount_format_from_f32() {
+        let jpy = CurrencyAmount::from_f32(Currency::JPY, 500.0);
+        assert_eq!(jpy.format_symbol(), "¥500");
+        assert_eq!(jpy.format_locale("de-DE"), "500 ¥");
+        let eur = CurrencyAmount::from_f32(Currency::EUR, 19.99);
+        assert_eq!(eur.format_symbol(), "€19.99");
+        assert_eq!(eur.format_locale("de-DE"), "19,99 €");
+        let btc = CurrencyAmount::from_f32(Currency::BTC, 0.5);
+        assert_eq!(btc.format_symbol(), "₿0.50000000");
+    }
+
+    #[test]
+    fn test_apply_percent_gbp_vat() {
+        let net = CurrencyAmount::from_u64(Currency::GBP, 1999);
+        assert_eq!(
+            net.apply_percent(2000),
+            CurrencyAmount::from_u64(Currency::GBP, 400)
+        );
+        assert_eq!(
+            net.with_tax(2000),
+            CurrencyAmount::from_u64(Currency::GBP, 2399)
+        );
+        // 19.8p rounds up, 0.5p rounds half up
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::GBP, 99)
+                .apply_percent(2000)
+                .value(),
+            20
+        );
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::GBP, 5)
+                .apply_percent(1000)
+                .value(),
+            1
+        );
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::GBP, 4)
+                .apply_percent(1000)
+                .value(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_apply_percent_jpy_zero_decimal() {
+        let net = CurrencyAmount::from_u64(Currency::JPY, 1234);
+        assert_eq!(
+            net.apply_percent(1000),
+            CurrencyAmount::from_u64(Currency::JPY, 123)
+        );
+        assert_eq!(
+            net.with_tax(1000),
+            CurrencyAmount::from_u64(Currency::JPY, 1357)
+        );
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::JPY, 15)
+                .apply_percent(1000)
+                .value(),
+            2
+        );
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::JPY, u64::MAX)
+                .with_tax(1000)
+                .value(),
+            u64::MAX
+        );
+    }
+
+    #[test]
+    fn test_split_distributes_remainder() {
+        let total = CurrencyAmount::from_u64(Currency::USD, 100);
+        let shares = total.split(3);
+        assert_eq!(
+            shares.iter().map(|s| s.value()).collect::<Vec<_>>(),
+            vec![34, 33, 33]
+        );
+        assert!(shares.iter().all(|s| s.currency() == Currency::USD));
+        assert_eq!(shares.iter().map(|s| s.value()).sum::<u64>(), 100);
+
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::JPY, 2)
+                .split(4)
+                .iter()
+                .map(|s| s.value())
+                .collect::<Vec<_>>(),
+            vec![1, 1, 0, 0]
+        );
+        assert!(total.split(0).is_empty());
+    }
+
+    #[test]
+    fn test_currency_all() {
+        // Fails to compile when a variant is added, as a reminder to list it
+        // in `Currency::all`.
+        fn index(c: Currency) -> usize {
+            match c {
+                Currency::EUR => 0,
+                Currency::BTC => 1,
+                Currency::USD => 2,
+                Currency::GBP => 3,
+                Currency::CAD => 4,
+                Currency::CHF => 5,
+                Currency::AUD => 6,
+                Currency::JPY => 7,
+            }
+        }
+        let all = Currency::all();
+        assert_eq!(all.len(), 8);
+        for (i, c) in all.iter().enumerate() {
+            assert_eq!(index(*c), i);
+            assert!(!c.display_name().is_empty());
+        }
+        assert_eq!(Currency::USD.display_name(), "US Dollar");
+        assert_eq!(Currency::JPY.display_name(), "Japanese Yen");
+    }
+
+    #[test]
+    fn test_currency_exponent() {
+        assert_eq!(Currency::USD.exponent(), 2);
+        assert_eq!(Currency::JPY.exponent(), 0);
+        assert_eq!(Currency::BTC.exponent(), 11);
+    }
+
+    #[test]
+    fn test_currency_is_crypto() {
+        assert!(Currency::BTC.is_crypto());
+        assert!(!Currency::USD.is_crypto());
+        assert!(!Currency::JPY.is_crypto());
+    }
+
+    #[test]
+    fn test_static_rate_converter_usd_to_btc() {
+        let conv = StaticRateConverter::new().with_rate(Currency::BTC, Currency::USD, 50_000.0);
+        // $10.00 at 50k USD/BTC = 0.0002 BTC = 20k sats
+        let btc = conv
+            .convert(CurrencyAmount::from_u64(Currency::USD, 1000), Currency::BTC)
+            .unwrap();
+        assert_eq!(btc.currency(), Currency::BTC);
+        assert_eq!(btc.value(), 20_000_000);
+    }
+
+    #[test]
+    fn test_static_rate_converter_btc_to_usd() {
+        let conv = StaticRateConverter::new().with_rate(Currency::BTC, Currency::USD, 50_000.0);
+        let usd = conv
+            .convert(CurrencyAmount::millisats(20_000_000), Currency::USD)
+            .unwrap();
+        assert_eq!(usd, CurrencyAmount::from_u64(Currency::USD, 1000));
+    }
+
+    #[test]
+    fn test_static_rate_converter_same_currency() {
+        let conv = StaticRateConverter::new();
+        let amount = CurrencyAmount::from_u64(Currency::EUR, 1234);
+        assert_eq!(conv.convert(amount, Currency::EUR).unwrap(), amount);
+    }
+
+    #[test]
+    fn test_static_rate_converter_missing_rate() {
+        let conv = StaticRateConverter::new();
+        let result = conv.convert(CurrencyAmount::from_u64(Currency::EUR, 100), Currency::GBP);
+        assert!(result.unwrap_err().to_string().contains("No exchange rate"));
+    }
+
+    #[test]
+    fn test_static_rate_converter_invalid_rate() {
+        let conv = StaticRateConverter::new().with_rate(Currency::EUR, Currency::GBP, 0.0);
+        assert!(
+            conv.convert(CurrencyAmount::from_u64(Currency::EUR, 100), Currency::GBP)
+                .is_err()
+        );
+    }
+
     #[test]
     fn test_currency_amount_display_btc() {
         let amount = CurrencyAmount::from_u64(Currency::BTC, 100_000_000_000);
         assert_eq!(amount.to_string(), "BTC 1.00000000");
     }
+
+    #[test]
+    fn test_currency_amount_from_str() {
+        let usd: CurrencyAmount = "USD 10.50".parse().unwrap();
+        assert_eq!(usd, CurrencyAmount::from_u64(Currency::USD, 1050));
+
+        let usd: CurrencyAmount = "usd 10.5".parse().unwrap();
+        assert_eq!(usd.value(), 1050);
+
+        let jpy: CurrencyAmount = "JPY 2000".parse().unwrap();
+        assert_eq!(jpy, CurrencyAmount::from_u64(Currency::JPY, 2000));
+        assert!("JPY 2000.50".parse::<CurrencyAmount>().is_err());
+
+        let btc: CurrencyAmount = "BTC 0.0001".parse().unwrap();
+        assert_eq!(btc, CurrencyAmount::millisats(10_000_000));
+    }
+
+    #[test]
+    fn test_currency_amount_from_str_invalid() {
+        for bad in [
+            "",
+            "USD",
+            "10.50",
+            "XYZ 10.50",
+            "USD 10.505",
+            "USD -1",
+            "USD 1e3",
+            "USD .",
+            "USD 10.50 extra",
+            "USD 99999999999999999999",
+        ] {
+            assert!(bad.parse::<CurrencyAmount>().is_err(), "{:?}", bad);
+        }
+    }
+
+    #[test]
+    fn test_currency_amount_display_round_trip() {
+        for amount in [
+            CurrencyAmount::from_u64(Currency::USD, 1),
+            CurrencyAmount::from_u64(Currency::EUR, 100_000),
+            CurrencyAmount::millisats(21_000),
+            CurrencyAmount::from_u64(Currency::JPY, 500),
+            CurrencyAmount::from_u64(Currency::JPY, u64::MAX),
+            // 1.23456789 BTC, which f32 would turn into 1.23456788
+            CurrencyAmount::millisats(123_456_789_000),
+            // 21M BTC and an amount with a milli-satoshi remainder
+            CurrencyAmount::millisats(2_100_000_000_000_000_000),
+            CurrencyAmount::millisats(123_456_789_012),
+            CurrencyAmount::from_u64(Currency::USD, u64::MAX),
+        ] {
+            assert_eq!(
+                amount.to_string().parse::<CurrencyAmount>().unwrap(),
+                amount
+            );
+        }
+        assert_eq!(
+            CurrencyAmount::from_u64(Currency::JPY, 500).to_string(),
+            "JPY 500"
+        );
+        assert_eq!(
+            CurrencyAmount::millisats(123_456_789_000).to_string(),
+            "BTC 1.23456789"
+        );
+        assert_eq!(
+            CurrencyAmount::millisats(123_456_789_012).to_string(),
+            "BTC 1.23456789012"
+        );
+    }
+
+    #[test]
+    fn test_currency_amount_f32_uses_exponent() {
+        let jpy = CurrencyAmount::from_f32(Currency::JPY, 500.0);
+        assert_eq!(jpy, CurrencyAmount::from_u64(Currency::JPY, 500));
+        assert_eq!(jpy.value_f32(), 500.0);
+        assert_eq!(jpy.to_decimal_string(), "500");
+        assert_eq!(jpy.to_string(), "JPY 500");
+
+        let usd = CurrencyAmount::from_f32(Currency::USD, 19.99);
+        assert_eq!(usd.value(), 1999);
+        // still truncates instead of rounding
+        assert_eq!(CurrencyAmount::from_f32(Currency::USD, 0.53).value(), 52);
+    }
+
+    #[test]
+    fn test_convert_exact_usd_to_jpy() {
+        // 1 USD = 149.37 JPY
+        let usd = CurrencyAmount::from_u64(Currency::USD, 1_000);
+        let (jpy, rem) = usd.convert_exact(Currency::JPY, 14_937, 100).unwrap();
+        assert_eq!(jpy, CurrencyAmount::from_u64(Currency::JPY, 1_493));
+        // 0.7 yen is lost to truncation, which is less than one cent
+        assert_eq!(rem, 0);
+
+        // and the other way around the yen remainder is tracked
+        let jpy = CurrencyAmount::from_u64(Currency::JPY, 1_002);
+        let (usd, rem) = jpy.convert_exact(Currency::USD, 100, 14_937).unwrap();
+        assert_eq!(usd, CurrencyAmount::from_u64(Currency::USD, 670));
+        assert_eq!(rem, 1);
+        let (again, rem) = CurrencyAmount::from_u64(Currency::JPY, 1_001)
+            .convert_exact(Currency::USD, 100, 14_937)
+            .unwrap();
+        assert_eq!((again, rem), (usd, 0));
+    }
+
+    #[test]
+    fn test_convert_exact_btc_to_usd() {
+        // 0.00123456789 BTC at 1 BTC = 50,000 USD is 61.7283945 USD
+        let btc = CurrencyAmount::millisats(123_456_789);
+        let (usd, rem) = btc.convert_exact(Currency::USD, 50_000, 1).unwrap();
+        assert_eq!(usd, CurrencyAmount::from_u64(Currency::USD, 6_172));
+        assert_eq!(rem, 16_789);
+        // the converted part plus the remainder accounts for the whole amount
+        let used = CurrencyAmount::millisats(btc.value() - rem);
+        assert_eq!(
+            used.convert_exact(Currency::USD, 50_000, 1).unwrap(),
+            (usd, 0)
+        );
+        // the remainder is worth less than a cent
+        let (dust, _) = CurrencyAmount::millisats(rem)
+            .convert_exact(Currency::USD, 50_000, 1)
+            .unwrap();
+        assert_eq!(dust.value(), 0);
+    }
+
+    #[test]
+    fn test_convert_exact_invalid() {
+        let usd = CurrencyAmount::from_u64(Currency::USD, 100);
+        assert!(usd.convert_exact(Currency::EUR, 0, 1).is_err());
+        assert!(usd.convert_exact(Currency::EUR, 1, 0).is_err());
+        assert!(
+            CurrencyAmount::from_u64(Currency::JPY, u64::MAX)
+                .convert_exact(Currency::BTC, u64::MAX, 1)
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,443 @@
+use crate::currency::{Currency, CurrencyAmount};
+use crate::fiat::{FiatPaymentInfo, FiatPaymentService, LineItem, VerifiedEvent};
+use crate::json_api::{JsonApi, TokenGen};
+use crate::webhook::{WebhookMessage, WebhookVerifier, verify_hmac_sha256};
+use anyhow::{Result, anyhow, bail};
+use log::warn;
+use reqwest::{Method, RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CoinbaseCommerceConfig {
+    /// API base url, defaults to `https://api.commerce.coinbase.com`
+    pub url: Option<String>,
+    pub api_key: String,
+    /// Shared secret of the webhook subscription, used by `verify_webhook`
+    #[serde(default)]
+    pub webhook_secret: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct CoinbaseCommerceApi {
+    api: JsonApi,
+    webhook_secret: Option<String>,
+}
+
+#[derive(Clone)]
+struct CoinbaseCommerceTokenGen {
+    pub api_key: String,
+}
+
+impl TokenGen for CoinbaseCommerceTokenGen {
+    fn generate_token(
+        &self,
+        _method: Method,
+        _url: &Url,
+        _body: Option<&str>,
+        req: RequestBuilder,
+    ) -> Result<RequestBuilder> {
+        Ok(req
+            .header("X-CC-Api-Key", &self.api_key)
+            .header("X-CC-Version", CoinbaseCommerceApi::API_VERSION))
+    }
+}
+
+impl CoinbaseCommerceApi {
+    /// Pinned API version sent with every request
+    pub const API_VERSION: &str = "2018-03-22";
+
+    pub fn new(config: CoinbaseCommerceConfig) -> Result<Self> {
+        const DEFAULT_URL: &str = "https://api.commerce.coinbase.com";
+
+        Ok(Self {
+            api: JsonApi::token_gen(
+                &config.url.unwrap_or(DEFAULT_URL.to_string()),
+                false,
+                CoinbaseCommerceTokenGen {
+                    api_key: config.api_key,
+                },
+            )?
+            .redact_header("X-CC-Api-Key"),
+            webhook_secret: config.webhook_secret,
+        })
+    }
+
+    /// Create a fixed price charge, paid at [`CoinbaseCharge::hosted_url`].
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn create_charge(
+        &self,
+        amount: CurrencyAmount,
+        name: String,
+        description: Option<String>,
+    ) -> Result<CoinbaseCharge> {
+        let rsp: CoinbaseResponse<CoinbaseCharge> = self
+            .api
+            .post(
+                "/charges",
+                CreateChargeRequest::new(amount, name, description)?,
+            )
+            .await?;
+        Ok(rsp.data)
+    }
+
+    /// Retrieve a charge by id or code
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn get_charge(&self, charge: &str) -> Result<CoinbaseCharge> {
+        let rsp: CoinbaseResponse<CoinbaseCharge> =
+            self.api.get(&format!("/charges/{}", charge)).await?;
+        Ok(rsp.data)
+    }
+
+    /// Cancel a charge, only possible before the customer paid
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn cancel_charge(&self, charge: &str) -> Result<CoinbaseCharge> {
+        let rsp: CoinbaseResponse<CoinbaseCharge> = self
+            .api
+            .req::<_, ()>(Method::POST, &format!("/charges/{}/cancel", charge), None)
+            .await?;
+        Ok(rsp.data)
+    }
+}
+
+impl FiatPaymentService for CoinbaseCommerceApi {
+    fn create_order(
+        &self,
+        description: &str,
+        amount: CurrencyAmount,
+        line_items: Option<Vec<LineItem>>,
+    ) -> Pin<Box<dyn Future<Output = Result<FiatPaymentInfo>> + Send>> {
+        let s = self.clone();
+        let desc = description.to_string();
+        Box::pin(async move {
+            if line_items.is_some() {
+                warn!("Coinbase Commerce charges do not support line items, ignoring them");
+            }
+            let rsp = s.create_charge(amount, desc, None).await?;
+            Ok(FiatPaymentInfo {
+                raw_data: serde_json::to_string(&rsp)?,
+                checkout_url: Some(rsp.hosted_url.clone()),
+                external_id: rsp.id,
+            })
+        })
+    }
+
+    fn cancel_order(&self, id: &str) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
+        let s = self.clone();
+        let id = id.to_string();
+        Box::pin(async move {
+            s.cancel_charge(&id).await?;
+            Ok(())
+        })
+    }
+
+    fn verify_webhook(&self, msg: &WebhookMessage) -> Result<VerifiedEvent> {
+        let secret = self
+            .webhook_secret
+            .as_ref()
+            .ok_or_else(|| anyhow!("No webhook secret configured"))?;
+        let event = CoinbaseWebhookEvent::verify(secret, msg)?;
+        Ok(VerifiedEvent {
+            event_id: Some(event.event.id),
+            event_type: event.event.kind,
+            external_id: event
+                .event
+                .data
+                .get("id")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string()),
+            raw_data: String::from_utf8_lossy(&msg.body).into_owned(),
+        })
+    }
+}
+
+impl WebhookVerifier for CoinbaseCommerceApi {
+    /// Verify a Coinbase Commerce webhook with the configured `webhook_secret`.
+    fn verify(&self, msg: &WebhookMessage) -> Result<()> {
+        let secret = self
+            .webhook_secret
+            .as_ref()
+            .ok_or_else(|| anyhow!("No webhook secret configured"))?;
+        CoinbaseWebhookEvent::verify_signature(secret, msg)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct CreateChargeRequest {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub pricing_type: String,
+    pub local_price: CoinbaseMoney,
+}
+
+impl CreateChargeRequest {
+    fn new(amount: CurrencyAmount, name: String, description: Option<String>) -> Result<Self> {
+        if amount.currency() == Currency::BTC {
+            bail!("Bitcoin amount not allowed for fiat payments");
+        }
+        Ok(Self {
+            name,
+            description,
+            pricing_type: "fixed_price".to_string(),
+            local_price: CoinbaseMoney {
+                amount: amount.to_decimal_string(),
+                currency: amount.currency().to_string(),
+            },
+        })
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+struct CoinbaseResponse<T> {
+    pub data: T,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CoinbaseMoney {
+    /// Decimal amount in the standard unit, e.g. `"20.00"`
+    pub amount: String,
+    pub currency: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CoinbaseCharge {
+    pub id: String,
+    /// Short code, also accepted in place of the id
+    pub code: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub hosted_url: String,
+    pub pricing_type: Option<String>,
+    pub expires_at: Option<String>,
+    /// Status changes, the last entry is the current status
+    #[serde(default)]
+    pub timeline: Vec<CoinbaseTimelineEntry>,
+}
+
+impl CoinbaseCharge {
+    /// Current status, e.g. `NEW`, `COMPLETED`, `EXPIRED` or `CANCELED`
+    pub fn status(&self) -> Option<&str> {
+        self.timeline.last().map(|t| t.status.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CoinbaseTimelineEntry {
+    pub status: String,
+    pub time: Option<String>,
+}
+
+/// A Coinbase Commerce webhook delivery.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CoinbaseWebhookEvent {
+    /// Delivery id
+    pub id: String,
+    pub scheduled_for: Option<String>,
+    pub event: CoinbaseEvent,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct CoinbaseEvent {
+    pub id: String,
+    /// Event type, e.g. `charge:confirmed`
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub created_at: Option<String>,
+    /// The charge the event is about
+    pub data: serde_json::Value,
+}
+
+impl CoinbaseWebhookEvent {
+    /// Verify and parse a Coinbase Commerce webhook.
+    ///
+    /// Checks the `X-CC-Webhook-Signature` header, the hex encoded HMAC-SHA256
+    /// of the raw body, in constant time. Coinbase Commerce does not sign a
+    /// timestamp, so there is no replay protection beyond deduplicating
+    /// [`CoinbaseEvent::id`].
+    pub fn verify(secret: &str, msg: &WebhookMessage) -> Result<Self> {
+        Self::verify_signature(secret, msg)?;
+        Ok(serde_json::from_slice(&msg.body)?)
+    }
+
+    /// Check the `X-CC-Webhook-Signature` header of `msg` without parsing the
+    /// body.
+    fn verify_signature(secret: &str, msg: &WebhookMessage) -> Result<()> {
+        let sig = msg
+            .headers
+            .get("x-cc-webhook-signature")
+            .ok_or_else(|| anyhow!("Missing X-CC-Webhook-Signature header"))?;
+        verify_hmac_sha256(secret.as_bytes(), &msg.body, sig)
+            .inspect_err(|_| warn!("Invalid Coinbase Commerce webhook signature"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hmac::{Hmac, Mac};
+
+    type HmacSha256 = Hmac<sha2::Sha256>;
+    use std::collections::HashMap;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const SECRET: &str = "whsec_cc";
+
+    fn sign(secret: &str, body: &[u8]) -> String {
+        let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    fn webhook(body: &[u8], signature: &str) -> WebhookMessage {
+        WebhookMessage {
+            endpoint: "/webhook/coinbase".to_string(),
+            query: None,
+            body: body.to_vec(),
+            headers: HashMap::from([("x-cc-webhook-signature".to_string(), signature.to_string())]),
+        }
+    }
+
+    fn event_body() -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "id": "delivery_1",
+            "scheduled_for": "2024-01-01T00:00:00Z",
+            "event": {
+                "id": "evt_1",
+                "type": "charge:confirmed",
+                "created_at": "2024-01-01T00:00:00Z",
+                "data": {"id": "charge_1", "code": "ABC123"}
+            }
+        }))
+        .unwrap()
+    }
+
+    fn charge_json(id: &str) -> serde_json::Value {
+        serde_json::json!({
+            "data": {
+                "id": id,
+                "code": "ABC123",
+                "name": "Order #1",
+                "hosted_url": "https://commerce.coinbase.com/charges/ABC123",
+                "pricing_type": "fixed_price",
+                "timeline": [{"status": "NEW", "time": "2024-01-01T00:00:00Z"}]
+            }
+        })
+    }
+
+    #[test]
+    fn test_create_charge_request() {
+        let req = CreateChargeRequest::new(
+            CurrencyAmount::from_u64(Currency::USD, 2050),
+            "Order #1".to_string(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(&req).unwrap(),
+            serde_json::json!({
+                "name": "Order #1",
+                "pricing_type": "fixed_price",
+                "local_price": {"amount": "20.50", "currency": "USD"}
+            })
+        );
+        assert!(
+            CreateChargeRequest::new(CurrencyAmount::millisats(1000), "x".to_string(), None)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_verify_webhook_valid() {
+        let body = event_body();
+        let msg = webhook(&body, &sign(SECRET, &body));
+        let event = CoinbaseWebhookEvent::verify(SECRET, &msg).unwrap();
+        assert_eq!(event.event.id, "evt_1");
+        assert_eq!(event.event.kind, "charge:confirmed");
+    }
+
+    #[test]
+    fn test_verify_webhook_invalid() {
+        let body = event_body();
+        // wrong secret
+        let msg = webhook(&body, &sign("other", &body));
+        assert!(CoinbaseWebhookEvent::verify(SECRET, &msg).is_err());
+        // tampered body
+        let msg = webhook(b"{}", &sign(SECRET, &body));
+        assert!(CoinbaseWebhookEvent::verify(SECRET, &msg).is_err());
+        // not hex
+        let msg = webhook(&body, "zz");
+        assert!(CoinbaseWebhookEvent::verify(SECRET, &msg).is_err());
+        // missing header
+        let mut msg = webhook(&body, "");
+        msg.headers.clear();
+        assert!(CoinbaseWebhookEvent::verify(SECRET, &msg).is_err());
+    }
+
+    #[test]
+    fn test_verify_webhook_service() {
+        let api = CoinbaseCommerceApi::new(CoinbaseCommerceConfig {
+            url: None,
+            api_key: "key".to_string(),
+            webhook_secret: Some(SECRET.to_string()),
+        })
+        .unwrap();
+        let body = event_body();
+        let verified = api
+            .verify_webhook(&webhook(&body, &sign(SECRET, &body)))
+            .unwrap();
+        assert_eq!(verified.event_id.as_deref(), Some("evt_1"));
+        assert_eq!(verified.event_type, "charge:confirmed");
+        assert_eq!(verified.external_id.as_deref(), Some("charge_1"));
+    }
+
+    #[tokio::test]
+    async fn test_create_and_cancel_order() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/charges"))
+            .and(header("x-cc-api-key", "key"))
+            .and(header("x-cc-version", CoinbaseCommerceApi::API_VERSION))
+            .and(body_json(serde_json::json!({
+                "name": "Order #1",
+                "pricing_type": "fixed_price",
+                "local_price": {"amount": "10.00", "currency": "EUR"}
+            })))
+            .respond_with(ResponseTemplate::new(201).set_body_json(charge_json("charge_1")))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/charges/charge_1/cancel"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(charge_json("charge_1")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = CoinbaseCommerceApi::new(CoinbaseCommerceConfig {
+            url: Some(server.uri()),
+            api_key: "key".to_string(),
+            webhook_secret: None,
+        })
+        .unwrap();
+        let info = FiatPaymentService::create_order(
+            &api,
+            "Order #1",
+            CurrencyAmount::from_u64(Currency::EUR, 1000),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(info.external_id, "charge_1");
+        assert_eq!(
+            info.checkout_url.as_deref(),
+            Some("https://commerce.coinbase.com/charges/ABC123")
+        );
+        FiatPaymentService::cancel_order(&api, "charge_1")
+            .await
+            .unwrap();
+    }
+}
@@ -7,6 +7,10 @@
 //!
 //! - **Stripe** (`method-stripe` feature) - Full checkout session and payment intent support
 //! - **Revolut** (`method-revolut` feature) - Merchant API integration with order management
+//! - **PayPal** (`method-paypal` feature) - Orders v2 checkout
+//! - **Mollie** (`method-mollie` feature) - Payments API checkout
+//! - **Coinbase Commerce** (`method-coinbase-commerce` feature) - Hosted crypto checkout
+//! - **Square** (`method-square` feature) - Payment link checkout
 //!
 //! # Example
 //!
@@ -18,6 +22,7 @@
 //!     url: None,
 //!     api_key: "sk_test_...".to_string(),
 //!     webhook_secret: None,
+//!     proxy: None,
 //! })?;
 //!
 //! let amount = CurrencyAmount::from_f32(Currency::USD, 50.00);
@@ -25,15 +30,39 @@
 //! ```
 
 use crate::currency::CurrencyAmount;
+#[cfg(feature = "webhook")]
+use crate::webhook::WebhookMessage;
 use anyhow::{Result, anyhow};
+use log::warn;
+use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
 
+#[cfg(feature = "method-coinbase-commerce")]
+mod coinbase_commerce;
+#[cfg(feature = "method-coinbase-commerce")]
+pub use coinbase_commerce::*;
+
+#[cfg(feature = "method-mollie")]
+mod mollie;
+#[cfg(feature = "method-mollie")]
+pub use mollie::*;
+
+#[cfg(feature = "method-paypal")]
+mod paypal;
+#[cfg(feature = "method-paypal")]
+pub use paypal::*;
+
 #[cfg(feature = "method-revolut")]
 mod revolut;
 #[cfg(feature = "method-revolut")]
 pub use revolut::*;
 
+#[cfg(feature = "method-square")]
+mod square;
+#[cfg(feature = "method-square")]
+pub use square::*;
+
 #[cfg(feature = "method-stripe")]
 mod stripe;
 #[cfg(feature = "method-stripe")]
@@ -63,6 +92,49 @@ pub struct LineItem {
     pub tax_amount: Option<u64>,
     /// Tax name/description (e.g., "VAT", "Sales Tax") (optional)
     pub tax_name: Option<String>,
+    /// Ids of tax rates defined at the provider (e.g. Stripe `txr_...`) to
+    /// apply to the item (optional). Only these are charged as tax,
+    /// `tax_amount` and `tax_name` are informational. The tax is added on
+    /// top of the item, so the order amount is the total before this tax
+    pub tax_rates: Option<Vec<String>>,
+}
+
+/// Optional settings of an order, see
+/// [`FiatPaymentService::create_order_with_options`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrderOptions {
+    /// Where the customer is sent after completing the payment
+    pub success_url: Option<String>,
+    /// Where the customer is sent after abandoning the payment
+    pub cancel_url: Option<String>,
+    /// Email of the customer, prefilled on the checkout page
+    pub customer_email: Option<String>,
+    /// Key-value pairs stored with the order
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+impl OrderOptions {
+    pub fn with_success_url(mut self, url: impl Into<String>) -> Self {
+        self.success_url = Some(url.into());
+        self
+    }
+
+    pub fn with_cancel_url(mut self, url: impl Into<String>) -> Self {
+        self.cancel_url = Some(url.into());
+        self
+    }
+
+    pub fn with_customer_email(mut self, email: impl Into<String>) -> Self {
+        self.customer_email = Some(email.into());
+        self
+    }
+
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata
+            .get_or_insert_with(HashMap::new)
+            .insert(key.into(), value.into());
+        self
+    }
 }
 
 impl LineItem {
@@ -82,6 +154,57 @@ impl LineItem {
     }
 }
 
+/// Check that all line items are in the currency of the order `amount`
+/// (case-insensitively).
+#[cfg(any(
+    feature = "method-stripe",
+    feature = "method-square",
+    feature = "method-revolut"
+))]
+pub(crate) fn validate_line_item_currency(
+    amount: &CurrencyAmount,
+    items: &[LineItem],
+) -> Result<()> {
+    let currency = amount.currency().to_string();
+    if let Some(item) = items
+        .iter()
+        .find(|i| !i.currency.eq_ignore_ascii_case(&currency))
+    {
+        anyhow::bail!(
+            "Line item {:?} currency {} does not match order currency {}",
+            item.name,
+            item.currency,
+            currency
+        );
+    }
+    Ok(())
+}
+
+/// Check that line items add up to the order `amount`.
+///
+/// Stripe and Square charge the sum of the line items (`unit_amount *
+/// quantity`) and ignore the order amount, so a mismatch would silently
+/// charge a different amount. `tax_amount` is not part of the sum, and tax
+/// from [`LineItem::tax_rates`] is charged on top of it: with tax rates,
+/// `amount` is the total before tax.
+#[cfg(any(feature = "method-stripe", feature = "method-square"))]
+pub(crate) fn validate_line_items(amount: &CurrencyAmount, items: &[LineItem]) -> Result<()> {
+    use anyhow::ensure;
+
+    validate_line_item_currency(amount, items)?;
+    let total = items
+        .iter()
+        .try_fold(0u64, |acc, i| acc.checked_add(i.subtotal_amount()))
+        .ok_or_else(|| anyhow!("Line item total overflows"))?;
+    ensure!(
+        total == amount.value(),
+        "Line item total {} does not match order amount {}",
+        CurrencyAmount::from_u64(amount.currency(), total),
+        amount
+    );
+    Ok(())
+}
+
 /// Trait for fiat payment service providers.
 ///
 /// Implement this trait to add support for additional payment processors.
@@ -93,7 +216,8 @@ pub trait FiatPaymentService: Send + Sync {
     /// # Arguments
     ///
     /// * `description` - A human-readable description of the order
-    /// * `amount` - The total amount to charge
+    /// * `amount` - The total amount to charge, before any tax added by
+    ///   [`LineItem::tax_rates`]
     /// * `line_items` - Optional detailed breakdown of items being purchased
     ///
     /// # Returns
@@ -106,6 +230,40 @@ pub trait FiatPaymentService: Send + Sync {
         line_items: Option<Vec<LineItem>>,
     ) -> Pin<Box<dyn Future<Output = Result<FiatPaymentInfo>> + Send>>;
 
+    /// Create a payment order with redirect urls, customer email and metadata.
+    ///
+    /// [`FiatPaymentService::create_order`] is the same with default options.
+    /// The default implementation ignores the options, providers override it
+    /// to pass them on.
+    fn create_order_with_options(
+        &self,
+        description: &str,
+        amount: CurrencyAmount,
+        line_items: Option<Vec<LineItem>>,
+        options: OrderOptions,
+    ) -> Pin<Box<dyn Future<Output = Result<FiatPaymentInfo>> + Send>> {
+        if options != OrderOptions::default() {
+            warn!("Order options are not supported by this provider, ignoring them");
+        }
+        self.create_order(description, amount, line_items)
+    }
+
+    /// Get the current state of an order, e.g. to poll for payment.
+    ///
+    /// Providers that cannot look up orders keep the default implementation,
+    /// which returns an `unsupported` error.
+    ///
+    /// # Arguments
+    ///
+    /// * `external_id` - The external ID of the order
+    fn get_order(
+        &self,
+        external_id: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<FiatOrderStatus>> + Send>> {
+        let _ = external_id;
+        Box::pin(async { Err(anyhow!("Order lookup is not supported by this provider")) })
+    }
+
     /// Cancel an existing order.
     ///
     /// # Arguments
@@ -113,6 +271,31 @@ pub trait FiatPaymentService: Send + Sync {
     /// * `id` - The external ID of the order to cancel
     fn cancel_order(&self, id: &str) -> Pin<Box<dyn Future<Output = Result<()>> + Send>>;
 
+    /// Cancel an existing order and return its final state.
+    ///
+    /// Unlike [`FiatPaymentService::cancel_order`] an order which can no
+    /// longer be canceled because it already reached a final state is not an
+    /// error: that state is returned instead, e.g.
+    /// [`FiatOrderStatus::Completed`] for an order that was paid before the
+    /// cancel, so fulfillment knows whether to reverse provisional actions.
+    ///
+    /// The default implementation calls `cancel_order` and reports
+    /// [`FiatOrderStatus::Cancelled`] on success.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - The external ID of the order to cancel
+    fn cancel_order_status(
+        &self,
+        id: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<FiatOrderStatus>> + Send>> {
+        let cancel = self.cancel_order(id);
+        Box::pin(async move {
+            cancel.await?;
+            Ok(FiatOrderStatus::Cancelled)
+        })
+    }
+
     /// Create a subscription: an initial checkout that also saves the
     /// customer's payment method for future merchant-initiated (off-session)
     /// charges.
@@ -166,6 +349,55 @@ pub trait FiatPaymentService: Send + Sync {
         let _ = (customer_id, payment_method_id, amount, description);
         Box::pin(async { Err(anyhow!("Subscriptions are not supported by this provider")) })
     }
+
+    /// Refund an order, fully or partially.
+    ///
+    /// Providers that do not support refunds keep the default implementation,
+    /// which returns an `unsupported` error.
+    ///
+    /// # Arguments
+    ///
+    /// * `external_id` - The external ID of the order to refund
+    /// * `amount` - The amount to refund, `None` refunds the full amount
+    fn refund(
+        &self,
+        external_id: &str,
+        amount: Option<CurrencyAmount>,
+    ) -> Pin<Box<dyn Future<Output = Result<FiatRefundInfo>> + Send>> {
+        let _ = (external_id, amount);
+        Box::pin(async { Err(anyhow!("Refunds are not supported by this provider")) })
+    }
+
+    /// Verify a webhook sent by this provider.
+    ///
+    /// Uses the webhook secret and signature scheme configured on the service,
+    /// so a single handler can verify webhooks for any provider through
+    /// `dyn FiatPaymentService`.
+    ///
+    /// Providers without webhook support keep the default implementation,
+    /// which returns an `unsupported` error.
+    #[cfg(feature = "webhook")]
+    fn verify_webhook(&self, msg: &WebhookMessage) -> Result<VerifiedEvent> {
+        let _ = msg;
+        Err(anyhow!(
+            "Webhook verification is not supported by this provider"
+        ))
+    }
+}
+
+/// A webhook event whose signature has been verified, normalized across
+/// providers.
+#[derive(Debug, Clone)]
+pub struct VerifiedEvent {
+    /// Provider event ID (when the provider sends one)
+    pub event_id: Option<String>,
+    /// Provider event type (e.g. `checkout.session.completed`, `ORDER_COMPLETED`)
+    pub event_type: String,
+    /// External ID of the order/payment the event refers to, matching
+    /// [`FiatPaymentInfo::external_id`]
+    pub external_id: Option<String>,
+    /// Raw JSON body of the webhook
+    pub raw_data: String,
 }
 
 /// Information about a created fiat payment.
@@ -173,6 +405,40 @@ pub trait FiatPaymentService: Send + Sync {
 pub struct FiatPaymentInfo {
     /// External payment ID from the provider
     pub external_id: String,
+    /// Hosted checkout URL the customer completes to pay (when the provider
+    /// returns one)
+    pub checkout_url: Option<String>,
+    /// Raw JSON response from the provider
+    pub raw_data: String,
+}
+
+/// Provider-agnostic state of a fiat order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FiatOrderStatus {
+    /// Waiting for the customer to pay, or payment in progress
+    Pending,
+    /// Paid
+    Completed,
+    /// Canceled or expired before payment
+    Cancelled,
+    /// Payment failed
+    Failed,
+    /// Paid and refunded
+    Refunded,
+}
+
+impl FiatOrderStatus {
+    /// Whether the order can no longer change (except by refunding)
+    pub fn is_final(&self) -> bool {
+        !matches!(self, FiatOrderStatus::Pending)
+    }
+}
+
+/// Information about a created refund.
+#[derive(Debug, Clone)]
+pub struct FiatRefundInfo {
+    /// External refund ID from the provider
+    pub external_id: String,
     /// Raw JSON response from the provider
     pub raw_data: String,
 }
@@ -198,6 +464,38 @@ pub struct SubscriptionPaymentInfo {
     pub raw_data: String,
 }
 
+/// Helpers shared by the fiat tests across the crate.
+#[cfg(test)]
+pub(crate) mod test_util {
+    use super::*;
+
+    /// A [`FiatPaymentService`] creating the order `order_1` with a fixed
+    /// checkout URL, echoing the description and amount in `raw_data`.
+    pub(crate) struct FakeFiat;
+
+    impl FiatPaymentService for FakeFiat {
+        fn create_order(
+            &self,
+            description: &str,
+            amount: CurrencyAmount,
+            _line_items: Option<Vec<LineItem>>,
+        ) -> Pin<Box<dyn Future<Output = Result<FiatPaymentInfo>> + Send>> {
+            let raw_data = format!("{} {}", description, amount);
+            Box::pin(async move {
+                Ok(FiatPaymentInfo {
+                    external_id: "order_1".to_string(),
+                    checkout_url: Some("https://pay.example.com/order_1".to_string()),
+                    raw_data,
+                })
+            })
+        }
+
+        fn cancel_order(&self, _id: &str) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
+            Box::pin(async { Ok(()) })
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -214,6 +512,7 @@ mod tests {
             metadata: None,
             tax_amount: None,
             tax_name: None,
+            tax_rates: None,
         };
         assert_eq!(item.total_amount(), 2000);
     }
@@ -230,6 +529,7 @@ mod tests {
             metadata: None,
             tax_amount: Some(200),
             tax_name: Some("VAT".to_string()),
+            tax_rates: None,
         };
         assert_eq!(item.total_amount(), 2200); // 2000 + 200 tax
     }
@@ -246,6 +546,7 @@ mod tests {
             metadata: None,
             tax_amount: Some(300),
             tax_name: Some("Sales Tax".to_string()),
+            tax_rates: None,
         };
         assert_eq!(item.subtotal_amount(), 3000);
         assert_eq!(item.total_amount(), 3300);
@@ -265,6 +566,7 @@ mod tests {
             metadata: None,
             tax_amount: Some(1000),
             tax_name: None,
+            tax_rates: None,
         };
         assert_eq!(item.subtotal_amount(), u64::MAX);
         assert_eq!(item.total_amount(), u64::MAX);
@@ -282,16 +584,27 @@ mod tests {
             metadata: Some(serde_json::json!({"key": "value"})),
             tax_amount: None,
             tax_name: None,
+            tax_rates: None,
         };
         let cloned = item.clone();
         assert_eq!(cloned.name, item.name);
         assert_eq!(cloned.unit_amount, item.unit_amount);
     }
 
+    #[test]
+    fn test_fiat_order_status_is_final() {
+        assert!(!FiatOrderStatus::Pending.is_final());
+        assert!(FiatOrderStatus::Completed.is_final());
+        assert!(FiatOrderStatus::Cancelled.is_final());
+        assert!(FiatOrderStatus::Failed.is_final());
+        assert!(FiatOrderStatus::Refunded.is_final());
+    }
+
     #[test]
     fn test_fiat_payment_info_debug() {
         let info = FiatPaymentInfo {
             external_id: "ext_123".to_string(),
+            checkout_url: None,
             raw_data: r#"{"id": "123"}"#.to_string(),
         };
         let debug_str = format!("{:?}", info);
@@ -0,0 +1,308 @@
+use crate::currency::{Currency, CurrencyAmount};
+use crate::fiat::{FiatPaymentInfo, FiatPaymentService, LineItem};
+use crate::json_api::JsonApi;
+use anyhow::{Result, bail};
+use log::warn;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct MollieConfig {
+    /// API base url, defaults to `https://api.mollie.com`
+    pub url: Option<String>,
+    /// Live (`live_...`) or test (`test_...`) API key
+    pub api_key: String,
+    /// Where the customer is sent after the checkout, required by Mollie
+    pub redirect_url: String,
+    /// Url Mollie notifies about payment status changes
+    pub webhook_url: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct MollieApi {
+    api: JsonApi,
+    redirect_url: String,
+    webhook_url: Option<String>,
+}
+
+impl MollieApi {
+    pub fn new(config: MollieConfig) -> Result<Self> {
+        const DEFAULT_URL: &str = "https://api.mollie.com";
+
+        Ok(Self {
+            api: JsonApi::token(
+                &config.url.unwrap_or(DEFAULT_URL.to_string()),
+                &format!("Bearer {}", config.api_key),
+                false,
+            )?,
+            redirect_url: config.redirect_url,
+            webhook_url: config.webhook_url,
+        })
+    }
+
+    /// Create a payment, the customer pays at [`MolliePayment::checkout_url`].
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn create_payment(
+        &self,
+        amount: CurrencyAmount,
+        description: String,
+    ) -> Result<MolliePayment> {
+        self.api
+            .post(
+                "/v2/payments",
+                CreatePaymentRequest {
+                    amount: MollieAmount::try_from(amount)?,
+                    description,
+                    redirect_url: self.redirect_url.clone(),
+                    webhook_url: self.webhook_url.clone(),
+                },
+            )
+            .await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn get_payment(&self, payment_id: &str) -> Result<MolliePayment> {
+        self.api.get(&format!("/v2/payments/{}", payment_id)).await
+    }
+
+    /// Cancel a payment, only possible while [`MolliePayment::is_cancelable`]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn cancel_payment(&self, payment_id: &str) -> Result<MolliePayment> {
+        Ok(self
+            .api
+            .req::<_, ()>(
+                Method::DELETE,
+                &format!("/v2/payments/{}", payment_id),
+                None,
+            )
+            .await?)
+    }
+}
+
+impl FiatPaymentService for MollieApi {
+    fn create_order(
+        &self,
+        description: &str,
+        amount: CurrencyAmount,
+        line_items: Option<Vec<LineItem>>,
+    ) -> Pin<Box<dyn Future<Output = Result<FiatPaymentInfo>> + Send>> {
+        let s = self.clone();
+        let desc = description.to_string();
+        Box::pin(async move {
+            if line_items.is_some() {
+                warn!("Mollie payments do not support line items, ignoring them");
+            }
+            let rsp = s.create_payment(amount, desc).await?;
+            Ok(FiatPaymentInfo {
+                raw_data: serde_json::to_string(&rsp)?,
+                checkout_url: rsp.checkout_url().map(|u| u.to_string()),
+                external_id: rsp.id,
+            })
+        })
+    }
+
+    fn cancel_order(&self, id: &str) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
+        let s = self.clone();
+        let id = id.to_string();
+        Box::pin(async move {
+            s.cancel_payment(&id).await?;
+            Ok(())
+        })
+    }
+}
+
+/// Format an amount as Mollie's decimal string, with exactly as many
+/// decimals as the currency has minor units, e.g. `"20.00"` EUR or `"500"` JPY.
+pub fn mollie_amount_value(amount: &CurrencyAmount) -> String {
+    amount.to_decimal_string()
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MollieAmount {
+    pub currency: String,
+    /// Decimal amount in the standard unit, e.g. `"20.00"`
+    pub value: String,
+}
+
+impl TryFrom<CurrencyAmount> for MollieAmount {
+    type Error = anyhow::Error;
+
+    fn try_from(amount: CurrencyAmount) -> Result<Self> {
+        if amount.currency() == Currency::BTC {
+            bail!("Bitcoin amount not allowed for fiat payments");
+        }
+        Ok(Self {
+            currency: amount.currency().to_string(),
+            value: mollie_amount_value(&amount),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreatePaymentRequest {
+    pub amount: MollieAmount,
+    pub description: String,
+    pub redirect_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub webhook_url: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MolliePayment {
+    pub id: String,
+    pub status: MolliePaymentStatus,
+    pub amount: MollieAmount,
+    pub description: Option<String>,
+    #[serde(default)]
+    pub is_cancelable: bool,
+    #[serde(rename = "_links", default)]
+    pub links: MollieLinks,
+}
+
+impl MolliePayment {
+    /// Hosted checkout url of an open payment
+    pub fn checkout_url(&self) -> Option<&str> {
+        self.links.checkout.as_ref().map(|l| l.href.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct MollieLinks {
+    pub checkout: Option<MollieLink>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct MollieLink {
+    pub href: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MolliePaymentStatus {
+    Open,
+    Pending,
+    Authorized,
+    Paid,
+    Canceled,
+    Expired,
+    Failed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn mock_api(server: &MockServer) -> MollieApi {
+        MollieApi::new(MollieConfig {
+            url: Some(server.uri()),
+            api_key: "test_key".to_string(),
+            redirect_url: "https://example.com/done".to_string(),
+            webhook_url: None,
+        })
+        .unwrap()
+    }
+
+    fn payment_json(id: &str, status: &str) -> serde_json::Value {
+        serde_json::json!({
+            "resource": "payment",
+            "id": id,
+            "status": status,
+            "amount": {"currency": "EUR", "value": "20.00"},
+            "description": "Order #1",
+            "isCancelable": status == "open",
+            "_links": {
+                "checkout": {"href": format!("https://www.mollie.com/checkout/{}", id), "type": "text/html"}
+            }
+        })
+    }
+
+    #[test]
+    fn test_mollie_amount_value() {
+        let eur = |v| mollie_amount_value(&CurrencyAmount::from_u64(Currency::EUR, v));
+        assert_eq!(eur(2000), "20.00");
+        assert_eq!(eur(5), "0.05");
+        assert_eq!(eur(123_456), "1234.56");
+
+        let jpy = |v| mollie_amount_value(&CurrencyAmount::from_u64(Currency::JPY, v));
+        assert_eq!(jpy(500), "500");
+        assert_eq!(jpy(0), "0");
+    }
+
+    #[test]
+    fn test_mollie_amount_rejects_btc() {
+        assert!(MollieAmount::try_from(CurrencyAmount::millisats(1000)).is_err());
+        let eur = MollieAmount::try_from(CurrencyAmount::from_u64(Currency::EUR, 2000)).unwrap();
+        assert_eq!(
+            (eur.currency.as_str(), eur.value.as_str()),
+            ("EUR", "20.00")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mollie_create_order() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v2/payments"))
+            .and(header("authorization", "Bearer test_key"))
+            .and(body_json(serde_json::json!({
+                "amount": {"currency": "EUR", "value": "20.00"},
+                "description": "Order #1",
+                "redirectUrl": "https://example.com/done"
+            })))
+            .respond_with(ResponseTemplate::new(201).set_body_json(payment_json("tr_1", "open")))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = mock_api(&server);
+        let info = FiatPaymentService::create_order(
+            &api,
+            "Order #1",
+            CurrencyAmount::from_u64(Currency::EUR, 2000),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(info.external_id, "tr_1");
+        assert_eq!(
+            info.checkout_url.as_deref(),
+            Some("https://www.mollie.com/checkout/tr_1")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mollie_cancel_order() {
+        let server = MockServer::start().await;
+        Mock::given(method("DELETE"))
+            .and(path("/v2/payments/tr_1"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(payment_json("tr_1", "canceled")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/v2/payments/tr_paid"))
+            .respond_with(ResponseTemplate::new(422).set_body_string(
+                r#"{"status":422,"title":"Unprocessable Entity","detail":"The payment cannot be canceled"}"#,
+            ))
+            .mount(&server)
+            .await;
+
+        let api = mock_api(&server);
+        let payment = api.cancel_payment("tr_1").await.unwrap();
+        assert_eq!(payment.status, MolliePaymentStatus::Canceled);
+        assert!(
+            FiatPaymentService::cancel_order(&api, "tr_paid")
+                .await
+                .is_err()
+        );
+    }
+}
@@ -1,14 +1,18 @@
 use crate::currency::{Currency, CurrencyAmount};
-use crate::fiat::{FiatPaymentInfo, FiatPaymentService, LineItem, SubscriptionPaymentInfo};
+use crate::fiat::{
+    FiatOrderStatus, FiatPaymentInfo, FiatPaymentService, FiatRefundInfo, LineItem, OrderOptions,
+    SubscriptionPaymentInfo, VerifiedEvent, validate_line_item_currency,
+};
 use crate::json_api::{JsonApi, TokenGen};
-use crate::webhook::{WebhookMessage, verify_timestamp_within};
-use anyhow::{Context, Result, anyhow, bail};
+use crate::random_uuid;
+use crate::webhook::{WebhookMessage, verify_hmac_sha256, verify_timestamp_within};
+use anyhow::{Context, Result, anyhow, bail, ensure};
 use chrono::{DateTime, Utc};
-use hmac::{Hmac, Mac};
 use log::warn;
 use reqwest::header::AUTHORIZATION;
 use reqwest::{Method, RequestBuilder, Url};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
 
@@ -19,11 +23,15 @@ pub struct RevolutConfig {
     pub api_version: String,
     pub token: String,
     pub public_key: String,
+    /// Signing secret of the webhook, used by `verify_webhook`
+    #[serde(default)]
+    pub webhook_secret: Option<String>,
 }
 
 #[derive(Clone)]
 pub struct RevolutApi {
     api: JsonApi,
+    webhook_secret: Option<String>,
 }
 
 #[derive(Clone)]
@@ -60,13 +68,16 @@ impl RevolutApi {
                 false,
                 token_gen,
             )?,
+            webhook_secret: config.webhook_secret,
         })
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn list_webhooks(&self) -> Result<Vec<RevolutWebhook>> {
         self.api.get("/api/1.0/webhooks").await
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn delete_webhook(&self, webhook_id: &str) -> Result<()> {
         self.api
             .req_status::<()>(
@@ -78,6 +89,7 @@ impl RevolutApi {
         Ok(())
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn create_webhook(
         &self,
         url: &str,
@@ -95,6 +107,7 @@ impl RevolutApi {
     }
 
     #[cfg_attr(coverage_nightly, coverage(off))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn create_order(
         &self,
         amount: CurrencyAmount,
@@ -114,6 +127,7 @@ impl RevolutApi {
     ///   Revolut to save the payment method used to complete this order for
     ///   future off-session, merchant-initiated charges.
     #[cfg_attr(coverage_nightly, coverage(off))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn create_order_ext(
         &self,
         amount: CurrencyAmount,
@@ -122,59 +136,93 @@ impl RevolutApi {
         customer: Option<RevolutCustomer>,
         save_payment_method_for: Option<String>,
     ) -> Result<RevolutOrder> {
-        // Convert generic LineItems to Revolut's format
-        let revolut_line_items = line_items.map(|items| {
-            items
-                .into_iter()
-                .map(|item| {
-                    let total = item.total_amount();
-
-                    // Build taxes array if tax info is provided
-                    let taxes =
-                        if let (Some(tax_amt), Some(tax_name)) = (item.tax_amount, item.tax_name) {
-                            Some(vec![RevolutTax {
-                                name: tax_name,
-                                amount: tax_amt,
-                            }])
-                        } else {
-                            None
-                        };
-
-                    RevolutLineItem {
-                        name: item.name,
-                        description: item.description,
-                        item_type: None, // Could be enhanced to detect from metadata
-                        quantity: RevolutQuantity {
-                            value: item.quantity,
-                            unit: None, // Could be enhanced to extract from metadata
-                        },
-                        unit_price_amount: item.unit_amount,
-                        total_amount: total,
-                        external_id: None,
-                        discounts: None,
-                        taxes,
-                        image_urls: item.images,
-                        url: None,
-                    }
-                })
-                .collect()
-        });
+        if let Some(items) = &line_items {
+            validate_line_item_currency(&amount, items)?;
+        }
+        let line_items =
+            line_items.map(|items| items.into_iter().map(RevolutLineItem::from).collect());
+        self.post_order(
+            CreateOrderRequest::new(
+                amount,
+                description,
+                line_items,
+                customer,
+                save_payment_method_for,
+            )?,
+            None,
+        )
+        .await
+    }
+
+    /// Create an order from Revolut line items, e.g. with discounts built via
+    /// [`RevolutLineItem::with_discounts`].
+    ///
+    /// The order amount is the sum of the line item totals.
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn create_order_with_items(
+        &self,
+        currency: Currency,
+        description: Option<String>,
+        line_items: Vec<RevolutLineItem>,
+    ) -> Result<RevolutOrder> {
+        let total = line_items
+            .iter()
+            .try_fold(0u64, |acc, i| acc.checked_add(i.total_amount))
+            .ok_or_else(|| anyhow!("Line item total overflows"))?;
+        self.post_order(
+            CreateOrderRequest::new(
+                CurrencyAmount::from_u64(currency, total),
+                description,
+                Some(line_items),
+                None,
+                None,
+            )?,
+            None,
+        )
+        .await
+    }
 
+    /// Create an order like [`RevolutApi::create_order`], sending the given
+    /// idempotency key.
+    ///
+    /// Revolut returns the order created first for a repeated key, so pass a
+    /// key stored with your own order (e.g. its id) to make retries safe
+    /// across restarts.
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn create_order_with_idempotency_key(
+        &self,
+        amount: CurrencyAmount,
+        description: Option<String>,
+        line_items: Option<Vec<LineItem>>,
+        idempotency_key: String,
+    ) -> Result<RevolutOrder> {
+        if let Some(items) = &line_items {
+            validate_line_item_currency(&amount, items)?;
+        }
+        let line_items =
+            line_items.map(|items| items.into_iter().map(RevolutLineItem::from).collect());
+        self.post_order(
+            CreateOrderRequest::new(amount, description, line_items, None, None)?,
+            Some(idempotency_key),
+        )
+        .await
+    }
+
+    /// Post an order with `idempotency_key`, or a random one so at least
+    /// retries of this call are deduplicated.
+    async fn post_order(
+        &self,
+        request: CreateOrderRequest,
+        idempotency_key: Option<String>,
+    ) -> Result<RevolutOrder> {
+        let key = match idempotency_key {
+            Some(k) => k,
+            None => random_uuid()?,
+        };
         self.api
-            .post(
-                "/api/orders",
-                CreateOrderRequest {
-                    currency: amount.currency().to_string(),
-                    amount: match amount.currency() {
-                        Currency::BTC => bail!("Bitcoin amount not allowed for fiat payments"),
-                        _ => amount.value(),
-                    },
-                    description,
-                    line_items: revolut_line_items,
-                    customer,
-                    save_payment_method_for,
-                },
-            )
+            .post_with_idempotency_key("/api/orders", request, &key)
             .await
     }
 
@@ -184,6 +232,7 @@ impl RevolutApi {
     /// method has been saved for the merchant. See
     /// [`RevolutApi::create_off_session_order`] for the full flow.
     #[cfg_attr(coverage_nightly, coverage(off))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn pay_order(
         &self,
         order_id: &str,
@@ -200,6 +249,7 @@ impl RevolutApi {
     /// This is a two-step flow: create an order attached to the customer, then
     /// pay for it using the saved payment method with `initiator = merchant`.
     #[cfg_attr(coverage_nightly, coverage(off))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn create_off_session_order(
         &self,
         customer_id: &str,
@@ -235,10 +285,48 @@ impl RevolutApi {
         self.get_order(&order.id).await
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn get_order(&self, order_id: &str) -> Result<RevolutOrder> {
         self.api.get(&format!("/api/orders/{}", order_id)).await
     }
 
+    /// Capture an `authorised` order (manual capture mode).
+    ///
+    /// Captures the full authorised amount when `amount` is `None`, otherwise
+    /// the given amount in minor units.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn capture_order(&self, order_id: &str, amount: Option<u64>) -> Result<RevolutOrder> {
+        self.api
+            .post(
+                &format!("/api/orders/{}/capture", order_id),
+                CaptureOrderRequest { amount },
+            )
+            .await
+    }
+
+    /// Refund a completed order, fully or partially.
+    ///
+    /// `amount` is in minor units of `currency`, which must be the order
+    /// currency. Returns the refund order created by Revolut.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn refund_order(
+        &self,
+        order_id: &str,
+        amount: u64,
+        currency: &str,
+    ) -> Result<RevolutOrder> {
+        self.api
+            .post(
+                &format!("/api/orders/{}/refund", order_id),
+                RefundOrderRequest {
+                    amount,
+                    currency: currency.to_uppercase(),
+                    description: None,
+                },
+            )
+            .await
+    }
+
     /// Retrieve a customer's saved payment methods.
     ///
     /// The reusable payment method id (needed for off-session/merchant-initiated
@@ -251,6 +339,7 @@ impl RevolutApi {
     ///
     /// Uses the `/api/1.0/customers/{id}/payment-methods` endpoint, which
     /// returns a bare JSON array.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn get_customer_payment_methods(
         &self,
         customer_id: &str,
@@ -272,14 +361,16 @@ impl RevolutApi {
         })
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn cancel_order(&self, order_id: &str) -> Result<RevolutOrder> {
-        self.api
+        Ok(self
+            .api
             .req::<_, ()>(
                 Method::POST,
                 &format!("/api/orders/{}/cancel", order_id),
                 None,
             )
-            .await
+            .await?)
     }
 }
 
@@ -289,13 +380,40 @@ impl FiatPaymentService for RevolutApi {
         description: &str,
         amount: CurrencyAmount,
         line_items: Option<Vec<LineItem>>,
+    ) -> Pin<Box<dyn Future<Output = Result<FiatPaymentInfo>> + Send>> {
+        self.create_order_with_options(description, amount, line_items, OrderOptions::default())
+    }
+
+    fn create_order_with_options(
+        &self,
+        description: &str,
+        amount: CurrencyAmount,
+        line_items: Option<Vec<LineItem>>,
+        options: OrderOptions,
     ) -> Pin<Box<dyn Future<Output = Result<FiatPaymentInfo>> + Send>> {
         let s = self.clone();
         let desc = description.to_string();
         Box::pin(async move {
-            let rsp = s.create_order(amount, Some(desc), line_items).await?;
+            if options.cancel_url.is_some() {
+                warn!("Revolut orders have no cancel url, ignoring it");
+            }
+            let customer = options.customer_email.map(|email| RevolutCustomer {
+                email: Some(email),
+                ..Default::default()
+            });
+            if let Some(items) = &line_items {
+                validate_line_item_currency(&amount, items)?;
+            }
+            let line_items =
+                line_items.map(|items| items.into_iter().map(RevolutLineItem::from).collect());
+            let mut request =
+                CreateOrderRequest::new(amount, Some(desc), line_items, customer, None)?;
+            request.redirect_url = options.success_url;
+            request.metadata = options.metadata;
+            let rsp = s.post_order(request, None).await?;
             Ok(FiatPaymentInfo {
                 raw_data: serde_json::to_string(&rsp)?,
+                checkout_url: rsp.checkout_url,
                 external_id: rsp.id,
             })
         })
@@ -310,6 +428,63 @@ impl FiatPaymentService for RevolutApi {
         })
     }
 
+    fn get_order(
+        &self,
+        external_id: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<FiatOrderStatus>> + Send>> {
+        let s = self.clone();
+        let id = external_id.to_string();
+        Box::pin(async move { Ok(s.get_order(&id).await?.state.order_status()) })
+    }
+
+    fn cancel_order_status(
+        &self,
+        id: &str,
+    ) -> Pin<Box<dyn Future<Output = Result<FiatOrderStatus>> + Send>> {
+        let s = self.clone();
+        let id = id.to_string();
+        Box::pin(async move {
+            match s.cancel_order(&id).await {
+                Ok(order) => Ok(order.state.order_status()),
+                Err(e) => {
+                    // Only pending/authorised orders can be cancelled
+                    let status = s.get_order(&id).await?.state.order_status();
+                    if status.is_final() {
+                        Ok(status)
+                    } else {
+                        Err(e)
+                    }
+                }
+            }
+        })
+    }
+
+    fn refund(
+        &self,
+        external_id: &str,
+        amount: Option<CurrencyAmount>,
+    ) -> Pin<Box<dyn Future<Output = Result<FiatRefundInfo>> + Send>> {
+        let s = self.clone();
+        let id = external_id.to_string();
+        Box::pin(async move {
+            let (amount, currency) = match amount {
+                Some(a) if a.currency() == Currency::BTC => {
+                    bail!("Bitcoin amount not allowed for fiat payments")
+                }
+                Some(a) => (a.value(), a.currency().to_string()),
+                None => {
+                    let order = s.get_order(&id).await?;
+                    (order.amount, order.currency)
+                }
+            };
+            let rsp = s.refund_order(&id, amount, &currency).await?;
+            Ok(FiatRefundInfo {
+                raw_data: serde_json::to_string(&rsp)?,
+                external_id: rsp.id,
+            })
+        })
+    }
+
     #[cfg_attr(coverage_nightly, coverage(off))]
     fn create_subscription(
         &self,
@@ -372,10 +547,28 @@ impl FiatPaymentService for RevolutApi {
                 .await?;
             Ok(FiatPaymentInfo {
                 raw_data: serde_json::to_string(&rsp)?,
+                checkout_url: rsp.checkout_url,
                 external_id: rsp.id,
             })
         })
     }
+
+    fn verify_webhook(&self, msg: &WebhookMessage) -> Result<VerifiedEvent> {
+        let secret = self
+            .webhook_secret
+            .as_ref()
+            .ok_or_else(|| anyhow!("No webhook secret configured"))?;
+        let body = RevolutWebhookBody::verify(secret, msg)?;
+        Ok(VerifiedEvent {
+            event_id: None,
+            event_type: serde_json::to_value(&body.event)?
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            external_id: Some(body.order_id),
+            raw_data: String::from_utf8_lossy(&msg.body).into_owned(),
+        })
+    }
 }
 
 #[derive(Clone, Serialize)]
@@ -398,6 +591,51 @@ pub struct CreateOrderRequest {
     /// this order for future off-session charges.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub save_payment_method_for: Option<String>,
+
+    /// Where the customer is sent after completing the payment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub redirect_url: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<HashMap<String, String>>,
+}
+
+impl CreateOrderRequest {
+    /// Build an order request, checking that line items add up to `amount`.
+    pub fn new(
+        amount: CurrencyAmount,
+        description: Option<String>,
+        line_items: Option<Vec<RevolutLineItem>>,
+        customer: Option<RevolutCustomer>,
+        save_payment_method_for: Option<String>,
+    ) -> Result<Self> {
+        if amount.currency() == Currency::BTC {
+            bail!("Bitcoin amount not allowed for fiat payments");
+        }
+        // Revolut rejects orders whose line items do not add up to the amount
+        if let Some(items) = &line_items {
+            let total = items
+                .iter()
+                .try_fold(0u64, |acc, i| acc.checked_add(i.total_amount))
+                .ok_or_else(|| anyhow!("Line item total overflows"))?;
+            ensure!(
+                total == amount.value(),
+                "Line item total {} does not match order amount {}",
+                CurrencyAmount::from_u64(amount.currency(), total),
+                amount
+            );
+        }
+        Ok(Self {
+            amount: amount.value(),
+            currency: amount.currency().to_string(),
+            description,
+            line_items,
+            customer,
+            save_payment_method_for,
+            redirect_url: None,
+            metadata: None,
+        })
+    }
 }
 
 /// A customer to create or attach to an order.
@@ -523,6 +761,39 @@ pub struct RevolutTax {
     pub amount: u64,
 }
 
+impl From<LineItem> for RevolutLineItem {
+    /// Convert a generic line item.
+    ///
+    /// `tax_amount`/`tax_name` become a tax. Revolut specific fields are read
+    /// from `metadata` when present: `type` (`"physical"`, `"digital"` or
+    /// `"service"`), `unit`, `url`, `external_id` and `discounts` (a list of
+    /// `{"name", "amount"}`).
+    fn from(item: LineItem) -> Self {
+        let meta = item.metadata.unwrap_or_default();
+        let meta_str = |key: &str| meta.get(key).and_then(|v| v.as_str()).map(String::from);
+        let mut ret = RevolutLineItem::simple(item.name, item.quantity, item.unit_amount);
+        ret.description = item.description;
+        ret.item_type = meta
+            .get("type")
+            .and_then(|v| serde_json::from_value(v.clone()).ok());
+        ret.quantity.unit = meta_str("unit");
+        ret.url = meta_str("url");
+        ret.external_id = meta_str("external_id");
+        ret.image_urls = item.images;
+        ret.discounts = meta
+            .get("discounts")
+            .and_then(|v| serde_json::from_value(v.clone()).ok());
+        ret.taxes = item.tax_amount.map(|amount| {
+            vec![RevolutTax {
+                name: item.tax_name.unwrap_or("Tax".to_string()),
+                amount,
+            }]
+        });
+        ret.recalculate_total();
+        ret
+    }
+}
+
 impl RevolutLineItem {
     /// Create a simple line item with just name, quantity, and pricing
     pub fn simple(name: String, quantity: u64, unit_price_amount: u64) -> Self {
@@ -668,6 +939,38 @@ impl RevolutOrder {
             Some((id.clone(), pm.kind.clone()))
         })
     }
+
+    /// Net amount settled to the merchant across the order's captured and
+    /// completed payments, with the settlement currency.
+    ///
+    /// Returns `None` if no payment has settled yet, or if payments settled in
+    /// different currencies and cannot be summed.
+    pub fn settled_total(&self) -> Option<(u64, String)> {
+        let mut total: Option<(u64, String)> = None;
+        let settled = self.payments.as_ref()?.iter().filter(|p| {
+            matches!(
+                p.state,
+                RevolutPaymentState::Captured | RevolutPaymentState::Completed
+            )
+        });
+        for p in settled {
+            let (Some(amount), Some(currency)) = (p.settled_amount, &p.settled_currency) else {
+                continue;
+            };
+            total = match total {
+                None => Some((amount, currency.clone())),
+                Some((sum, c)) if c == *currency => Some((sum + amount, c)),
+                Some(_) => return None,
+            };
+        }
+        total
+    }
+
+    /// Whether the full order amount has been captured, i.e. nothing is
+    /// outstanding.
+    pub fn is_fully_captured(&self) -> bool {
+        self.outstanding_amount == 0
+    }
 }
 
 /// Customer object nested on a [`RevolutOrder`].
@@ -740,6 +1043,7 @@ pub struct RevolutOrderPayment {
     pub updated_at: Option<DateTime<Utc>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub token: Option<String>,
+    #[serde(default)]
     pub amount: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub currency: Option<String>,
@@ -805,7 +1109,9 @@ pub struct RevolutBillingAddress {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub city: Option<String>,
 
+    #[serde(default)]
     pub country_code: String,
+    #[serde(default)]
     pub postcode: String,
 }
 
@@ -820,6 +1126,20 @@ pub enum RevolutOrderState {
     Failed,
 }
 
+impl RevolutOrderState {
+    /// Normalized order state
+    pub fn order_status(&self) -> FiatOrderStatus {
+        match self {
+            RevolutOrderState::Pending
+            | RevolutOrderState::Processing
+            | RevolutOrderState::Authorised => FiatOrderStatus::Pending,
+            RevolutOrderState::Completed => FiatOrderStatus::Completed,
+            RevolutOrderState::Cancelled => FiatOrderStatus::Cancelled,
+            RevolutOrderState::Failed => FiatOrderStatus::Failed,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum RevolutPaymentState {
@@ -849,6 +1169,7 @@ pub enum RevolutPaymentState {
 pub struct RevolutWebhook {
     pub id: String,
     pub url: String,
+    #[serde(default)]
     pub events: Vec<RevolutWebhookEvent>,
     pub signing_secret: Option<String>,
 }
@@ -860,7 +1181,6 @@ pub struct RevolutWebhookBody {
     pub merchant_order_ext_ref: Option<String>,
 }
 
-type HmacSha256 = Hmac<sha2::Sha256>;
 impl RevolutWebhookBody {
     /// Default tolerance for webhook timestamp replay protection (5 minutes).
     pub const DEFAULT_TOLERANCE: std::time::Duration = std::time::Duration::from_secs(300);
@@ -903,19 +1223,9 @@ impl RevolutWebhookBody {
                 sig_split.next().context("Invalid signature format")?,
                 sig_split.next().context("Invalid signature format")?,
             );
-            let Ok(expected) = hex::decode(code) else {
-                warn!("Invalid signature encoding: {}", code);
-                continue;
-            };
-            // HMAC accepts keys of any length, so `new_from_slice` cannot fail.
-            let mut mac =
-                HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
-            mac.update(version.as_bytes());
-            mac.update(b".");
-            mac.update(timestamp.as_bytes());
-            mac.update(b".");
-            mac.update(msg.body.as_slice());
-            if mac.verify_slice(&expected).is_ok() {
+            let mut payload = format!("{}.{}.", version, timestamp).into_bytes();
+            payload.extend_from_slice(&msg.body);
+            if verify_hmac_sha256(secret.as_bytes(), &payload, code).is_ok() {
                 verified = true;
                 break;
             }
@@ -948,6 +1258,20 @@ pub enum RevolutWebhookEvent {
     OrderCancelled,
 }
 
+#[derive(Clone, Serialize)]
+pub struct CaptureOrderRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<u64>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct RefundOrderRequest {
+    pub amount: u64,
+    pub currency: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
 #[derive(Clone, Deserialize, Serialize)]
 pub struct CreateWebhookRequest {
     pub url: String,
@@ -957,8 +1281,10 @@ pub struct CreateWebhookRequest {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use hmac::{Hmac, Mac};
+
+    type HmacSha256 = Hmac<sha2::Sha256>;
     use crate::webhook::WebhookMessage;
-    use hmac::Mac;
     use std::collections::HashMap;
 
     fn create_revolut_signature(
@@ -984,6 +1310,497 @@ mod tests {
             .as_millis() as i64
     }
 
+    fn order_json(id: &str, state: &str, amount: u64) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "token": "tok",
+            "state": state,
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "amount": amount,
+            "currency": "GBP",
+            "outstanding_amount": if state == "completed" { 0 } else { amount }
+        })
+    }
+
+    fn mock_api(server: &wiremock::MockServer) -> RevolutApi {
+        RevolutApi::new(RevolutConfig {
+            url: Some(server.uri()),
+            api_version: "2024-09-01".to_string(),
+            token: "test_token".to_string(),
+            public_key: "pk_test".to_string(),
+            webhook_secret: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_revolut_line_item_from_metadata() {
+        let item = RevolutLineItem::from(LineItem {
+            name: "Coffee".to_string(),
+            description: Some("Beans".to_string()),
+            unit_amount: 500,
+            quantity: 2,
+            currency: "GBP".to_string(),
+            images: Some(vec!["https://example.com/coffee.jpg".to_string()]),
+            metadata: Some(serde_json::json!({
+                "type": "physical",
+                "unit": "kg",
+                "url": "https://example.com/coffee",
+                "external_id": "sku_1",
+                "discounts": [{"name": "Loyalty", "amount": 150}]
+            })),
+            tax_amount: Some(170),
+            tax_name: Some("20% VAT".to_string()),
+            tax_rates: None,
+        });
+        assert_eq!(item.total_amount, 1000 - 150 + 170);
+        assert_eq!(
+            serde_json::to_value(&item).unwrap(),
+            serde_json::json!({
+                "name": "Coffee",
+                "description": "Beans",
+                "type": "physical",
+                "quantity": {"value": 2, "unit": "kg"},
+                "unit_price_amount": 500,
+                "total_amount": 1020,
+                "external_id": "sku_1",
+                "discounts": [{"name": "Loyalty", "amount": 150}],
+                "taxes": [{"name": "20% VAT", "amount": 170}],
+                "image_urls": ["https://example.com/coffee.jpg"],
+                "url": "https://example.com/coffee"
+            })
+        );
+    }
+
+    #[test]
+    fn test_revolut_order_request_checks_item_total() {
+        let items = vec![
+            RevolutLineItem::simple("A".to_string(), 1, 1000).with_discounts(vec![
+                RevolutDiscount {
+                    name: "Sale".to_string(),
+                    amount: 200,
+                },
+            ]),
+        ];
+        let gbp = |v| CurrencyAmount::from_u64(Currency::GBP, v);
+        assert!(CreateOrderRequest::new(gbp(1000), None, Some(items.clone()), None, None).is_err());
+        let req = CreateOrderRequest::new(gbp(800), None, Some(items), None, None).unwrap();
+        assert_eq!(req.amount, 800);
+        assert!(
+            CreateOrderRequest::new(CurrencyAmount::millisats(1000), None, None, None, None)
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_revolut_create_order_with_items() {
+        use wiremock::matchers::{body_partial_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/orders"))
+            .and(body_partial_json(serde_json::json!({
+                "amount": 170,
+                "currency": "GBP",
+                "line_items": [{
+                    "name": "Example item",
+                    "quantity": {"value": 2, "unit": "kg"},
+                    "total_amount": 170,
+                    "discounts": [{"name": "Discount 1", "amount": 50}],
+                    "taxes": [{"name": "10% VAT", "amount": 20}]
+                }]
+            })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(order_json("ord_1", "pending", 170)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = mock_api(&server);
+        let item = RevolutLineItem::simple("Example item".to_string(), 2, 100)
+            .with_unit("kg".to_string())
+            .with_discounts(vec![RevolutDiscount {
+                name: "Discount 1".to_string(),
+                amount: 50,
+            }])
+            .with_taxes(vec![RevolutTax {
+                name: "10% VAT".to_string(),
+                amount: 20,
+            }]);
+        let order = api
+            .create_order_with_items(Currency::GBP, None, vec![item])
+            .await
+            .unwrap();
+        assert_eq!(order.id, "ord_1");
+    }
+
+    #[tokio::test]
+    async fn test_revolut_create_order_with_options() {
+        use wiremock::matchers::{body_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/orders"))
+            .and(body_json(serde_json::json!({
+                "amount": 1000,
+                "currency": "GBP",
+                "description": "Order #1",
+                "customer": {"email": "jane@example.com"},
+                "redirect_url": "https://shop.example/done",
+                "metadata": {"order_id": "42"}
+            })))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(order_json("ord_1", "pending", 1000)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = mock_api(&server);
+        let info = FiatPaymentService::create_order_with_options(
+            &api,
+            "Order #1",
+            CurrencyAmount::from_u64(Currency::GBP, 1000),
+            None,
+            OrderOptions::default()
+                .with_success_url("https://shop.example/done")
+                .with_customer_email("jane@example.com")
+                .with_metadata("order_id", "42"),
+        )
+        .await
+        .unwrap();
+        assert_eq!(info.external_id, "ord_1");
+    }
+
+    #[tokio::test]
+    async fn test_revolut_create_order_idempotency_key() {
+        use crate::IDEMPOTENCY_KEY_HEADER;
+        use wiremock::matchers::{header, header_exists, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/orders"))
+            .and(header(IDEMPOTENCY_KEY_HEADER, "shop-order-42"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(order_json("ord_1", "pending", 1000)),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/orders"))
+            .and(header_exists(IDEMPOTENCY_KEY_HEADER))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(order_json("ord_2", "pending", 1000)),
+            )
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let api = mock_api(&server);
+        let amount = CurrencyAmount::from_u64(Currency::GBP, 1000);
+        for _ in 0..2 {
+            let order = api
+                .create_order_with_idempotency_key(amount, None, None, "shop-order-42".to_string())
+                .await
+                .unwrap();
+            assert_eq!(order.id, "ord_1");
+        }
+
+        // Without a key every order gets its own random one
+        api.create_order(amount, None, None).await.unwrap();
+        api.create_order(amount, None, None).await.unwrap();
+        let requests = server.received_requests().await.unwrap();
+        let keys: Vec<_> = requests
+            .iter()
+            .map(|r| {
+                r.headers[IDEMPOTENCY_KEY_HEADER]
+                    .to_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(keys[0], keys[1]);
+        assert_ne!(keys[2], keys[3]);
+        assert_eq!(keys[2].len(), 36);
+    }
+
+    #[tokio::test]
+    async fn test_revolut_get_order_status() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let cases = [
+            ("pending", FiatOrderStatus::Pending),
+            ("authorised", FiatOrderStatus::Pending),
+            ("completed", FiatOrderStatus::Completed),
+            ("cancelled", FiatOrderStatus::Cancelled),
+            ("failed", FiatOrderStatus::Failed),
+        ];
+        for (state, _) in &cases {
+            Mock::given(method("GET"))
+                .and(path(format!("/api/orders/ord_{}", state)))
+                .respond_with(ResponseTemplate::new(200).set_body_json(order_json(
+                    &format!("ord_{}", state),
+                    state,
+                    1000,
+                )))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+
+        let api = mock_api(&server);
+        for (state, expected) in cases {
+            let status = FiatPaymentService::get_order(&api, &format!("ord_{}", state))
+                .await
+                .unwrap();
+            assert_eq!(status, expected, "{}", state);
+        }
+        assert!(
+            FiatPaymentService::get_order(&api, "ord_missing")
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_revolut_create_order_line_item_currency() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let item = |currency: &str| LineItem {
+            name: "Widget".to_string(),
+            description: None,
+            unit_amount: 500,
+            quantity: 2,
+            currency: currency.to_string(),
+            images: None,
+            metadata: None,
+            tax_amount: None,
+            tax_name: None,
+            tax_rates: None,
+        };
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/orders"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(order_json("ord_1", "pending", 1000)),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = mock_api(&server);
+        let gbp = CurrencyAmount::from_u64(Currency::GBP, 1000);
+        // currency codes match case-insensitively
+        let order = api
+            .create_order(gbp, None, Some(vec![item("gbp")]))
+            .await
+            .unwrap();
+        assert_eq!(order.id, "ord_1");
+
+        // rejected before any request is made
+        let err = api
+            .create_order(gbp, None, Some(vec![item("USD")]))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("currency"), "{}", err);
+        let err = FiatPaymentService::create_order(
+            &api,
+            "Order",
+            CurrencyAmount::from_u64(Currency::GBP, 2000),
+            Some(vec![item("gbp"), item("EUR")]),
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("EUR"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn test_revolut_capture_order() {
+        use wiremock::matchers::{body_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/orders/ord_1/capture"))
+            .and(body_json(serde_json::json!({})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(order_json(
+                "ord_1",
+                "completed",
+                1000,
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/orders/ord_2/capture"))
+            .and(body_json(serde_json::json!({"amount": 400})))
+            .respond_with(ResponseTemplate::new(200).set_body_json(order_json(
+                "ord_2",
+                "completed",
+                1000,
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = mock_api(&server);
+        let full = api.capture_order("ord_1", None).await.unwrap();
+        assert!(matches!(full.state, RevolutOrderState::Completed));
+        let partial = api.capture_order("ord_2", Some(400)).await.unwrap();
+        assert_eq!(partial.id, "ord_2");
+    }
+
+    #[tokio::test]
+    async fn test_revolut_refund_order() {
+        use wiremock::matchers::{body_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/orders/ord_1/refund"))
+            .and(body_json(
+                serde_json::json!({"amount": 1000, "currency": "GBP"}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(order_json(
+                "ref_full",
+                "completed",
+                1000,
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/orders/ord_1/refund"))
+            .and(body_json(
+                serde_json::json!({"amount": 250, "currency": "GBP"}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(order_json(
+                "ref_partial",
+                "completed",
+                250,
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = mock_api(&server);
+        let full = api.refund_order("ord_1", 1000, "GBP").await.unwrap();
+        assert_eq!(full.id, "ref_full");
+        let partial = api.refund_order("ord_1", 250, "gbp").await.unwrap();
+        assert_eq!((partial.id.as_str(), partial.amount), ("ref_partial", 250));
+    }
+
+    #[tokio::test]
+    async fn test_revolut_refund() {
+        use wiremock::matchers::{body_json, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/api/orders/ord_1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(order_json(
+                "ord_1",
+                "completed",
+                1000,
+            )))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/orders/ord_1/refund"))
+            .and(body_json(
+                serde_json::json!({"amount": 1000, "currency": "GBP"}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(order_json(
+                "ref_full",
+                "completed",
+                1000,
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/orders/ord_1/refund"))
+            .and(body_json(
+                serde_json::json!({"amount": 300, "currency": "GBP"}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(order_json(
+                "ref_partial",
+                "completed",
+                300,
+            )))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = mock_api(&server);
+        let svc: &dyn FiatPaymentService = &api;
+        let full = svc.refund("ord_1", None).await.unwrap();
+        assert_eq!(full.external_id, "ref_full");
+        assert!(full.raw_data.contains("ref_full"));
+        let partial = svc
+            .refund("ord_1", Some(CurrencyAmount::from_u64(Currency::GBP, 300)))
+            .await
+            .unwrap();
+        assert_eq!(partial.external_id, "ref_partial");
+
+        let err = svc
+            .refund("ord_1", Some(CurrencyAmount::from_u64(Currency::BTC, 1000)))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Bitcoin"));
+    }
+
+    #[tokio::test]
+    async fn test_revolut_cancel_order_status() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/orders/ord_open/cancel"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(order_json(
+                "ord_open",
+                "cancelled",
+                1000,
+            )))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/orders/ord_paid/cancel"))
+            .respond_with(ResponseTemplate::new(422).set_body_string("{}"))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/orders/ord_paid"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(order_json(
+                "ord_paid",
+                "completed",
+                1000,
+            )))
+            .mount(&server)
+            .await;
+
+        let api = mock_api(&server);
+        assert_eq!(
+            api.cancel_order_status("ord_open").await.unwrap(),
+            FiatOrderStatus::Cancelled
+        );
+        assert_eq!(
+            api.cancel_order_status("ord_paid").await.unwrap(),
+            FiatOrderStatus::Completed
+        );
+        assert!(api.cancel_order_status("ord_missing").await.is_err());
+    }
+
     #[test]
     fn test_revolut_webhook_verify_valid() {
         let secret = "test_secret";
@@ -995,6 +1812,7 @@ mod tests {
 
         let msg = WebhookMessage {
             endpoint: "/webhooks/revolut".to_string(),
+            query: None,
             body: body.as_bytes().to_vec(),
             headers: HashMap::from([
                 ("revolut-signature".to_string(), signature),
@@ -1009,6 +1827,81 @@ mod tests {
         assert!(matches!(webhook.event, RevolutWebhookEvent::OrderCompleted));
     }
 
+    #[test]
+    fn test_revolut_verify_webhook() {
+        let secret = "test_secret";
+        let api = RevolutApi::new(RevolutConfig {
+            url: None,
+            api_version: "2024-09-01".to_string(),
+            token: "test_token".to_string(),
+            public_key: "pk_test".to_string(),
+            webhook_secret: Some(secret.to_string()),
+        })
+        .unwrap();
+        let timestamp = now_millis().to_string();
+        let body = r#"{"event":"ORDER_COMPLETED","order_id":"order_123"}"#;
+        let signature = create_revolut_signature(secret, "v1", &timestamp, body.as_bytes());
+        let mut msg = WebhookMessage {
+            endpoint: "/webhooks/revolut".to_string(),
+            query: None,
+            body: body.as_bytes().to_vec(),
+            headers: HashMap::from([
+                ("revolut-signature".to_string(), signature),
+                ("revolut-request-timestamp".to_string(), timestamp),
+            ]),
+        };
+
+        let service: &dyn FiatPaymentService = &api;
+        let event = service.verify_webhook(&msg).unwrap();
+        assert_eq!(event.event_type, "ORDER_COMPLETED");
+        assert_eq!(event.external_id.as_deref(), Some("order_123"));
+        assert!(event.event_id.is_none());
+
+        msg.body = body.replace("order_123", "order_456").into_bytes();
+        assert!(service.verify_webhook(&msg).is_err());
+    }
+
+    #[test]
+    fn test_revolut_verify_webhook_requires_secret() {
+        let api = RevolutApi::new(RevolutConfig {
+            url: None,
+            api_version: "2024-09-01".to_string(),
+            token: "test_token".to_string(),
+            public_key: "pk_test".to_string(),
+            webhook_secret: None,
+        })
+        .unwrap();
+        let msg = WebhookMessage {
+            endpoint: "/webhooks/revolut".to_string(),
+            query: None,
+            body: vec![],
+            headers: HashMap::new(),
+        };
+        assert!(api.verify_webhook(&msg).is_err());
+    }
+
+    #[test]
+    fn test_revolut_webhook_verify_tampered_body_rejected() {
+        let secret = "test_secret";
+        let timestamp = now_millis().to_string();
+        let body = r#"{"event":"ORDER_COMPLETED","order_id":"order_123"}"#;
+
+        let signature = create_revolut_signature(secret, "v1", &timestamp, body.as_bytes());
+        let tampered = body.replace("order_123", "order_456");
+
+        let msg = WebhookMessage {
+            endpoint: "/webhooks/revolut".to_string(),
+            query: None,
+            body: tampered.into_bytes(),
+            headers: HashMap::from([
+                ("revolut-signature".to_string(), signature),
+                ("revolut-request-timestamp".to_string(), timestamp),
+            ]),
+        };
+
+        assert!(RevolutWebhookBody::verify(secret, &msg).is_err());
+    }
+
     #[test]
     fn test_revolut_webhook_verify_expired_timestamp_rejected() {
         // Regression: a validly-signed but old event must be rejected by the
@@ -1020,6 +1913,7 @@ mod tests {
         let signature = create_revolut_signature(secret, "v1", &timestamp, body.as_bytes());
         let msg = WebhookMessage {
             endpoint: "/webhooks/revolut".to_string(),
+            query: None,
             body: body.as_bytes().to_vec(),
             headers: HashMap::from([
                 ("revolut-signature".to_string(), signature),
@@ -1046,6 +1940,7 @@ mod tests {
         let signature = create_revolut_signature(secret, "v1", timestamp, body.as_bytes());
         let msg = WebhookMessage {
             endpoint: "/webhooks/revolut".to_string(),
+            query: None,
             body: body.as_bytes().to_vec(),
             headers: HashMap::from([
                 ("revolut-signature".to_string(), signature),
@@ -1064,6 +1959,7 @@ mod tests {
     fn test_revolut_webhook_verify_missing_signature() {
         let msg = WebhookMessage {
             endpoint: "/webhooks/revolut".to_string(),
+            query: None,
             body: b"{}".to_vec(),
             headers: HashMap::from([(
                 "revolut-request-timestamp".to_string(),
@@ -1085,6 +1981,7 @@ mod tests {
     fn test_revolut_webhook_verify_missing_timestamp() {
         let msg = WebhookMessage {
             endpoint: "/webhooks/revolut".to_string(),
+            query: None,
             body: b"{}".to_vec(),
             headers: HashMap::from([("revolut-signature".to_string(), "v1=abc123".to_string())]),
         };
@@ -1103,6 +2000,7 @@ mod tests {
     fn test_revolut_webhook_verify_invalid_signature() {
         let msg = WebhookMessage {
             endpoint: "/webhooks/revolut".to_string(),
+            query: None,
             body: r#"{"event":"ORDER_COMPLETED","order_id":"123"}"#.as_bytes().to_vec(),
             headers: HashMap::from([
                 (
@@ -1269,9 +2167,12 @@ mod tests {
             line_items: None,
             customer: None,
             save_payment_method_for: None,
+            redirect_url: None,
+            metadata: None,
         };
         let json = serde_json::to_value(&req).unwrap();
         assert!(json.get("customer").is_none());
+        assert!(json.get("redirect_url").is_none());
         assert!(json.get("save_payment_method_for").is_none());
         assert_eq!(json["amount"], 1000);
     }
@@ -1288,6 +2189,8 @@ mod tests {
                 ..Default::default()
             }),
             save_payment_method_for: Some("merchant".to_string()),
+            redirect_url: None,
+            metadata: None,
         };
         let json = serde_json::to_value(&req).unwrap();
         assert_eq!(json["save_payment_method_for"], "merchant");
@@ -1351,6 +2254,63 @@ mod tests {
         assert!(matches!(pm_type, RevolutPaymentMethodType::Card));
     }
 
+    fn order_with_payments(outstanding: u64, payments: serde_json::Value) -> RevolutOrder {
+        serde_json::from_value(serde_json::json!({
+            "id": "order_1",
+            "token": "tok_1",
+            "state": "completed",
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "amount": 3000,
+            "currency": "EUR",
+            "outstanding_amount": outstanding,
+            "payments": payments
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_order_settled_total_sums_settled_payments() {
+        let order = order_with_payments(
+            0,
+            serde_json::json!([
+                { "id": "pay_1", "state": "completed", "amount": 2000,
+                  "settled_amount": 1960, "settled_currency": "GBP" },
+                { "id": "pay_2", "state": "captured", "amount": 1000,
+                  "settled_amount": 980, "settled_currency": "GBP" },
+                { "id": "pay_3", "state": "declined", "amount": 1000,
+                  "settled_amount": 980, "settled_currency": "GBP" },
+                { "id": "pay_4", "state": "pending", "amount": 1000 }
+            ]),
+        );
+        assert_eq!(order.settled_total(), Some((2940, "GBP".to_string())));
+        assert!(order.is_fully_captured());
+    }
+
+    #[test]
+    fn test_order_settled_total_none_when_unsettled_or_mixed() {
+        let pending = order_with_payments(
+            3000,
+            serde_json::json!([
+                { "id": "pay_1", "state": "authorised", "amount": 3000 },
+                { "id": "pay_2", "state": "failed", "amount": 3000 }
+            ]),
+        );
+        assert_eq!(pending.settled_total(), None);
+        assert!(!pending.is_fully_captured());
+
+        let mixed = order_with_payments(
+            0,
+            serde_json::json!([
+                { "id": "pay_1", "state": "completed", "amount": 2000,
+                  "settled_amount": 1960, "settled_currency": "GBP" },
+                { "id": "pay_2", "state": "completed", "amount": 1000,
+                  "settled_amount": 1000, "settled_currency": "EUR" }
+            ]),
+        );
+        assert_eq!(mixed.settled_total(), None);
+    }
+
     #[test]
     fn test_order_saved_payment_method_none_when_no_payments() {
         let json = r#"{
@@ -1442,6 +2402,86 @@ mod tests {
         assert_eq!(order.customer_id().as_deref(), Some("cust_nested"));
     }
 
+    #[test]
+    fn test_order_pending_deserialize() {
+        // A freshly created order as returned by the Merchant API, fields this
+        // crate doesn't model are ignored
+        let json = r#"{
+            "id": "6516e61c-d279-a454-a837-bc52ce55ed49",
+            "token": "0adc0e3c-ab44-4f33-bcc0-534ded7354ce",
+            "type": "payment",
+            "state": "pending",
+            "created_at": "2023-09-29T14:58:36.079398Z",
+            "updated_at": "2023-09-29T14:58:36.079398Z",
+            "amount": 1000,
+            "currency": "GBP",
+            "outstanding_amount": 1000,
+            "capture_mode": "automatic",
+            "checkout_url": "https://checkout.revolut.com/payment-link/0adc0e3c-ab44-4f33-bcc0-534ded7354ce",
+            "enforce_challenge": "automatic"
+        }"#;
+        let order: RevolutOrder = serde_json::from_str(json).unwrap();
+        assert_eq!(order.id, "6516e61c-d279-a454-a837-bc52ce55ed49");
+        assert!(matches!(order.state, RevolutOrderState::Pending));
+        assert_eq!((order.amount, order.currency.as_str()), (1000, "GBP"));
+        assert_eq!(order.outstanding_amount, 1000);
+        assert!(order.description.is_none());
+        assert!(order.payments.is_none());
+
+        // amount and currency are always present, a response without them is
+        // rejected instead of read as a zero amount
+        let mut partial: serde_json::Value = serde_json::from_str(json).unwrap();
+        partial.as_object_mut().unwrap().remove("amount");
+        assert!(serde_json::from_value::<RevolutOrder>(partial).is_err());
+    }
+
+    #[test]
+    fn test_order_round_trip() {
+        let json = r#"{
+            "id": "order_1",
+            "token": "tok_1",
+            "state": "completed",
+            "created_at": "2024-01-01T00:00:00Z",
+            "updated_at": "2024-01-01T00:00:00Z",
+            "amount": 1000,
+            "currency": "EUR",
+            "outstanding_amount": 0,
+            "checkout_url": "https://checkout.revolut.com/pay/tok_1",
+            "payments": [{ "id": "pay_1", "state": "captured" }]
+        }"#;
+        let order: RevolutOrder = serde_json::from_str(json).unwrap();
+        let round_trip: RevolutOrder =
+            serde_json::from_str(&serde_json::to_string(&order).unwrap()).unwrap();
+        assert_eq!(round_trip.id, "order_1");
+        assert_eq!(round_trip.amount, 1000);
+        assert_eq!(round_trip.currency, "EUR");
+        assert!(matches!(round_trip.state, RevolutOrderState::Completed));
+        assert_eq!(
+            round_trip.checkout_url.as_deref(),
+            Some("https://checkout.revolut.com/pay/tok_1")
+        );
+        let payments = round_trip.payments.unwrap();
+        assert_eq!(payments[0].amount, 0);
+    }
+
+    #[test]
+    fn test_billing_address_minimal_deserialize() {
+        let json = r#"{"city":"London"}"#;
+        let address: RevolutBillingAddress = serde_json::from_str(json).unwrap();
+        assert_eq!(address.city.as_deref(), Some("London"));
+        assert_eq!(address.country_code, "");
+        assert_eq!(address.postcode, "");
+    }
+
+    #[test]
+    fn test_revolut_webhook_minimal_deserialize() {
+        let json = r#"{"id":"wh_1","url":"https://example.com/hook"}"#;
+        let webhook: RevolutWebhook = serde_json::from_str(json).unwrap();
+        assert_eq!(webhook.id, "wh_1");
+        assert!(webhook.events.is_empty());
+        assert!(webhook.signing_secret.is_none());
+    }
+
     #[test]
     fn test_revolut_config_clone() {
         let config = RevolutConfig {
@@ -1449,6 +2489,7 @@ mod tests {
             api_version: "2024-09-01".to_string(),
             token: "test_token".to_string(),
             public_key: "pk_test".to_string(),
+            webhook_secret: None,
         };
         let cloned = config.clone();
         assert_eq!(cloned.token, "test_token");
@@ -0,0 +1,441 @@
+use crate::currency::{Currency, CurrencyAmount};
+use crate::fiat::{FiatPaymentInfo, FiatPaymentService, LineItem, validate_line_items};
+use crate::json_api::{JsonApi, TokenGen};
+use crate::random_uuid;
+use anyhow::{Result, bail};
+use reqwest::header::AUTHORIZATION;
+use reqwest::{Method, RequestBuilder, Url};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct SquareConfig {
+    /// API base url, defaults to `https://connect.squareup.com`
+    /// (`https://connect.squareupsandbox.com` for the sandbox)
+    pub url: Option<String>,
+    pub access_token: String,
+    /// Location the payments are taken for
+    pub location_id: String,
+    /// `Square-Version` header, defaults to [`SquareApi::API_VERSION`]
+    #[serde(default)]
+    pub api_version: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct SquareApi {
+    api: JsonApi,
+    location_id: String,
+}
+
+#[derive(Clone)]
+struct SquareTokenGen {
+    pub token: String,
+    pub api_version: String,
+}
+
+impl TokenGen for SquareTokenGen {
+    fn generate_token(
+        &self,
+        _method: Method,
+        _url: &Url,
+        _body: Option<&str>,
+        req: RequestBuilder,
+    ) -> Result<RequestBuilder> {
+        Ok(req
+            .header(AUTHORIZATION, format!("Bearer {}", self.token))
+            .header("Square-Version", &self.api_version))
+    }
+}
+
+impl SquareApi {
+    /// Default `Square-Version` header
+    pub const API_VERSION: &str = "2025-01-23";
+
+    pub fn new(config: SquareConfig) -> Result<Self> {
+        const DEFAULT_URL: &str = "https://connect.squareup.com";
+
+        let token_gen = SquareTokenGen {
+            token: config.access_token,
+            api_version: config.api_version.unwrap_or(Self::API_VERSION.to_string()),
+        };
+        Ok(Self {
+            api: JsonApi::token_gen(
+                &config.url.unwrap_or(DEFAULT_URL.to_string()),
+                false,
+                token_gen,
+            )?,
+            location_id: config.location_id,
+        })
+    }
+
+    /// Create a hosted checkout page (payment link).
+    ///
+    /// Without line items a quick pay link for `amount` is created, otherwise
+    /// an order with the line items, which must add up to `amount`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn create_payment_link(
+        &self,
+        amount: CurrencyAmount,
+        description: String,
+        line_items: Option<Vec<LineItem>>,
+    ) -> Result<SquarePaymentLink> {
+        let req = CreatePaymentLinkRequest::new(
+            random_uuid()?,
+            &self.location_id,
+            amount,
+            description,
+            line_items,
+        )?;
+        let rsp: PaymentLinkResponse = self
+            .api
+            .post("/v2/online-checkout/payment-links", req)
+            .await?;
+        Ok(rsp.payment_link)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn get_payment_link(&self, id: &str) -> Result<SquarePaymentLink> {
+        let rsp: PaymentLinkResponse = self
+            .api
+            .get(&format!("/v2/online-checkout/payment-links/{}", id))
+            .await?;
+        Ok(rsp.payment_link)
+    }
+
+    /// Delete a payment link, which also cancels its order
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn delete_payment_link(&self, id: &str) -> Result<DeletePaymentLinkResponse> {
+        Ok(self
+            .api
+            .req::<_, ()>(
+                Method::DELETE,
+                &format!("/v2/online-checkout/payment-links/{}", id),
+                None,
+            )
+            .await?)
+    }
+}
+
+impl FiatPaymentService for SquareApi {
+    fn create_order(
+        &self,
+        description: &str,
+        amount: CurrencyAmount,
+        line_items: Option<Vec<LineItem>>,
+    ) -> Pin<Box<dyn Future<Output = Result<FiatPaymentInfo>> + Send>> {
+        let s = self.clone();
+        let desc = description.to_string();
+        Box::pin(async move {
+            let rsp = s.create_payment_link(amount, desc, line_items).await?;
+            Ok(FiatPaymentInfo {
+                raw_data: serde_json::to_string(&rsp)?,
+                checkout_url: Some(rsp.url.clone()),
+                external_id: rsp.id,
+            })
+        })
+    }
+
+    fn cancel_order(&self, id: &str) -> Pin<Box<dyn Future<Output = Result<()>> + Send>> {
+        let s = self.clone();
+        let id = id.to_string();
+        Box::pin(async move {
+            s.delete_payment_link(&id).await?;
+            Ok(())
+        })
+    }
+}
+
+/// Square money object, `amount` in the smallest unit of `currency`
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub struct SquareMoney {
+    pub amount: u64,
+    pub currency: String,
+}
+
+impl TryFrom<CurrencyAmount> for SquareMoney {
+    type Error = anyhow::Error;
+
+    fn try_from(amount: CurrencyAmount) -> Result<Self> {
+        if amount.currency() == Currency::BTC {
+            bail!("Bitcoin amount not allowed for fiat payments");
+        }
+        // Square uses the ISO 4217 minor unit, which matches the currency
+        // exponent (e.g. cents for USD, whole yen for JPY)
+        Ok(Self {
+            amount: amount.value(),
+            currency: amount.currency().to_string(),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct CreatePaymentLinkRequest {
+    pub idempotency_key: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quick_pay: Option<QuickPay>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<SquareOrderRequest>,
+}
+
+impl CreatePaymentLinkRequest {
+    fn new(
+        idempotency_key: String,
+        location_id: &str,
+        amount: CurrencyAmount,
+        description: String,
+        line_items: Option<Vec<LineItem>>,
+    ) -> Result<Self> {
+        let price_money = SquareMoney::try_from(amount)?;
+        let (quick_pay, order) = match line_items {
+            None => (
+                Some(QuickPay {
+                    name: description.clone(),
+                    price_money,
+                    location_id: location_id.to_string(),
+                }),
+                None,
+            ),
+            Some(items) => {
+                validate_line_items(&amount, &items)?;
+                let line_items = items
+                    .into_iter()
+                    .map(|i| SquareLineItem {
+                        base_price_money: SquareMoney {
+                            amount: i.unit_amount,
+                            currency: price_money.currency.clone(),
+                        },
+                        name: i.name,
+                        note: i.description,
+                        quantity: i.quantity.to_string(),
+                    })
+                    .collect();
+                (
+                    None,
+                    Some(SquareOrderRequest {
+                        location_id: location_id.to_string(),
+                        line_items,
+                    }),
+                )
+            }
+        };
+        Ok(Self {
+            idempotency_key,
+            description: Some(description),
+            quick_pay,
+            order,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct QuickPay {
+    pub name: String,
+    pub price_money: SquareMoney,
+    pub location_id: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct SquareOrderRequest {
+    pub location_id: String,
+    pub line_items: Vec<SquareLineItem>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct SquareLineItem {
+    pub name: String,
+    /// Decimal quantity as a string
+    pub quantity: String,
+    pub base_price_money: SquareMoney,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+struct PaymentLinkResponse {
+    pub payment_link: SquarePaymentLink,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct SquarePaymentLink {
+    pub id: String,
+    pub version: Option<u32>,
+    pub description: Option<String>,
+    /// Order created for the link
+    pub order_id: Option<String>,
+    /// Hosted checkout page
+    pub url: String,
+    pub created_at: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DeletePaymentLinkResponse {
+    pub deleted_payment_link_id: Option<String>,
+    pub cancelled_order_id: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::{body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn item(name: &str, unit_amount: u64, quantity: u64) -> LineItem {
+        LineItem {
+            name: name.to_string(),
+            description: None,
+            unit_amount,
+            quantity,
+            currency: "USD".to_string(),
+            images: None,
+            metadata: None,
+            tax_amount: None,
+            tax_name: None,
+            tax_rates: None,
+        }
+    }
+
+    fn mock_api(server: &MockServer) -> SquareApi {
+        SquareApi::new(SquareConfig {
+            url: Some(server.uri()),
+            access_token: "sq_token".to_string(),
+            location_id: "LOC1".to_string(),
+            api_version: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_square_money() {
+        let usd = SquareMoney::try_from(CurrencyAmount::from_u64(Currency::USD, 1050)).unwrap();
+        assert_eq!(
+            usd,
+            SquareMoney {
+                amount: 1050,
+                currency: "USD".to_string()
+            }
+        );
+        // JPY has no minor unit, the amount is whole yen
+        let jpy = SquareMoney::try_from(CurrencyAmount::from_u64(Currency::JPY, 500)).unwrap();
+        assert_eq!(jpy.amount, 500);
+        assert!(SquareMoney::try_from(CurrencyAmount::millisats(1000)).is_err());
+    }
+
+    #[test]
+    fn test_quick_pay_request() {
+        let req = CreatePaymentLinkRequest::new(
+            "key-1".to_string(),
+            "LOC1",
+            CurrencyAmount::from_u64(Currency::EUR, 2000),
+            "Order #1".to_string(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(&req).unwrap(),
+            serde_json::json!({
+                "idempotency_key": "key-1",
+                "description": "Order #1",
+                "quick_pay": {
+                    "name": "Order #1",
+                    "price_money": {"amount": 2000, "currency": "EUR"},
+                    "location_id": "LOC1"
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_order_request_line_items() {
+        let req = CreatePaymentLinkRequest::new(
+            "key-1".to_string(),
+            "LOC1",
+            CurrencyAmount::from_u64(Currency::USD, 1300),
+            "Order #1".to_string(),
+            Some(vec![item("Widget", 500, 2), item("Gadget", 300, 1)]),
+        )
+        .unwrap();
+        let json = serde_json::to_value(&req).unwrap();
+        assert!(json.get("quick_pay").is_none());
+        assert_eq!(json["order"]["location_id"], "LOC1");
+        assert_eq!(
+            json["order"]["line_items"][0],
+            serde_json::json!({
+                "name": "Widget",
+                "quantity": "2",
+                "base_price_money": {"amount": 500, "currency": "USD"}
+            })
+        );
+
+        // items that do not add up to the amount are rejected
+        assert!(
+            CreatePaymentLinkRequest::new(
+                "key-1".to_string(),
+                "LOC1",
+                CurrencyAmount::from_u64(Currency::USD, 1000),
+                "Order #1".to_string(),
+                Some(vec![item("Widget", 500, 1)]),
+            )
+            .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_and_cancel_order() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v2/online-checkout/payment-links"))
+            .and(header("authorization", "Bearer sq_token"))
+            .and(header("square-version", SquareApi::API_VERSION))
+            .and(body_partial_json(serde_json::json!({
+                "quick_pay": {"price_money": {"amount": 1500, "currency": "USD"}}
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "payment_link": {
+                    "id": "PL1",
+                    "version": 1,
+                    "order_id": "ORD1",
+                    "url": "https://square.link/u/abc"
+                }
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("DELETE"))
+            .and(path("/v2/online-checkout/payment-links/PL1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "deleted_payment_link_id": "PL1",
+                "cancelled_order_id": "ORD1"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let api = mock_api(&server);
+        let info = FiatPaymentService::create_order(
+            &api,
+            "Order #1",
+            CurrencyAmount::from_u64(Currency::USD, 1500),
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(info.external_id, "PL1");
+        assert_eq!(
+            info.checkout_url.as_deref(),
+            Some("https://square.link/u/abc")
+        );
+        FiatPaymentService::cancel_order(&api, "PL1").await.unwrap();
+    }
+
+    #[test]
+    fn test_random_idempotency_key() {
+        let a = random_uuid().unwrap();
+        assert_eq!(a.len(), 36);
+        assert_eq!(&a[14..15], "4");
+        assert_ne!(a, random_uuid().unwrap());
+    }
+}
@@ -4,26 +4,28 @@
 //! for backwards compatibility and will be removed in a future release. Use the
 //! LND backend ([`crate::lightning::LndNode`]) instead.
 //!
-//! This module requires network access to the Bitvora API and cannot be unit tested
-//! without external services. Coverage exclusions are applied accordingly.
+//! The API base url can be pointed at a mock server with
+//! [`BitvoraNode::with_base_url`] for testing.
 #![allow(deprecated)]
 
-use crate::json_api::JsonApi;
+use crate::json_api::{JsonApi, JsonApiError};
 use crate::lightning::{
-    AddInvoiceRequest, AddInvoiceResponse, InvoiceUpdate, LightningNode, PayInvoiceRequest,
-    PayInvoiceResponse,
+    AddInvoiceRequest, AddInvoiceResponse, DEFAULT_DEDUP_WINDOW, DEFAULT_INVOICE_EXPIRY,
+    InvalidInvoice, InvoiceUpdate, InvoiceUpdateExt, LightningNode, PayInvoiceRequest,
+    PayInvoiceResponse, invoice_memo, parse_invoice,
+};
+use crate::webhook::{
+    WEBHOOK_BRIDGE, WebhookMessage, WebhookPathMatcher, WebhookVerifier, verify_hmac_sha256,
 };
-use crate::webhook::{WEBHOOK_BRIDGE, WebhookMessage};
 use anyhow::{anyhow, bail};
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use futures::{Stream, StreamExt};
 use hex::ToHex;
-use hmac::{Hmac, Mac};
-use lightning_invoice::Bolt11Invoice;
 use log::{info, warn};
+use reqwest::Method;
 use serde::{Deserialize, Serialize};
 use std::pin::Pin;
-use tokio_stream::wrappers::BroadcastStream;
 
 /// Bitvora Lightning payment node client.
 ///
@@ -36,8 +38,35 @@ use tokio_stream::wrappers::BroadcastStream;
 pub struct BitvoraNode {
     api: JsonApi,
     webhook_secret: String,
-    /// Path used in the request for webhook matching
-    webhook_path: String,
+    /// Matches the request path of Bitvora webhooks
+    webhook_path: WebhookPathMatcher,
+    /// Invoice expiry in seconds when a request sets none
+    default_expiry_seconds: u32,
+}
+
+/// Configuration of a [`BitvoraNode`].
+#[deprecated(
+    since = "0.3.0",
+    note = "Bitvora is no longer operational; use the LND backend (LndNode) instead"
+)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BitvoraConfig {
+    /// API base url, defaults to `https://api.bitvora.com/`
+    pub url: Option<String>,
+    /// Your Bitvora API token
+    pub api_token: String,
+    /// Secret for verifying webhook signatures
+    pub webhook_secret: String,
+    /// The URL path where webhooks will be received
+    pub webhook_path: String,
+    /// Accept invalid TLS certificates, e.g. of a local sandbox
+    #[serde(default)]
+    pub allow_invalid_certs: bool,
+    /// Invoice expiry in seconds when a request sets none, defaults to
+    /// [`DEFAULT_INVOICE_EXPIRY`](crate::lightning::DEFAULT_INVOICE_EXPIRY)
+    #[serde(default)]
+    pub default_expiry_seconds: Option<u32>,
 }
 
 impl BitvoraNode {
@@ -49,24 +78,78 @@ impl BitvoraNode {
     /// * `webhook_secret` - Secret for verifying webhook signatures
     /// * `webhook_path` - The URL path where webhooks will be received
     pub fn new(api_token: &str, webhook_secret: &str, webhook_path: &str) -> anyhow::Result<Self> {
-        let auth = format!("Bearer {}", api_token);
-        Ok(Self {
-            api: JsonApi::token("https://api.bitvora.com/", &auth, false)?,
+        Self::from_config(BitvoraConfig {
+            url: None,
+            api_token: api_token.to_string(),
+            webhook_secret: webhook_secret.to_string(),
+            webhook_path: webhook_path.to_string(),
+            allow_invalid_certs: false,
+            default_expiry_seconds: None,
+        })
+    }
+
+    /// Create a new Bitvora node client using the API at `base_url`, e.g. a
+    /// mock server in tests.
+    pub fn with_base_url(
+        base_url: &str,
+        api_token: &str,
+        webhook_secret: &str,
+        webhook_path: &str,
+    ) -> anyhow::Result<Self> {
+        Self::from_config(BitvoraConfig {
+            url: Some(base_url.to_string()),
+            api_token: api_token.to_string(),
             webhook_secret: webhook_secret.to_string(),
             webhook_path: webhook_path.to_string(),
+            allow_invalid_certs: false,
+            default_expiry_seconds: None,
         })
     }
+
+    /// Create a new Bitvora node client from `config`.
+    ///
+    /// Fails if the url or API token are invalid.
+    pub fn from_config(config: BitvoraConfig) -> anyhow::Result<Self> {
+        const DEFAULT_URL: &str = "https://api.bitvora.com/";
+
+        let url = config.url.as_deref().unwrap_or(DEFAULT_URL);
+        let auth = format!("Bearer {}", config.api_token);
+        Ok(Self {
+            api: JsonApi::token(url, &auth, config.allow_invalid_certs)
+                .map_err(|e| anyhow!("Invalid Bitvora config {}: {}", url, e))?,
+            webhook_secret: config.webhook_secret,
+            webhook_path: config.webhook_path.as_str().into(),
+            default_expiry_seconds: config
+                .default_expiry_seconds
+                .unwrap_or(DEFAULT_INVOICE_EXPIRY),
+        })
+    }
+
+    /// Match webhook requests with `matcher` instead of the exact
+    /// `webhook_path`, e.g. when the app is mounted under a different prefix
+    /// per environment.
+    pub fn with_webhook_path(mut self, matcher: WebhookPathMatcher) -> Self {
+        self.webhook_path = matcher;
+        self
+    }
+
+    /// Expire invoices after `seconds` when [`AddInvoiceRequest::expire`] is
+    /// not set, instead of [`DEFAULT_INVOICE_EXPIRY`].
+    pub fn with_default_expiry_seconds(mut self, seconds: u32) -> Self {
+        self.default_expiry_seconds = seconds;
+        self
+    }
 }
 
 #[async_trait]
-#[cfg_attr(coverage_nightly, coverage(off))]
 impl LightningNode for BitvoraNode {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     async fn add_invoice(&self, req: AddInvoiceRequest) -> anyhow::Result<AddInvoiceResponse> {
         let req = CreateInvoiceRequest {
             amount: req.amount / 1000,
             currency: "sats".to_string(),
             description: req.memo.unwrap_or_default(),
-            expiry_seconds: req.expire.unwrap_or(3600) as u64,
+            expiry_seconds: req.expire.unwrap_or(self.default_expiry_seconds) as u64,
         };
         let rsp: BitvoraResponse<CreateInvoiceResponse> = self
             .api
@@ -85,10 +168,42 @@ impl LightningNode for BitvoraNode {
         )?)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     async fn cancel_invoice(&self, _id: &[u8]) -> anyhow::Result<()> {
         bail!("Not supported yet!")
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn lookup_invoice(&self, payment_hash: &[u8]) -> anyhow::Result<Option<InvoiceUpdate>> {
+        let rsp: BitvoraResponse<LightningInvoiceDetails> = match self
+            .api
+            .req::<_, ()>(
+                Method::GET,
+                &format!(
+                    "/v1/bitcoin/deposit/lightning-invoice/{}",
+                    hex::encode(payment_hash)
+                ),
+                None,
+            )
+            .await
+        {
+            Err(JsonApiError::Http { status: 404, .. }) => return Ok(None),
+            r => r?,
+        };
+        if rsp.status == 404 {
+            return Ok(None);
+        }
+        if rsp.status >= 400 {
+            bail!(
+                "API error: {} {}",
+                rsp.status,
+                rsp.message.unwrap_or_default()
+            );
+        }
+        Ok(Some(rsp.data.into_update()))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     async fn pay_invoice(&self, req: PayInvoiceRequest) -> anyhow::Result<PayInvoiceResponse> {
         let pay_req = SendPaymentRequest {
             payment_request: req.invoice.clone(),
@@ -106,10 +221,7 @@ impl LightningNode for BitvoraNode {
         }
 
         // Parse the invoice to get the payment hash
-        let parsed_invoice: Bolt11Invoice = req
-            .invoice
-            .parse()
-            .map_err(|e| anyhow!("Failed to parse invoice: {}", e))?;
+        let parsed_invoice = parse_invoice(&req.invoice)?;
 
         Ok(PayInvoiceResponse {
             payment_hash: parsed_invoice.payment_hash().encode_hex(),
@@ -119,61 +231,29 @@ impl LightningNode for BitvoraNode {
         })
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     async fn subscribe_invoices(
         &self,
         _from_payment_hash: Option<Vec<u8>>,
     ) -> anyhow::Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>> {
-        let rx = BroadcastStream::new(WEBHOOK_BRIDGE.listen());
+        let rx = WEBHOOK_BRIDGE.listen_path(self.webhook_path.clone());
         let secret = self.webhook_secret.clone();
-        let webhook_path = self.webhook_path.clone();
-        let mapped = rx.filter_map(move |r| {
-            let secret = secret.clone();
-            let webhook_path = webhook_path.clone();
-            async move {
-                match r {
-                    Ok(r) => {
-                        if r.endpoint != webhook_path {
-                            // not being handled here, could be some other webhook event
-                            return None;
-                        }
-                        let r_body = r.body.as_slice();
-                        info!("Received webhook {}", String::from_utf8_lossy(r_body));
-                        let body: BitvoraWebhook = match serde_json::from_slice(r_body) {
-                            Ok(b) => b,
-                            Err(e) => return Some(InvoiceUpdate::Error(e.to_string())),
-                        };
-
-                        if let Err(e) = verify_webhook(&secret, &r) {
-                            return Some(InvoiceUpdate::Error(e.to_string()));
-                        }
-
-                        Some(match body.event {
-                            BitvoraWebhookEvent::DepositLightningComplete => {
-                                match body.data.recipient.parse::<Bolt11Invoice>() {
-                                    Ok(invoice) => InvoiceUpdate::Settled {
-                                        payment_hash: invoice.payment_hash().encode_hex(),
-                                        preimage: None,
-                                        external_id: Some(body.data.lightning_invoice_id),
-                                    },
-                                    Err(e) => InvoiceUpdate::Error(format!(
-                                        "Failed to parse invoice: {}",
-                                        e
-                                    )),
-                                }
-                            }
-                            BitvoraWebhookEvent::DepositLightningFailed => {
-                                InvoiceUpdate::Error("Payment failed".to_string())
-                            }
-                        })
-                    }
-                    Err(e) => {
-                        warn!("Error handling webhook: {}", e);
-                        Some(InvoiceUpdate::Error(e.to_string()))
-                    }
-                }
+        let mapped = rx.map(move |r| {
+            let r_body = r.body.as_slice();
+            info!("Received webhook {}", String::from_utf8_lossy(r_body));
+            let body: BitvoraWebhook = match serde_json::from_slice(r_body) {
+                Ok(b) => b,
+                Err(e) => return InvoiceUpdate::Error(e.to_string()),
+            };
+
+            if let Err(e) = verify_webhook(&secret, &r) {
+                return InvoiceUpdate::Error(e.to_string());
             }
+
+            body.into_update()
         });
-        Ok(Box::pin(mapped))
+        // webhook providers redeliver events, only surface each settlement once
+        Ok(Box::pin(mapped.dedup(DEFAULT_DEDUP_WINDOW)))
     }
 }
 
@@ -199,6 +279,41 @@ struct CreateInvoiceResponse {
     pub payment_request: String,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct LightningInvoiceDetails {
+    pub id: String,
+    pub r_hash: String,
+    pub payment_request: String,
+    pub status: String,
+}
+
+impl LightningInvoiceDetails {
+    /// Map the invoice status onto the matching [`InvoiceUpdate`].
+    fn into_update(self) -> InvoiceUpdate {
+        match self.status.to_lowercase().as_str() {
+            "settled" | "completed" | "paid" => InvoiceUpdate::Settled {
+                payment_hash: self.r_hash,
+                preimage: None,
+                external_id: Some(self.id),
+                payment_request: Some(self.payment_request),
+                settled_at: None,
+                amount_msat: None,
+                memo: None,
+            },
+            "expired" | "canceled" | "cancelled" | "failed" => InvoiceUpdate::Canceled {
+                payment_hash: self.r_hash,
+            },
+            "pending" | "open" => InvoiceUpdate::Created {
+                payment_hash: self.r_hash,
+                payment_request: self.payment_request,
+            },
+            _ => InvoiceUpdate::Unknown {
+                payment_hash: self.r_hash,
+            },
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct SendPaymentRequest {
     pub payment_request: String,
@@ -215,49 +330,94 @@ struct SendPaymentResponse {
 #[derive(Deserialize, Debug, Clone)]
 struct BitvoraWebhook {
     pub event: BitvoraWebhookEvent,
+    /// Only deposit events are guaranteed to carry these fields
+    #[serde(default)]
     pub data: BitvoraPayment,
 }
 
+impl BitvoraWebhook {
+    /// Map the webhook event onto the matching [`InvoiceUpdate`].
+    fn into_update(self) -> InvoiceUpdate {
+        match self.event {
+            BitvoraWebhookEvent::DepositLightningComplete => {
+                match parse_invoice(&self.data.recipient) {
+                    Ok(invoice) => InvoiceUpdate::Settled {
+                        payment_hash: invoice.payment_hash().encode_hex(),
+                        preimage: self.data.preimage.filter(|p| !p.is_empty()),
+                        external_id: Some(self.data.lightning_invoice_id),
+                        payment_request: Some(self.data.recipient),
+                        settled_at: self.data.updated_at,
+                        amount_msat: invoice.amount_milli_satoshis(),
+                        memo: invoice_memo(&invoice),
+                    },
+                    Err(e) => InvoiceUpdate::InvalidInvoice(e),
+                }
+            }
+            BitvoraWebhookEvent::DepositLightningFailed => {
+                InvoiceUpdate::Error("Payment failed".to_string())
+            }
+            // Not an invoice event (e.g. a withdrawal), pass it on without
+            // flagging an error
+            BitvoraWebhookEvent::Unknown => InvoiceUpdate::Unknown {
+                payment_hash: parse_invoice(&self.data.recipient)
+                    .map(|i| i.payment_hash().encode_hex())
+                    .unwrap_or_default(),
+            },
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 enum BitvoraWebhookEvent {
     #[serde(rename = "deposit.lightning.completed")]
     DepositLightningComplete,
     #[serde(rename = "deposit.lightning.failed")]
     DepositLightningFailed,
+    /// Any other event type
+    #[serde(other)]
+    Unknown,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, Default)]
 struct BitvoraPayment {
     #[allow(dead_code)]
+    #[serde(default)]
     pub id: String,
+    #[serde(default)]
     pub lightning_invoice_id: String,
     // the payment request
+    #[serde(default)]
     pub recipient: String,
+    /// Last status change, i.e. the settle time of a completed deposit
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Payment preimage (hex) of a completed deposit, if Bitvora sends it
+    #[serde(default, alias = "payment_preimage")]
+    pub preimage: Option<String>,
+}
+
+impl WebhookVerifier for BitvoraNode {
+    fn verify(&self, msg: &WebhookMessage) -> anyhow::Result<()> {
+        verify_webhook(&self.webhook_secret, msg)
+    }
 }
 
-type HmacSha256 = Hmac<sha2::Sha256>;
 fn verify_webhook(secret: &str, msg: &WebhookMessage) -> anyhow::Result<()> {
     let sig = msg
         .headers
         .get("bitvora-signature")
         .ok_or_else(|| anyhow!("Missing bitvora-signature header"))?;
 
-    let expected = hex::decode(sig).map_err(|_| anyhow!("Invalid signature encoding"))?;
-    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())?;
-    mac.update(msg.body.as_slice());
-
-    if mac.verify_slice(&expected).is_ok() {
-        return Ok(());
-    }
-
-    warn!("Invalid signature found for webhook");
-    bail!("No valid signature found!");
+    verify_hmac_sha256(secret.as_bytes(), &msg.body, sig)
+        .inspect_err(|_| warn!("Invalid signature found for webhook"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use hmac::Mac;
+    use hmac::{Hmac, Mac};
+
+    type HmacSha256 = Hmac<sha2::Sha256>;
     use std::collections::HashMap;
 
     fn create_bitvora_signature(secret: &str, body: &[u8]) -> String {
@@ -271,7 +431,195 @@ mod tests {
     fn test_bitvora_node_new() {
         let node = BitvoraNode::new("test_token", "webhook_secret", "/webhooks/bitvora").unwrap();
         assert_eq!(node.webhook_secret, "webhook_secret");
-        assert_eq!(node.webhook_path, "/webhooks/bitvora");
+        assert!(node.webhook_path.matches("/webhooks/bitvora"));
+
+        let node =
+            node.with_webhook_path(WebhookPathMatcher::Suffix("/webhooks/bitvora".to_string()));
+        assert!(node.webhook_path.matches("/prod/webhooks/bitvora"));
+    }
+
+    #[test]
+    fn test_bitvora_from_config() {
+        let config = |url: Option<&str>| BitvoraConfig {
+            url: url.map(|u| u.to_string()),
+            api_token: "test_token".to_string(),
+            webhook_secret: "secret".to_string(),
+            webhook_path: "/webhooks/bitvora".to_string(),
+            allow_invalid_certs: true,
+            default_expiry_seconds: None,
+        };
+        assert!(BitvoraNode::from_config(config(None)).is_ok());
+        assert!(BitvoraNode::from_config(config(Some("https://sandbox.bitvora.test/"))).is_ok());
+
+        let err = BitvoraNode::from_config(config(Some("not a url")))
+            .err()
+            .expect("invalid url is an error");
+        assert!(err.to_string().contains("not a url"), "{}", err);
+        let mut bad_token = config(None);
+        bad_token.api_token = "bad\ntoken".to_string();
+        assert!(BitvoraNode::from_config(bad_token).is_err());
+
+        let parsed: BitvoraConfig = serde_json::from_str(
+            r#"{"api-token":"t","webhook-secret":"s","webhook-path":"/hook"}"#,
+        )
+        .unwrap();
+        assert!(parsed.url.is_none());
+        assert!(!parsed.allow_invalid_certs);
+    }
+
+    #[tokio::test]
+    async fn test_bitvora_add_invoice() {
+        use crate::lightning::test_util::signed_invoice;
+        use wiremock::matchers::{body_json, header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let invoice = signed_invoice(21_000, "coffee", 7).to_string();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/bitcoin/deposit/lightning-invoice"))
+            .and(header("authorization", "Bearer test_token"))
+            .and(body_json(serde_json::json!({
+                "amount": 21,
+                "currency": "sats",
+                "description": "coffee",
+                "expiry_seconds": 600
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "status": 200,
+                "message": "ok",
+                "data": {"id": "inv_1", "r_hash": hex::encode([7; 32]), "payment_request": invoice}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let node =
+            BitvoraNode::with_base_url(&server.uri(), "test_token", "secret", "/webhooks/bitvora")
+                .unwrap();
+        let rsp = node
+            .add_invoice(AddInvoiceRequest {
+                amount: 21_000,
+                memo: Some("coffee".to_string()),
+                expire: Some(600),
+                idempotency_key: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(rsp.pr(), invoice);
+        assert_eq!(rsp.external_id.as_deref(), Some("inv_1"));
+        assert_eq!(rsp.payment_hash(), hex::encode([7; 32]));
+    }
+
+    #[tokio::test]
+    async fn test_bitvora_add_invoice_error() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/bitcoin/deposit/lightning-invoice"))
+            .respond_with(ResponseTemplate::new(401).set_body_json(serde_json::json!({
+                "status": 401,
+                "message": "invalid api key"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let node = BitvoraNode::with_base_url(&server.uri(), "bad", "secret", "/webhooks/bitvora")
+            .unwrap();
+        let err = node
+            .add_invoice(AddInvoiceRequest {
+                amount: 1_000,
+                memo: None,
+                expire: None,
+                idempotency_key: None,
+            })
+            .await
+            .unwrap_err();
+        let err = err.downcast_ref::<JsonApiError>().expect("json api error");
+        assert_eq!(err.status(), Some(401));
+    }
+
+    #[tokio::test]
+    async fn test_bitvora_lookup_invoice() {
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let details = |id: &str, hash: [u8; 32], status: &str| {
+            serde_json::json!({
+                "status": 200,
+                "message": "ok",
+                "data": {
+                    "id": id,
+                    "r_hash": hex::encode(hash),
+                    "payment_request": format!("lnbc_{}", id),
+                    "status": status
+                }
+            })
+        };
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/v1/bitcoin/deposit/lightning-invoice/{}",
+                hex::encode([1; 32])
+            )))
+            .and(header("authorization", "Bearer test_token"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(details("inv_1", [1; 32], "pending")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/v1/bitcoin/deposit/lightning-invoice/{}",
+                hex::encode([2; 32])
+            )))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(details("inv_2", [2; 32], "settled")),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(format!(
+                "/v1/bitcoin/deposit/lightning-invoice/{}",
+                hex::encode([3; 32])
+            )))
+            .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+                "status": 404,
+                "message": "not found"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let node =
+            BitvoraNode::with_base_url(&server.uri(), "test_token", "secret", "/webhooks/bitvora")
+                .unwrap();
+        match node.lookup_invoice(&[1; 32]).await.unwrap() {
+            Some(InvoiceUpdate::Created {
+                payment_hash,
+                payment_request,
+            }) => {
+                assert_eq!(payment_hash, hex::encode([1; 32]));
+                assert_eq!(payment_request, "lnbc_inv_1");
+            }
+            u => panic!("unexpected update {:?}", u),
+        }
+        match node.lookup_invoice(&[2; 32]).await.unwrap() {
+            Some(InvoiceUpdate::Settled {
+                payment_hash,
+                external_id,
+                ..
+            }) => {
+                assert_eq!(payment_hash, hex::encode([2; 32]));
+                assert_eq!(external_id.as_deref(), Some("inv_2"));
+            }
+            u => panic!("unexpected update {:?}", u),
+        }
+        assert!(node.lookup_invoice(&[3; 32]).await.unwrap().is_none());
     }
 
     #[test]
@@ -282,6 +630,7 @@ mod tests {
 
         let msg = WebhookMessage {
             endpoint: "/webhooks/bitvora".to_string(),
+            query: None,
             body: body.to_vec(),
             headers: HashMap::from([("bitvora-signature".to_string(), signature)]),
         };
@@ -294,6 +643,7 @@ mod tests {
     fn test_verify_webhook_missing_signature() {
         let msg = WebhookMessage {
             endpoint: "/webhooks/bitvora".to_string(),
+            query: None,
             body: b"test".to_vec(),
             headers: HashMap::new(),
         };
@@ -308,10 +658,24 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_verify_webhook_malformed_hex() {
+        let msg = WebhookMessage {
+            endpoint: "/webhooks/bitvora".to_string(),
+            query: None,
+            body: b"test".to_vec(),
+            headers: HashMap::from([("bitvora-signature".to_string(), "zz-not-hex".to_string())]),
+        };
+
+        let err = verify_webhook("secret", &msg).unwrap_err();
+        assert!(err.to_string().contains("Invalid signature encoding"));
+    }
+
     #[test]
     fn test_verify_webhook_invalid_signature() {
         let msg = WebhookMessage {
             endpoint: "/webhooks/bitvora".to_string(),
+            query: None,
             body: b"test".to_vec(),
             headers: HashMap::from([("bitvora-signature".to_string(), "00".to_string())]),
         };
@@ -326,6 +690,138 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_lightning_invoice_details_into_update() {
+        let details: LightningInvoiceDetails = serde_json::from_str(
+            r#"{"id":"inv_1","r_hash":"abc123","payment_request":"lnbc...","status":"settled"}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            details.clone().into_update(),
+            InvoiceUpdate::Settled { payment_hash, external_id, .. }
+                if payment_hash == "abc123" && external_id.as_deref() == Some("inv_1")
+        ));
+
+        let pending = LightningInvoiceDetails {
+            status: "pending".to_string(),
+            ..details.clone()
+        };
+        assert!(matches!(
+            pending.into_update(),
+            InvoiceUpdate::Created { .. }
+        ));
+
+        let expired = LightningInvoiceDetails {
+            status: "expired".to_string(),
+            ..details
+        };
+        assert!(matches!(
+            expired.into_update(),
+            InvoiceUpdate::Canceled { .. }
+        ));
+    }
+
+    #[test]
+    fn test_bitvora_webhook_verifier_trait() {
+        let node = BitvoraNode::new("test_token", "test_secret", "/webhooks/bitvora").unwrap();
+        let body = br#"{"event":"deposit.lightning.completed"}"#;
+        let mut msg = WebhookMessage {
+            endpoint: "/webhooks/bitvora".to_string(),
+            query: None,
+            body: body.to_vec(),
+            headers: HashMap::from([(
+                "bitvora-signature".to_string(),
+                create_bitvora_signature("test_secret", body),
+            )]),
+        };
+        let verifier: &dyn WebhookVerifier = &node;
+        assert!(verifier.verify(&msg).is_ok());
+
+        msg.body = br#"{"event":"deposit.lightning.failed"}"#.to_vec();
+        assert!(verifier.verify(&msg).is_err());
+    }
+
+    #[test]
+    fn test_bitvora_webhook_settled_at() {
+        let recipient = crate::lightning::test_util::signed_invoice(1_000, "x", 3).to_string();
+        let webhook: BitvoraWebhook = serde_json::from_value(serde_json::json!({
+            "event": "deposit.lightning.completed",
+            "data": {
+                "id": "pay_1",
+                "lightning_invoice_id": "inv_1",
+                "recipient": recipient,
+                "updated_at": "2024-01-02T03:04:05Z",
+            }
+        }))
+        .unwrap();
+        let InvoiceUpdate::Settled {
+            settled_at,
+            amount_msat,
+            memo,
+            ..
+        } = webhook.into_update()
+        else {
+            panic!("Expected Settled variant");
+        };
+        assert_eq!(amount_msat, Some(1_000));
+        assert_eq!(memo.as_deref(), Some("x"));
+        assert_eq!(
+            settled_at,
+            Some("2024-01-02T03:04:05Z".parse::<DateTime<Utc>>().unwrap())
+        );
+    }
+
+    #[test]
+    fn test_bitvora_webhook_preimage() {
+        let recipient = crate::lightning::test_util::signed_invoice(1_000, "x", 3).to_string();
+        let preimage = hex::encode([0xab; 32]);
+        let webhook: BitvoraWebhook = serde_json::from_value(serde_json::json!({
+            "event": "deposit.lightning.completed",
+            "data": {
+                "id": "pay_1",
+                "lightning_invoice_id": "inv_1",
+                "recipient": recipient,
+                "preimage": preimage,
+            }
+        }))
+        .unwrap();
+        let InvoiceUpdate::Settled {
+            preimage: settled, ..
+        } = webhook.clone().into_update()
+        else {
+            panic!("Expected Settled variant");
+        };
+        assert_eq!(settled, Some(preimage));
+
+        let mut without = webhook;
+        without.data.preimage = Some(String::new());
+        let InvoiceUpdate::Settled { preimage, .. } = without.into_update() else {
+            panic!("Expected Settled variant");
+        };
+        assert_eq!(preimage, None);
+    }
+
+    #[test]
+    fn test_bitvora_webhook_malformed_invoice() {
+        let recipient = format!("lnbc1{}", "q".repeat(100));
+        let webhook: BitvoraWebhook = serde_json::from_value(serde_json::json!({
+            "event": "deposit.lightning.completed",
+            "data": {
+                "id": "pay_1",
+                "lightning_invoice_id": "inv_1",
+                "recipient": recipient,
+            }
+        }))
+        .unwrap();
+        let InvoiceUpdate::InvalidInvoice(err) = webhook.into_update() else {
+            panic!("Expected InvalidInvoice variant");
+        };
+        assert_eq!(
+            err.input,
+            format!("{}...", &recipient[..InvalidInvoice::MAX_INPUT_LEN])
+        );
+    }
+
     #[test]
     fn test_bitvora_webhook_event_serde() {
         let json = r#""deposit.lightning.completed""#;
@@ -338,5 +834,30 @@ mod tests {
         let json = r#""deposit.lightning.failed""#;
         let event: BitvoraWebhookEvent = serde_json::from_str(json).unwrap();
         assert!(matches!(event, BitvoraWebhookEvent::DepositLightningFailed));
+
+        let json = r#""withdrawal.lightning.completed""#;
+        let event: BitvoraWebhookEvent = serde_json::from_str(json).unwrap();
+        assert!(matches!(event, BitvoraWebhookEvent::Unknown));
+    }
+
+    #[test]
+    fn test_bitvora_webhook_unknown_event() {
+        let webhook: BitvoraWebhook = serde_json::from_str(
+            r#"{"event":"deposit.onchain.completed","data":{"id":"dep_1","txid":"abc","amount":1000}}"#,
+        )
+        .unwrap();
+        let update = webhook.into_update();
+        assert!(
+            matches!(&update, InvoiceUpdate::Unknown { payment_hash } if payment_hash.is_empty()),
+            "{:?}",
+            update
+        );
+
+        let webhook: BitvoraWebhook =
+            serde_json::from_str(r#"{"event":"api.key.rotated"}"#).unwrap();
+        assert!(matches!(
+            webhook.into_update(),
+            InvoiceUpdate::Unknown { .. }
+        ));
     }
 }
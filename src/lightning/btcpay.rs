@@ -0,0 +1,466 @@
+//! BTCPay Server Lightning integration via the Greenfield API.
+//!
+//! Uses the Lightning node connected to a store
+//! (`/api/v1/stores/{storeId}/lightning/{cryptoCode}`), authenticated with a
+//! Greenfield API key that has the `btcpay.store.cancreatelightninginvoice`
+//! (and for payments `btcpay.store.canuselightningnode`) permission.
+//!
+//! BTCPay only sends webhooks for store invoices, not for invoices of the
+//! Lightning node, so [`LightningNode::subscribe_invoices`] polls the invoices
+//! created by this client until they are paid or expire.
+
+use crate::json_api::{JsonApi, JsonApiError};
+use crate::lightning::{
+    AddInvoiceRequest, AddInvoiceResponse, DEFAULT_INVOICE_EXPIRY, InvoiceUpdate, LightningNode,
+    PayInvoiceRequest, PayInvoiceResponse, parse_invoice,
+};
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use hex::ToHex;
+use log::warn;
+use reqwest::Method;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Default interval between polls of open invoices
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// BTCPay Server Lightning node client.
+#[derive(Clone)]
+pub struct BtcPayNode {
+    api: JsonApi,
+    store_id: String,
+    crypto_code: String,
+    poll_interval: Duration,
+    /// Ids of invoices created by this client which are not paid or expired yet
+    open_invoices: Arc<Mutex<HashSet<String>>>,
+    /// Invoice expiry in seconds when a request sets none
+    default_expiry_seconds: u32,
+}
+
+/// Configuration of a [`BtcPayNode`].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BtcPayConfig {
+    /// Base url of the BTCPay Server, e.g. `https://btcpay.example.com/`
+    pub url: String,
+    /// Greenfield API key
+    pub api_key: String,
+    /// Id of the store whose Lightning node is used
+    pub store_id: String,
+    /// Crypto code of the Lightning network, defaults to `BTC`
+    pub crypto_code: Option<String>,
+    /// Accept invalid TLS certificates, e.g. of a self-signed instance
+    #[serde(default)]
+    pub allow_invalid_certs: bool,
+    /// Invoice expiry in seconds when a request sets none, defaults to
+    /// [`DEFAULT_INVOICE_EXPIRY`](crate::lightning::DEFAULT_INVOICE_EXPIRY)
+    #[serde(default)]
+    pub default_expiry_seconds: Option<u32>,
+}
+
+impl BtcPayNode {
+    /// Create a new BTCPay Server node client.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - Base url of the BTCPay Server
+    /// * `api_key` - Greenfield API key
+    /// * `store_id` - Id of the store whose Lightning node is used
+    pub fn new(url: &str, api_key: &str, store_id: &str) -> Result<Self> {
+        Self::from_config(BtcPayConfig {
+            url: url.to_string(),
+            api_key: api_key.to_string(),
+            store_id: store_id.to_string(),
+            crypto_code: None,
+            allow_invalid_certs: false,
+            default_expiry_seconds: None,
+        })
+    }
+
+    /// Create a new BTCPay Server node client from `config`.
+    ///
+    /// Fails if the url or API key are invalid.
+    pub fn from_config(config: BtcPayConfig) -> Result<Self> {
+        let auth = format!("token {}", config.api_key);
+        Ok(Self {
+            api: JsonApi::token(&config.url, &auth, config.allow_invalid_certs)
+                .map_err(|e| anyhow!("Invalid BTCPay config {}: {}", config.url, e))?,
+            store_id: config.store_id,
+            crypto_code: config.crypto_code.unwrap_or_else(|| "BTC".to_string()),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            open_invoices: Arc::new(Mutex::new(HashSet::new())),
+            default_expiry_seconds: config
+                .default_expiry_seconds
+                .unwrap_or(DEFAULT_INVOICE_EXPIRY),
+        })
+    }
+
+    /// Poll open invoices every `interval` instead of every 5 seconds
+    pub fn with_poll_interval(mut self, interval: Duration) -> Self {
+        self.poll_interval = interval;
+        self
+    }
+
+    /// Expire invoices after `seconds` when [`AddInvoiceRequest::expire`] is
+    /// not set, instead of [`DEFAULT_INVOICE_EXPIRY`].
+    pub fn with_default_expiry_seconds(mut self, seconds: u32) -> Self {
+        self.default_expiry_seconds = seconds;
+        self
+    }
+
+    fn path(&self, path: &str) -> String {
+        format!(
+            "/api/v1/stores/{}/lightning/{}{}",
+            self.store_id, self.crypto_code, path
+        )
+    }
+
+    /// Get an invoice by its BTCPay id, `None` if it doesn't exist
+    async fn get_invoice(&self, id: &str) -> Result<Option<BtcPayLightningInvoice>> {
+        match self
+            .api
+            .req::<_, ()>(Method::GET, &self.path(&format!("/invoices/{}", id)), None)
+            .await
+        {
+            Err(JsonApiError::Http { status: 404, .. }) => Ok(None),
+            r => Ok(Some(r?)),
+        }
+    }
+
+    /// Check every open invoice once, returning those which are now final
+    async fn poll_open_invoices(&self) -> Vec<InvoiceUpdate> {
+        let ids: Vec<String> = self.open_invoices.lock().unwrap().iter().cloned().collect();
+        let mut updates = vec![];
+        for id in ids {
+            let update = match self.get_invoice(&id).await {
+                Ok(Some(invoice)) => invoice.into_update(),
+                Ok(None) => InvoiceUpdate::Error(format!("Invoice {} not found", id)),
+                Err(e) => {
+                    warn!("Failed to poll BTCPay invoice {}: {}", id, e);
+                    continue;
+                }
+            };
+            if !matches!(update, InvoiceUpdate::Created { .. }) {
+                self.open_invoices.lock().unwrap().remove(&id);
+                updates.push(update);
+            }
+        }
+        updates
+    }
+}
+
+#[async_trait]
+impl LightningNode for BtcPayNode {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn add_invoice(&self, req: AddInvoiceRequest) -> Result<AddInvoiceResponse> {
+        let body = CreateLightningInvoiceRequest {
+            amount: req.amount.to_string(),
+            description: req.memo,
+            description_hash_only: false,
+            expiry: req.expire.unwrap_or(self.default_expiry_seconds),
+        };
+        let invoice: BtcPayLightningInvoice = self.api.post(&self.path("/invoices"), body).await?;
+        let rsp = AddInvoiceResponse::from_invoice(&invoice.bolt11, Some(invoice.id.clone()))?;
+        self.open_invoices.lock().unwrap().insert(invoice.id);
+        Ok(rsp)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn cancel_invoice(&self, _id: &[u8]) -> Result<()> {
+        bail!("BTCPay Server does not support canceling Lightning invoices")
+    }
+
+    /// BTCPay uses the payment hash as the invoice id for LND and Core
+    /// Lightning nodes, which this relies on.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn lookup_invoice(&self, payment_hash: &[u8]) -> Result<Option<InvoiceUpdate>> {
+        Ok(self
+            .get_invoice(&hex::encode(payment_hash))
+            .await?
+            .map(BtcPayLightningInvoice::into_update))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn pay_invoice(&self, req: PayInvoiceRequest) -> Result<PayInvoiceResponse> {
+        let parsed_invoice = parse_invoice(&req.invoice)?;
+        let rsp: BtcPayLightningPayment = self
+            .api
+            .post(
+                &self.path("/invoices/pay"),
+                PayLightningInvoiceRequest {
+                    bolt11: req.invoice,
+                    send_timeout: req.timeout_seconds,
+                },
+            )
+            .await?;
+        if rsp.status.eq_ignore_ascii_case("failed") {
+            bail!("Payment failed");
+        }
+        Ok(PayInvoiceResponse {
+            payment_hash: rsp
+                .payment_hash
+                .unwrap_or_else(|| parsed_invoice.payment_hash().encode_hex()),
+            payment_preimage: rsp.preimage,
+            amount_msat: rsp
+                .total_amount
+                .or(parsed_invoice.amount_milli_satoshis())
+                .unwrap_or_default(),
+            fee_msat: rsp.fee_amount.unwrap_or_default(),
+        })
+    }
+
+    /// Updates for invoices created by this client (and its clones), polled
+    /// every [`BtcPayNode::with_poll_interval`]. Each invoice is reported
+    /// once, when it is paid or expires; `from_payment_hash` is ignored.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn subscribe_invoices(
+        &self,
+        _from_payment_hash: Option<Vec<u8>>,
+    ) -> Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>> {
+        let node = self.clone();
+        let updates = futures::stream::unfold(node, |node| async move {
+            tokio::time::sleep(node.poll_interval).await;
+            let updates = node.poll_open_invoices().await;
+            Some((futures::stream::iter(updates), node))
+        })
+        .flatten();
+        Ok(Box::pin(updates))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateLightningInvoiceRequest {
+    /// Amount in milli-satoshis
+    pub amount: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub description_hash_only: bool,
+    /// Expiry in seconds
+    pub expiry: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PayLightningInvoiceRequest {
+    #[serde(rename = "BOLT11")]
+    pub bolt11: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_timeout: Option<u32>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BtcPayLightningInvoice {
+    pub id: String,
+    /// `Unpaid`, `Paid` or `Expired`
+    pub status: String,
+    #[serde(rename = "BOLT11")]
+    pub bolt11: String,
+    pub payment_hash: Option<String>,
+    pub preimage: Option<String>,
+    /// Unix timestamp (seconds)
+    pub paid_at: Option<i64>,
+}
+
+impl BtcPayLightningInvoice {
+    /// Map the invoice status onto the matching [`InvoiceUpdate`].
+    fn into_update(self) -> InvoiceUpdate {
+        let payment_hash = match self.payment_hash {
+            Some(h) => h,
+            None => match parse_invoice(&self.bolt11) {
+                Ok(i) => i.payment_hash().encode_hex(),
+                Err(e) => return InvoiceUpdate::InvalidInvoice(e),
+            },
+        };
+        match self.status.to_lowercase().as_str() {
+            "paid" | "settled" => InvoiceUpdate::Settled {
+                payment_hash,
+                preimage: self.preimage,
+                external_id: Some(self.id),
+                payment_request: Some(self.bolt11),
+                settled_at: self
+                    .paid_at
+                    .and_then(|t| DateTime::<Utc>::from_timestamp(t, 0)),
+                amount_msat: None,
+                memo: None,
+            },
+            "expired" => InvoiceUpdate::Canceled { payment_hash },
+            "unpaid" => InvoiceUpdate::Created {
+                payment_hash,
+                payment_request: self.bolt11,
+            },
+            _ => InvoiceUpdate::Unknown { payment_hash },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BtcPayLightningPayment {
+    /// `Pending`, `Complete` or `Failed`
+    pub status: String,
+    pub payment_hash: Option<String>,
+    pub preimage: Option<String>,
+    #[serde(default, deserialize_with = "msat")]
+    pub total_amount: Option<u64>,
+    #[serde(default, deserialize_with = "msat")]
+    pub fee_amount: Option<u64>,
+}
+
+/// Greenfield encodes milli-satoshi amounts as strings
+fn msat<'de, D: Deserializer<'de>>(d: D) -> Result<Option<u64>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Amount {
+        Str(String),
+        Num(u64),
+    }
+    match Option::<Amount>::deserialize(d)? {
+        None => Ok(None),
+        Some(Amount::Num(n)) => Ok(Some(n)),
+        Some(Amount::Str(s)) => s.parse().map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lightning::test_util::signed_invoice;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const INVOICES: &str = "/api/v1/stores/store1/lightning/BTC/invoices";
+
+    #[tokio::test]
+    async fn test_btcpay_add_invoice() {
+        let invoice = signed_invoice(21_000, "coffee", 7).to_string();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path(INVOICES))
+            .and(header("authorization", "token key1"))
+            .and(body_json(serde_json::json!({
+                "amount": "21000",
+                "description": "coffee",
+                "descriptionHashOnly": false,
+                "expiry": 600
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "inv_1",
+                "status": "Unpaid",
+                "BOLT11": invoice,
+                "amount": "21000"
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let node = BtcPayNode::new(&server.uri(), "key1", "store1").unwrap();
+        let rsp = node
+            .add_invoice(AddInvoiceRequest {
+                amount: 21_000,
+                memo: Some("coffee".to_string()),
+                expire: Some(600),
+                idempotency_key: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(rsp.external_id.as_deref(), Some("inv_1"));
+        assert_eq!(rsp.pr(), invoice);
+        assert!(node.open_invoices.lock().unwrap().contains("inv_1"));
+    }
+
+    #[test]
+    fn test_btcpay_invoice_status() {
+        let invoice = signed_invoice(21_000, "coffee", 7).to_string();
+        let parse = |status: &str| -> BtcPayLightningInvoice {
+            serde_json::from_value(serde_json::json!({
+                "id": "inv_1",
+                "status": status,
+                "BOLT11": invoice,
+                "preimage": "ab".repeat(32),
+                "paidAt": 1_700_000_000
+            }))
+            .unwrap()
+        };
+
+        match parse("Paid").into_update() {
+            InvoiceUpdate::Settled {
+                payment_hash,
+                preimage,
+                external_id,
+                settled_at,
+                ..
+            } => {
+                assert_eq!(payment_hash, "07".repeat(32));
+                assert_eq!(preimage, Some("ab".repeat(32)));
+                assert_eq!(external_id.as_deref(), Some("inv_1"));
+                assert_eq!(settled_at.unwrap().timestamp(), 1_700_000_000);
+            }
+            u => panic!("unexpected update {:?}", u),
+        }
+        assert!(matches!(
+            parse("Settled").into_update(),
+            InvoiceUpdate::Settled { .. }
+        ));
+        assert!(matches!(
+            parse("Expired").into_update(),
+            InvoiceUpdate::Canceled { .. }
+        ));
+        assert!(matches!(
+            parse("Unpaid").into_update(),
+            InvoiceUpdate::Created { .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_btcpay_subscribe_polls_open_invoices() {
+        let invoice = signed_invoice(21_000, "coffee", 7).to_string();
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/inv_1", INVOICES)))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "id": "inv_1",
+                "status": "Paid",
+                "BOLT11": invoice
+            })))
+            .mount(&server)
+            .await;
+
+        let node = BtcPayNode::new(&server.uri(), "key1", "store1")
+            .unwrap()
+            .with_poll_interval(Duration::from_millis(10));
+        node.open_invoices
+            .lock()
+            .unwrap()
+            .insert("inv_1".to_string());
+
+        let mut updates = node.subscribe_invoices(None).await.unwrap();
+        let update = tokio::time::timeout(Duration::from_secs(5), updates.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(update, InvoiceUpdate::Settled { .. }));
+        // reported once, then no longer polled
+        assert!(node.open_invoices.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_btcpay_lookup_invoice_not_found() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(format!("{}/{}", INVOICES, "07".repeat(32))))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let node = BtcPayNode::new(&server.uri(), "key1", "store1").unwrap();
+        assert!(node.lookup_invoice(&[7; 32]).await.unwrap().is_none());
+    }
+}
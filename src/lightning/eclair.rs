@@ -0,0 +1,486 @@
+//! Eclair Lightning node integration.
+//!
+//! Uses Eclair's HTTP API (form encoded requests, password only basic auth)
+//! and the `/ws` WebSocket event stream for invoice updates.
+
+use crate::json_api::{JsonApi, JsonApiError};
+use crate::lightning::{
+    AddInvoiceRequest, AddInvoiceResponse, DEFAULT_INVOICE_EXPIRY, InvoiceUpdate, LightningNode,
+    PayInvoiceRequest, PayInvoiceResponse, ws_invoice_stream_with_tls,
+};
+use anyhow::{Result, anyhow, bail};
+use async_trait::async_trait;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::{DateTime, Utc};
+use futures::Stream;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::pin::Pin;
+
+/// Eclair Lightning node client.
+#[derive(Clone)]
+pub struct EclairNode {
+    api: JsonApi,
+    auth: String,
+    /// Accept invalid TLS certificates on the event stream too
+    allow_invalid_certs: bool,
+    /// Invoice expiry in seconds when a request sets none
+    default_expiry_seconds: u32,
+}
+
+/// Configuration of an [`EclairNode`].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct EclairConfig {
+    /// API url, e.g. `http://localhost:8080/`
+    pub url: String,
+    /// API password (`eclair.api.password`)
+    pub password: String,
+    /// Accept invalid TLS certificates, e.g. of a self-signed proxy
+    #[serde(default)]
+    pub allow_invalid_certs: bool,
+    /// Invoice expiry in seconds when a request sets none, defaults to
+    /// [`DEFAULT_INVOICE_EXPIRY`](crate::lightning::DEFAULT_INVOICE_EXPIRY)
+    #[serde(default)]
+    pub default_expiry_seconds: Option<u32>,
+}
+
+impl EclairNode {
+    /// Create a new Eclair node client.
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - API url, e.g. `http://localhost:8080/`
+    /// * `password` - API password
+    pub fn new(url: &str, password: &str) -> Result<Self> {
+        Self::from_config(EclairConfig {
+            url: url.to_string(),
+            password: password.to_string(),
+            allow_invalid_certs: false,
+            default_expiry_seconds: None,
+        })
+    }
+
+    /// Create a new Eclair node client from `config`.
+    ///
+    /// Fails if the url or password are invalid.
+    pub fn from_config(config: EclairConfig) -> Result<Self> {
+        // Eclair only checks the password, the user name is empty
+        let auth = format!("Basic {}", BASE64.encode(format!(":{}", config.password)));
+        Ok(Self {
+            api: JsonApi::token(&config.url, &auth, config.allow_invalid_certs)
+                .map_err(|e| anyhow!("Invalid Eclair config {}: {}", config.url, e))?
+                .with_form_body(true),
+            auth,
+            allow_invalid_certs: config.allow_invalid_certs,
+            default_expiry_seconds: config
+                .default_expiry_seconds
+                .unwrap_or(DEFAULT_INVOICE_EXPIRY),
+        })
+    }
+}
+
+#[async_trait]
+impl LightningNode for EclairNode {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn add_invoice(&self, req: AddInvoiceRequest) -> Result<AddInvoiceResponse> {
+        let invoice: EclairInvoice = self
+            .api
+            .post(
+                "/createinvoice",
+                CreateInvoiceRequest {
+                    description: req.memo.unwrap_or_default(),
+                    amount_msat: req.amount,
+                    expire_in: req.expire.unwrap_or(self.default_expiry_seconds),
+                },
+            )
+            .await?;
+        AddInvoiceResponse::from_invoice(&invoice.serialized, None)
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn cancel_invoice(&self, id: &[u8]) -> Result<()> {
+        let _: serde_json::Value = self
+            .api
+            .post(
+                "/deleteinvoice",
+                PaymentHashRequest {
+                    payment_hash: hex::encode(id),
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn lookup_invoice(&self, payment_hash: &[u8]) -> Result<Option<InvoiceUpdate>> {
+        let payment_hash = hex::encode(payment_hash);
+        let info: EclairReceivedInfo = match self
+            .api
+            .req(
+                reqwest::Method::POST,
+                "/getreceivedinfo",
+                Some(PaymentHashRequest {
+                    payment_hash: payment_hash.clone(),
+                }),
+            )
+            .await
+        {
+            Err(JsonApiError::Http { status: 404, .. }) => return Ok(None),
+            r => r?,
+        };
+        Ok(Some(info.into_update(payment_hash)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn pay_invoice(&self, req: PayInvoiceRequest) -> Result<PayInvoiceResponse> {
+        let event: EclairEvent = self
+            .api
+            .post(
+                "/payinvoice",
+                PayRequest {
+                    invoice: req.invoice,
+                    blocking: true,
+                },
+            )
+            .await?;
+        match event {
+            EclairEvent::PaymentSent {
+                payment_hash,
+                payment_preimage,
+                recipient_amount,
+                parts,
+            } => Ok(PayInvoiceResponse {
+                payment_hash,
+                payment_preimage: Some(payment_preimage),
+                amount_msat: recipient_amount,
+                fee_msat: parts.iter().map(|p| p.fees_paid).sum(),
+            }),
+            EclairEvent::PaymentFailed { payment_hash } => {
+                bail!("Payment {} failed", payment_hash)
+            }
+            _ => bail!("Unexpected payment result"),
+        }
+    }
+
+    /// Updates from the `/ws` event stream, which only carries new events:
+    /// `from_payment_hash` is ignored and events sent while reconnecting are
+    /// missed.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn subscribe_invoices(
+        &self,
+        _from_payment_hash: Option<Vec<u8>>,
+    ) -> Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>> {
+        ws_invoice_stream_with_tls(
+            self.api.base().join("ws")?,
+            Some(self.auth.clone()),
+            self.allow_invalid_certs,
+            |msg| match serde_json::from_str::<EclairEvent>(msg) {
+                Ok(event) => event.into_update(),
+                Err(e) => {
+                    warn!("Invalid Eclair event {}: {}", msg, e);
+                    None
+                }
+            },
+        )
+        .await
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateInvoiceRequest {
+    pub description: String,
+    pub amount_msat: u64,
+    /// Expiry in seconds
+    pub expire_in: u32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PaymentHashRequest {
+    pub payment_hash: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PayRequest {
+    pub invoice: String,
+    /// Wait for the payment to succeed or fail
+    pub blocking: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct EclairInvoice {
+    /// The BOLT11 invoice
+    pub serialized: String,
+}
+
+/// Eclair timestamps are `{"iso": .., "unix": ..}` objects, older versions
+/// send unix milliseconds
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum EclairTimestamp {
+    Object { unix: i64 },
+    Millis(i64),
+}
+
+impl EclairTimestamp {
+    fn to_datetime(&self) -> Option<DateTime<Utc>> {
+        match self {
+            EclairTimestamp::Object { unix } => DateTime::from_timestamp(*unix, 0),
+            EclairTimestamp::Millis(ms) => DateTime::from_timestamp_millis(*ms),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EclairReceivedPart {
+    pub amount: u64,
+    pub timestamp: Option<EclairTimestamp>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EclairSentPart {
+    #[serde(default)]
+    pub fees_paid: u64,
+}
+
+/// Events of the `/ws` stream (and the result of a blocking `/payinvoice`)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum EclairEvent {
+    PaymentReceived {
+        #[serde(rename = "paymentHash")]
+        payment_hash: String,
+        #[serde(default)]
+        parts: Vec<EclairReceivedPart>,
+    },
+    PaymentSent {
+        #[serde(rename = "paymentHash")]
+        payment_hash: String,
+        #[serde(rename = "paymentPreimage")]
+        payment_preimage: String,
+        #[serde(rename = "recipientAmount")]
+        recipient_amount: u64,
+        #[serde(default)]
+        parts: Vec<EclairSentPart>,
+    },
+    PaymentFailed {
+        #[serde(rename = "paymentHash")]
+        payment_hash: String,
+    },
+    /// Relayed payments, channel events etc.
+    #[serde(other)]
+    Other,
+}
+
+impl EclairEvent {
+    /// The invoice update of a `payment-received` event, other events are not
+    /// about our invoices
+    fn into_update(self) -> Option<InvoiceUpdate> {
+        match self {
+            EclairEvent::PaymentReceived {
+                payment_hash,
+                parts,
+            } => Some(InvoiceUpdate::Settled {
+                payment_hash,
+                preimage: None,
+                external_id: None,
+                payment_request: None,
+                // a multi-part payment completes with its last part
+                settled_at: parts
+                    .iter()
+                    .filter_map(|p| p.timestamp.as_ref()?.to_datetime())
+                    .max(),
+                amount_msat: (!parts.is_empty()).then(|| parts.iter().map(|p| p.amount).sum()),
+                memo: None,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EclairReceivedInfo {
+    pub invoice: Option<EclairInvoice>,
+    /// Older versions call the invoice `paymentRequest`
+    pub payment_request: Option<EclairInvoice>,
+    pub payment_preimage: Option<String>,
+    pub status: EclairReceivedStatus,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct EclairReceivedStatus {
+    /// `pending`, `expired` or `received`
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub received_at: Option<EclairTimestamp>,
+}
+
+impl EclairReceivedInfo {
+    /// Map the received info onto the matching [`InvoiceUpdate`].
+    fn into_update(self, payment_hash: String) -> InvoiceUpdate {
+        let payment_request = self.invoice.or(self.payment_request).map(|i| i.serialized);
+        match self.status.kind.as_str() {
+            "received" => InvoiceUpdate::Settled {
+                payment_hash,
+                preimage: self.payment_preimage,
+                external_id: None,
+                payment_request,
+                settled_at: self.status.received_at.and_then(|t| t.to_datetime()),
+                amount_msat: None,
+                memo: None,
+            },
+            "expired" => InvoiceUpdate::Canceled { payment_hash },
+            "pending" => match payment_request {
+                Some(payment_request) => InvoiceUpdate::Created {
+                    payment_hash,
+                    payment_request,
+                },
+                None => InvoiceUpdate::Unknown { payment_hash },
+            },
+            _ => InvoiceUpdate::Unknown { payment_hash },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lightning::test_util::signed_invoice;
+
+    #[test]
+    fn test_eclair_payment_received_event() {
+        let event: EclairEvent = serde_json::from_str(
+            r#"{
+                "type": "payment-received",
+                "paymentHash": "0707070707070707070707070707070707070707070707070707070707070707",
+                "parts": [
+                    {"amount": 15000, "fromChannelId": "c1", "timestamp": {"iso": "2023-11-14T22:13:20Z", "unix": 1700000000}},
+                    {"amount": 6000, "fromChannelId": "c2", "timestamp": {"iso": "2023-11-14T22:13:21Z", "unix": 1700000001}}
+                ]
+            }"#,
+        )
+        .unwrap();
+        match event.into_update() {
+            Some(InvoiceUpdate::Settled {
+                payment_hash,
+                settled_at,
+                ..
+            }) => {
+                assert_eq!(payment_hash, "07".repeat(32));
+                assert_eq!(settled_at.unwrap().timestamp(), 1_700_000_001);
+            }
+            u => panic!("unexpected update {:?}", u),
+        }
+
+        // older versions send unix milliseconds
+        let event: EclairEvent = serde_json::from_str(
+            r#"{"type":"payment-received","paymentHash":"07","parts":[{"amount":1,"fromChannelId":"c","timestamp":1700000000000}]}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            event.into_update(),
+            Some(InvoiceUpdate::Settled {
+                settled_at: Some(_),
+                ..
+            })
+        ));
+
+        let relayed: EclairEvent =
+            serde_json::from_str(r#"{"type":"payment-relayed","amountIn":2,"amountOut":1}"#)
+                .unwrap();
+        assert!(relayed.into_update().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_eclair_add_invoice() {
+        use wiremock::matchers::{body_string, header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let invoice = signed_invoice(21_000, "coffee", 7).to_string();
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/createinvoice"))
+            // base64(":secret")
+            .and(header("authorization", "Basic OnNlY3JldA=="))
+            .and(header("content-type", "application/x-www-form-urlencoded"))
+            .and(body_string(
+                "amountMsat=21000&description=coffee&expireIn=600",
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "prefix": "lnbcrt",
+                "serialized": invoice,
+                "paymentHash": "07".repeat(32),
+                "amount": 21000
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let node = EclairNode::new(&server.uri(), "secret").unwrap();
+        let rsp = node
+            .add_invoice(AddInvoiceRequest {
+                amount: 21_000,
+                memo: Some("coffee".to_string()),
+                expire: Some(600),
+                idempotency_key: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(rsp.pr(), invoice);
+    }
+
+    #[tokio::test]
+    async fn test_eclair_subscribe_invoices() {
+        use futures::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+        use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+
+        #[allow(clippy::result_large_err)]
+        fn check_request(
+            req: &Request,
+            rsp: Response,
+        ) -> std::result::Result<Response, ErrorResponse> {
+            assert_eq!(req.uri().path(), "/ws");
+            assert_eq!(req.headers()["authorization"], "Basic OnNlY3JldA==");
+            Ok(rsp)
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (conn, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_hdr_async(conn, check_request)
+                .await
+                .unwrap();
+            ws.send(Message::text(
+                r#"{"type":"payment-relayed","amountIn":2,"amountOut":1}"#,
+            ))
+            .await
+            .unwrap();
+            ws.send(Message::text(
+                r#"{"type":"payment-received","paymentHash":"07","parts":[{"amount":1,"fromChannelId":"c","timestamp":1700000000000}]}"#,
+            ))
+            .await
+            .unwrap();
+            ws
+        });
+
+        let node = EclairNode::new(&url, "secret").unwrap();
+        let mut stream = node.subscribe_invoices(None).await.unwrap();
+        let update = tokio::time::timeout(std::time::Duration::from_secs(5), stream.next())
+            .await
+            .unwrap();
+        match update {
+            Some(InvoiceUpdate::Settled { payment_hash, .. }) => assert_eq!(payment_hash, "07"),
+            u => panic!("unexpected update {:?}", u),
+        }
+        server.await.unwrap();
+    }
+}
@@ -4,21 +4,28 @@
 //! Coverage exclusions are applied to async methods that require network access.
 
 use crate::lightning::{
-    AddInvoiceRequest, AddInvoiceResponse, InvoiceUpdate, LightningNode, PayInvoiceRequest,
-    PayInvoiceResponse,
+    AddInvoiceRequest, AddInvoiceResponse, AddressType, DEFAULT_INVOICE_EXPIRY, InvoiceUpdate,
+    LightningNode, PayInvoiceRequest, PayInvoiceResponse,
 };
-use anyhow::{Result, anyhow};
+use anyhow::{Result, anyhow, ensure};
 use async_trait::async_trait;
+use chrono::DateTime;
 use fedimint_tonic_lnd::invoicesrpc::lookup_invoice_msg::InvoiceRef;
-use fedimint_tonic_lnd::invoicesrpc::{CancelInvoiceMsg, LookupInvoiceMsg};
+use fedimint_tonic_lnd::invoicesrpc::{
+    AddHoldInvoiceRequest as LndAddHoldInvoiceRequest, CancelInvoiceMsg, LookupInvoiceMsg,
+    SettleInvoiceMsg, SubscribeSingleInvoiceRequest,
+};
 use fedimint_tonic_lnd::lnrpc::invoice::InvoiceState;
-use fedimint_tonic_lnd::lnrpc::{Invoice, InvoiceSubscription};
+use fedimint_tonic_lnd::lnrpc::{Invoice, InvoiceSubscription, NewAddressRequest};
 use fedimint_tonic_lnd::routerrpc::SendPaymentRequest;
+use fedimint_tonic_lnd::tonic::Code;
 use fedimint_tonic_lnd::{Client, connect};
 use futures::{Stream, StreamExt};
+use std::future::Future;
 use std::path::Path;
 use std::pin::Pin;
 use std::sync::Once;
+use std::time::Duration;
 
 #[cfg(not(any(feature = "tls-ring", feature = "tls-aws")))]
 compile_error!(
@@ -82,6 +89,7 @@ pub fn setup_crypto_provider() {
 ///         amount: 1000,
 ///         memo: Some("Test payment".to_string()),
 ///         expire: None,
+///         idempotency_key: None,
 ///     }).await?;
 ///     
 ///     println!("Pay this invoice: {}", invoice.pr());
@@ -91,6 +99,10 @@ pub fn setup_crypto_provider() {
 #[derive(Clone)]
 pub struct LndNode {
     client: Client,
+    /// Maximum time to wait for a single gRPC call
+    timeout: Duration,
+    /// Invoice expiry in seconds when a request sets none
+    default_expiry_seconds: u32,
 }
 
 impl LndNode {
@@ -106,76 +118,300 @@ impl LndNode {
     ///
     /// You must call [`setup_crypto_provider`] before creating connections.
     #[cfg_attr(coverage_nightly, coverage(off))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     pub async fn new(url: &str, cert: &Path, macaroon: &Path) -> Result<Self> {
+        Self::connect_with_timeout(url, cert, macaroon, Self::DEFAULT_TIMEOUT).await
+    }
+
+    /// Default timeout for connecting and for each gRPC call.
+    pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+    /// Create a new LND client connection, using `timeout` both for
+    /// connecting and for each subsequent gRPC call.
+    ///
+    /// See [`LndNode::new`] for the arguments.
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn connect_with_timeout(
+        url: &str,
+        cert: &Path,
+        macaroon: &Path,
+        timeout: Duration,
+    ) -> Result<Self> {
         let cert = cert
             .to_str()
             .ok_or_else(|| anyhow!("cert path is not valid UTF-8"))?;
         let macaroon = macaroon
             .to_str()
             .ok_or_else(|| anyhow!("macaroon path is not valid UTF-8"))?;
-        let lnd = connect(url.to_string(), cert, macaroon)
-            .await
+        let lnd = with_timeout(timeout, "connect", connect(url.to_string(), cert, macaroon))
+            .await?
             .map_err(|e| anyhow!("Failed to connect to LND: {}", e))?;
 
-        Ok(Self { client: lnd })
+        Ok(Self {
+            client: lnd,
+            timeout,
+            default_expiry_seconds: DEFAULT_INVOICE_EXPIRY,
+        })
+    }
+
+    /// Set the maximum time to wait for each gRPC call.
+    ///
+    /// A call that takes longer (e.g. because the wallet is locked) fails with
+    /// a timeout error instead of blocking the caller indefinitely.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Expire invoices after `seconds` when [`AddInvoiceRequest::expire`] is
+    /// not set, instead of [`DEFAULT_INVOICE_EXPIRY`].
+    pub fn with_default_expiry_seconds(mut self, seconds: u32) -> Self {
+        self.default_expiry_seconds = seconds;
+        self
     }
 
     /// Get a clone of the underlying LND client for advanced operations.
     pub fn client(&self) -> Client {
         self.client.clone()
     }
+
+    /// Create a hold invoice for `req.hash`.
+    ///
+    /// A payment to it is only locked in ([`InvoiceUpdate::Accepted`]): settle
+    /// it with [`LndNode::settle_hold_invoice`] once the preimage is revealed,
+    /// or refund it with [`LightningNode::cancel_invoice`]. LND only reports
+    /// the accepted state on [`LndNode::subscribe_hold_invoice`] and
+    /// [`LightningNode::lookup_invoice`].
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn add_hold_invoice(&self, req: AddHoldInvoiceRequest) -> Result<AddInvoiceResponse> {
+        let msg = hold_invoice_request(req, self.default_expiry_seconds)?;
+        let mut client = self.client.clone();
+        let res = with_timeout(
+            self.timeout,
+            "add_hold_invoice",
+            client.invoices().add_hold_invoice(msg),
+        )
+        .await??;
+        AddInvoiceResponse::from_invoice(&res.into_inner().payment_request, None)
+    }
+
+    /// Settle an accepted hold invoice with the preimage of its payment hash.
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn settle_hold_invoice(&self, preimage: &[u8]) -> Result<()> {
+        ensure!(preimage.len() == 32, "Preimage must be 32 bytes");
+        let mut client = self.client.clone();
+        with_timeout(
+            self.timeout,
+            "settle_hold_invoice",
+            client.invoices().settle_invoice(SettleInvoiceMsg {
+                preimage: preimage.to_vec(),
+            }),
+        )
+        .await??;
+        Ok(())
+    }
+
+    /// Updates of a single invoice, including [`InvoiceUpdate::Accepted`] for
+    /// hold invoices. The stream ends once the invoice is settled or canceled.
+    #[cfg_attr(coverage_nightly, coverage(off))]
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn subscribe_hold_invoice(
+        &self,
+        payment_hash: &[u8],
+    ) -> Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>> {
+        let mut client = self.client.clone();
+        let stream = with_timeout(
+            self.timeout,
+            "subscribe_hold_invoice",
+            client
+                .invoices()
+                .subscribe_single_invoice(SubscribeSingleInvoiceRequest {
+                    r_hash: payment_hash.to_vec(),
+                }),
+        )
+        .await??;
+        Ok(Box::pin(stream.into_inner().map(|i| match i {
+            Ok(m) => invoice_to_update(m),
+            Err(e) => InvoiceUpdate::Error(e.to_string()),
+        })))
+    }
+}
+
+/// Request to create a hold invoice with [`LndNode::add_hold_invoice`].
+#[derive(Debug, Clone)]
+pub struct AddHoldInvoiceRequest {
+    /// Amount in milli-satoshis
+    pub amount: u64,
+    /// Payment hash (32 bytes), the merchant keeps the preimage until settling
+    pub hash: Vec<u8>,
+    /// Optional memo/description for the invoice
+    pub memo: Option<String>,
+    /// Expiration time in seconds, defaults to the node's default expiry
+    pub expire: Option<u32>,
+}
+
+/// Build LND's `Invoice` to add for `req`, expiring after `default_expiry`
+/// seconds unless the request sets an expiry.
+fn invoice_request(req: AddInvoiceRequest, default_expiry: u32) -> Invoice {
+    Invoice {
+        memo: req.memo.unwrap_or_default(),
+        value_msat: req.amount as i64,
+        expiry: req.expire.unwrap_or(default_expiry) as i64,
+        ..Default::default()
+    }
+}
+
+/// Build LND's `AddHoldInvoiceRequest` from `req`, see [`invoice_request`]
+/// for `default_expiry`.
+fn hold_invoice_request(
+    req: AddHoldInvoiceRequest,
+    default_expiry: u32,
+) -> Result<LndAddHoldInvoiceRequest> {
+    ensure!(req.hash.len() == 32, "Payment hash must be 32 bytes");
+    Ok(LndAddHoldInvoiceRequest {
+        memo: req.memo.unwrap_or_default(),
+        hash: req.hash,
+        value_msat: i64::try_from(req.amount)?,
+        expiry: req.expire.unwrap_or(default_expiry) as i64,
+        ..Default::default()
+    })
+}
+
+/// Await `fut`, failing with a timeout error naming `call` after `timeout`.
+async fn with_timeout<F: Future>(timeout: Duration, call: &str, fut: F) -> Result<F::Output> {
+    tokio::time::timeout(timeout, fut)
+        .await
+        .map_err(|_| anyhow!("LND {} timed out after {:?}", call, timeout))
+}
+
+/// LND's `AddressType` for a fresh address of `kind`.
+fn lnd_address_type(kind: AddressType) -> fedimint_tonic_lnd::lnrpc::AddressType {
+    match kind {
+        AddressType::P2wkh => fedimint_tonic_lnd::lnrpc::AddressType::WitnessPubkeyHash,
+        AddressType::P2tr => fedimint_tonic_lnd::lnrpc::AddressType::TaprootPubkey,
+    }
+}
+
+/// Map an LND [`Invoice`] onto the [`InvoiceUpdate`] for its current state.
+fn invoice_to_update(m: Invoice) -> InvoiceUpdate {
+    const SETTLED: i32 = InvoiceState::Settled as i32;
+    const CREATED: i32 = InvoiceState::Open as i32;
+    const CANCELED: i32 = InvoiceState::Canceled as i32;
+    const ACCEPTED: i32 = InvoiceState::Accepted as i32;
+    let payment_hash = hex::encode(m.r_hash);
+    match m.state {
+        SETTLED => InvoiceUpdate::Settled {
+            payment_hash,
+            preimage: Some(hex::encode(m.r_preimage)),
+            external_id: None,
+            payment_request: Some(m.payment_request),
+            // unix seconds, 0 while unsettled
+            settled_at: (m.settle_date > 0)
+                .then(|| DateTime::from_timestamp(m.settle_date, 0))
+                .flatten(),
+            amount_msat: u64::try_from(m.amt_paid_msat).ok().filter(|a| *a > 0),
+            memo: (!m.memo.is_empty()).then_some(m.memo),
+        },
+        CREATED => InvoiceUpdate::Created {
+            payment_hash,
+            payment_request: m.payment_request,
+        },
+        CANCELED => InvoiceUpdate::Canceled { payment_hash },
+        ACCEPTED => InvoiceUpdate::Accepted {
+            payment_hash,
+            amount_msat: u64::try_from(m.amt_paid_msat).ok().filter(|a| *a > 0),
+        },
+        _ => InvoiceUpdate::Unknown { payment_hash },
+    }
 }
 
 #[async_trait]
 #[cfg_attr(coverage_nightly, coverage(off))]
 impl LightningNode for LndNode {
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     async fn add_invoice(&self, req: AddInvoiceRequest) -> Result<AddInvoiceResponse> {
         let mut client = self.client.clone();
         let ln = client.lightning();
-        let res = ln
-            .add_invoice(Invoice {
-                memo: req.memo.unwrap_or_default(),
-                value_msat: req.amount as i64,
-                expiry: req.expire.unwrap_or(3600) as i64,
-                ..Default::default()
-            })
-            .await?;
+        let res = with_timeout(
+            self.timeout,
+            "add_invoice",
+            ln.add_invoice(invoice_request(req, self.default_expiry_seconds)),
+        )
+        .await??;
 
         let inner = res.into_inner();
-        Ok(AddInvoiceResponse::from_invoice(
-            &inner.payment_request,
-            None,
-        )?)
+        AddInvoiceResponse::from_invoice(&inner.payment_request, None)
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     async fn cancel_invoice(&self, id: &[u8]) -> Result<()> {
         let mut client = self.client.clone();
         let ln = client.invoices();
-        ln.cancel_invoice(CancelInvoiceMsg {
-            payment_hash: id.to_vec(),
-        })
-        .await?;
+        with_timeout(
+            self.timeout,
+            "cancel_invoice",
+            ln.cancel_invoice(CancelInvoiceMsg {
+                payment_hash: id.to_vec(),
+            }),
+        )
+        .await??;
         Ok(())
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn lookup_invoice(&self, payment_hash: &[u8]) -> Result<Option<InvoiceUpdate>> {
+        let mut client = self.client.clone();
+        let res = with_timeout(
+            self.timeout,
+            "lookup_invoice",
+            client.invoices().lookup_invoice_v2(LookupInvoiceMsg {
+                lookup_modifier: 0,
+                invoice_ref: Some(InvoiceRef::PaymentHash(payment_hash.to_vec())),
+            }),
+        )
+        .await?;
+        match res {
+            Ok(inv) => Ok(Some(invoice_to_update(inv.into_inner()))),
+            Err(e) if e.code() == Code::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     async fn pay_invoice(&self, req: PayInvoiceRequest) -> Result<PayInvoiceResponse> {
         let mut client = self.client.clone();
         let router = client.router();
-        let mut stream = router
-            .send_payment_v2(SendPaymentRequest {
+        let payment_timeout = req.timeout_seconds.unwrap_or(60);
+        let mut stream = with_timeout(
+            self.timeout,
+            "send_payment_v2",
+            router.send_payment_v2(SendPaymentRequest {
                 payment_request: req.invoice.clone(),
-                timeout_seconds: req.timeout_seconds.unwrap_or(60) as i32,
+                timeout_seconds: payment_timeout as i32,
                 ..Default::default()
-            })
-            .await?
-            .into_inner();
+            }),
+        )
+        .await??
+        .into_inner();
 
-        // Wait for the final payment result
-        let mut final_result = None;
-        while let Some(update) = stream.message().await? {
-            // LND sends multiple updates, we want the final one
-            final_result = Some(update);
-        }
+        // Wait for the final payment result, LND gives up on the payment after
+        // `payment_timeout` so only a stuck node takes longer than this
+        let final_result = with_timeout(
+            Duration::from_secs(payment_timeout as u64) + self.timeout,
+            "send_payment_v2 result",
+            async {
+                let mut final_result = None;
+                while let Some(update) = stream.message().await? {
+                    // LND sends multiple updates, we want the final one
+                    final_result = Some(update);
+                }
+                Ok::<_, anyhow::Error>(final_result)
+            },
+        )
+        .await??;
 
         let payment = final_result.ok_or_else(|| anyhow!("No payment result received"))?;
 
@@ -197,19 +433,22 @@ impl LightningNode for LndNode {
         })
     }
 
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
     async fn subscribe_invoices(
         &self,
         from_payment_hash: Option<Vec<u8>>,
     ) -> Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>> {
         let mut client = self.client.clone();
         let from_settle_index = if let Some(ph) = from_payment_hash {
-            if let Ok(inv) = client
-                .invoices()
-                .lookup_invoice_v2(LookupInvoiceMsg {
+            if let Ok(Ok(inv)) = with_timeout(
+                self.timeout,
+                "lookup_invoice",
+                client.invoices().lookup_invoice_v2(LookupInvoiceMsg {
                     lookup_modifier: 0,
                     invoice_ref: Some(InvoiceRef::PaymentHash(ph)),
-                })
-                .await
+                }),
+            )
+            .await
             {
                 inv.into_inner().settle_index
             } else {
@@ -219,50 +458,203 @@ impl LightningNode for LndNode {
             0
         };
 
-        let stream = client
-            .lightning()
-            .subscribe_invoices(InvoiceSubscription {
+        let stream = with_timeout(
+            self.timeout,
+            "subscribe_invoices",
+            client.lightning().subscribe_invoices(InvoiceSubscription {
                 add_index: 0,
                 settle_index: from_settle_index,
-            })
-            .await?;
+            }),
+        )
+        .await??;
 
         let stream = stream.into_inner();
         Ok(Box::pin(stream.map(|i| match i {
-            Ok(m) => {
-                const SETTLED: i32 = InvoiceState::Settled as i32;
-                const CREATED: i32 = InvoiceState::Open as i32;
-                const CANCELED: i32 = InvoiceState::Canceled as i32;
-                let payment_hash = hex::encode(m.r_hash);
-                match m.state {
-                    SETTLED => InvoiceUpdate::Settled {
-                        payment_hash,
-                        preimage: Some(hex::encode(m.r_preimage)),
-                        external_id: None,
-                    },
-                    CREATED => InvoiceUpdate::Created {
-                        payment_hash,
-                        payment_request: m.payment_request,
-                    },
-                    CANCELED => InvoiceUpdate::Canceled { payment_hash },
-                    _ => InvoiceUpdate::Unknown { payment_hash },
-                }
-            }
+            Ok(m) => invoice_to_update(m),
             Err(e) => InvoiceUpdate::Error(e.to_string()),
         })))
     }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn new_onchain_address(&self, kind: AddressType) -> Result<String> {
+        let mut client = self.client.clone();
+        let res = with_timeout(
+            self.timeout,
+            "new_address",
+            client.lightning().new_address(NewAddressRequest {
+                r#type: lnd_address_type(kind) as i32,
+                account: String::new(),
+            }),
+        )
+        .await??;
+        Ok(res.into_inner().address)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_lnd_address_type() {
+        use fedimint_tonic_lnd::lnrpc::AddressType as LndAddressType;
+
+        assert_eq!(
+            lnd_address_type(AddressType::P2wkh),
+            LndAddressType::WitnessPubkeyHash
+        );
+        assert_eq!(lnd_address_type(AddressType::P2wkh) as i32, 0);
+        assert_eq!(
+            lnd_address_type(AddressType::P2tr),
+            LndAddressType::TaprootPubkey
+        );
+        assert_eq!(lnd_address_type(AddressType::P2tr) as i32, 4);
+    }
+
     #[test]
     fn test_setup_crypto_provider() {
         // Should not panic when called
         setup_crypto_provider();
     }
 
+    #[tokio::test]
+    async fn test_with_timeout_fires() {
+        let slow = tokio::time::sleep(Duration::from_secs(10));
+        let err = with_timeout(Duration::from_millis(10), "add_invoice", slow)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("LND add_invoice timed out"));
+    }
+
+    #[tokio::test]
+    async fn test_with_timeout_passes_result() {
+        let fast = async { Ok::<_, anyhow::Error>(42) };
+        let res = with_timeout(Duration::from_secs(1), "add_invoice", fast).await;
+        assert_eq!(res.unwrap().unwrap(), 42);
+    }
+
+    #[test]
+    fn test_invoice_to_update_settled() {
+        let update = invoice_to_update(Invoice {
+            r_hash: vec![0xab; 32],
+            r_preimage: vec![0xcd; 32],
+            state: InvoiceState::Settled as i32,
+            payment_request: "lnbcrt1".to_string(),
+            settle_date: 1_700_000_000,
+            amt_paid_msat: 21_000,
+            memo: "Coffee".to_string(),
+            ..Default::default()
+        });
+        if let InvoiceUpdate::Settled {
+            payment_hash,
+            preimage,
+            external_id,
+            payment_request,
+            settled_at,
+            amount_msat,
+            memo,
+        } = update
+        {
+            assert_eq!(amount_msat, Some(21_000));
+            assert_eq!(memo.as_deref(), Some("Coffee"));
+            assert_eq!(settled_at, DateTime::from_timestamp(1_700_000_000, 0));
+            assert_eq!(payment_hash, "ab".repeat(32));
+            assert_eq!(preimage, Some("cd".repeat(32)));
+            assert_eq!(external_id, None);
+            assert_eq!(payment_request.as_deref(), Some("lnbcrt1"));
+        } else {
+            panic!("Expected Settled variant");
+        }
+    }
+
+    #[test]
+    fn test_invoice_to_update_open_and_canceled() {
+        let update = invoice_to_update(Invoice {
+            r_hash: vec![0x01; 32],
+            payment_request: "lnbcrt1...".to_string(),
+            state: InvoiceState::Open as i32,
+            ..Default::default()
+        });
+        assert!(matches!(
+            update,
+            InvoiceUpdate::Created { payment_request, .. } if payment_request == "lnbcrt1..."
+        ));
+
+        let update = invoice_to_update(Invoice {
+            r_hash: vec![0x01; 32],
+            state: InvoiceState::Canceled as i32,
+            ..Default::default()
+        });
+        assert!(matches!(update, InvoiceUpdate::Canceled { .. }));
+    }
+
+    #[test]
+    fn test_invoice_to_update_accepted() {
+        let update = invoice_to_update(Invoice {
+            r_hash: vec![0x01; 32],
+            state: InvoiceState::Accepted as i32,
+            amt_paid_msat: 21_000,
+            ..Default::default()
+        });
+        match update {
+            InvoiceUpdate::Accepted {
+                payment_hash,
+                amount_msat,
+            } => {
+                assert_eq!(payment_hash, "01".repeat(32));
+                assert_eq!(amount_msat, Some(21_000));
+            }
+            u => panic!("Expected Accepted variant, got {:?}", u),
+        }
+    }
+
+    #[test]
+    fn test_invoice_request_default_expiry() {
+        let req = |expire| AddInvoiceRequest {
+            amount: 21_000,
+            memo: Some("Coffee".to_string()),
+            expire,
+            idempotency_key: None,
+        };
+        let invoice = invoice_request(req(None), 60);
+        assert_eq!(invoice.expiry, 60);
+        assert_eq!(invoice.value_msat, 21_000);
+        assert_eq!(invoice.memo, "Coffee");
+        assert_eq!(invoice_request(req(Some(600)), 60).expiry, 600);
+    }
+
+    #[test]
+    fn test_hold_invoice_request() {
+        let msg = hold_invoice_request(
+            AddHoldInvoiceRequest {
+                amount: 21_000,
+                hash: vec![0xab; 32],
+                memo: Some("Escrow".to_string()),
+                expire: None,
+            },
+            DEFAULT_INVOICE_EXPIRY,
+        )
+        .unwrap();
+        assert_eq!(msg.hash, vec![0xab; 32]);
+        assert_eq!(msg.value_msat, 21_000);
+        assert_eq!(msg.value, 0);
+        assert_eq!(msg.memo, "Escrow");
+        assert_eq!(msg.expiry, 3600);
+
+        assert!(
+            hold_invoice_request(
+                AddHoldInvoiceRequest {
+                    amount: 21_000,
+                    hash: vec![0xab; 16],
+                    memo: None,
+                    expire: Some(600),
+                },
+                DEFAULT_INVOICE_EXPIRY,
+            )
+            .is_err()
+        );
+    }
+
     #[test]
     fn test_setup_crypto_provider_idempotent() {
         // Should be safe to call multiple times
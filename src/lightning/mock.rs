@@ -0,0 +1,330 @@
+//! Mock Lightning node for downstream integration tests.
+//!
+//! [`MockLightningNode`] implements [`LightningNode`] entirely in memory so that
+//! consumers can test their payment flows without a real node. It is intended
+//! for tests only and is enabled by the `mock` feature (and in this crate's
+//! own tests).
+
+use crate::lightning::{
+    AddInvoiceRequest, AddInvoiceResponse, DEFAULT_INVOICE_EXPIRY, InvoiceUpdate, LightningNode,
+    PayInvoiceRequest, PayInvoiceResponse, invoice_memo,
+};
+use anyhow::{Result, anyhow, bail, ensure};
+use async_trait::async_trait;
+use bitcoin::hashes::{Hash, sha256};
+use bitcoin::secp256k1::{Secp256k1, SecretKey};
+use chrono::Utc;
+use futures::Stream;
+use futures::channel::mpsc::{UnboundedSender, unbounded};
+use lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Key used to sign mock invoices, never use it for real funds.
+const MOCK_NODE_KEY: [u8; 32] = [0x42; 32];
+
+/// An invoice created by a [`MockLightningNode`].
+#[derive(Debug, Clone)]
+pub struct MockInvoice {
+    /// The invoice as returned from [`add_invoice`](LightningNode::add_invoice)
+    pub invoice: AddInvoiceResponse,
+    /// Preimage of the invoice's payment hash
+    pub preimage: [u8; 32],
+    /// Latest state of the invoice
+    pub state: InvoiceUpdate,
+}
+
+#[derive(Default)]
+struct MockState {
+    /// Number of invoices created, used to derive unique preimages
+    created: u64,
+    /// Invoices keyed by hex payment hash
+    invoices: HashMap<String, MockInvoice>,
+    /// Senders of every open [`subscribe_invoices`](LightningNode::subscribe_invoices) stream
+    subscribers: Vec<UnboundedSender<InvoiceUpdate>>,
+    /// Fail every [`LightningNode`] call, see [`MockLightningNode::set_offline`]
+    offline: bool,
+}
+
+/// An in-memory [`LightningNode`] for tests.
+///
+/// Invoices are signed regtest BOLT11 invoices with a fixed key, so they parse
+/// like real ones. Nothing is ever paid: call [`settle`](Self::settle) or
+/// [`cancel`](Self::cancel) to drive an invoice to its final state, which is
+/// pushed to every open [`subscribe_invoices`](LightningNode::subscribe_invoices)
+/// stream.
+#[derive(Clone)]
+pub struct MockLightningNode {
+    state: Arc<Mutex<MockState>>,
+    /// Invoice expiry in seconds when a request sets none
+    default_expiry_seconds: u32,
+}
+
+impl Default for MockLightningNode {
+    fn default() -> Self {
+        Self {
+            state: Arc::default(),
+            default_expiry_seconds: DEFAULT_INVOICE_EXPIRY,
+        }
+    }
+}
+
+impl MockLightningNode {
+    /// Create an empty mock node.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expire invoices after `seconds` when [`AddInvoiceRequest::expire`] is
+    /// not set, instead of [`DEFAULT_INVOICE_EXPIRY`].
+    pub fn with_default_expiry_seconds(mut self, seconds: u32) -> Self {
+        self.default_expiry_seconds = seconds;
+        self
+    }
+
+    /// Make every [`LightningNode`] call fail as if the node were unreachable,
+    /// e.g. to test failover. [`settle`](Self::settle) and
+    /// [`cancel`](Self::cancel) keep working.
+    pub fn set_offline(&self, offline: bool) {
+        if let Ok(mut state) = self.state.lock() {
+            state.offline = offline;
+        }
+    }
+
+    /// All invoices created so far.
+    pub fn invoices(&self) -> Vec<MockInvoice> {
+        self.state
+            .lock()
+            .map(|s| s.invoices.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Look up an invoice by payment hash.
+    pub fn invoice(&self, payment_hash: &[u8]) -> Option<MockInvoice> {
+        let state = self.state.lock().ok()?;
+        state.invoices.get(&hex::encode(payment_hash)).cloned()
+    }
+
+    /// Mark an invoice as paid and push [`InvoiceUpdate::Settled`] to subscribers.
+    pub fn settle(&self, payment_hash: &[u8]) -> Result<()> {
+        self.transition(payment_hash, |hash, inv| InvoiceUpdate::Settled {
+            payment_hash: hash.to_string(),
+            preimage: Some(hex::encode(inv.preimage)),
+            external_id: inv.invoice.external_id.clone(),
+            payment_request: Some(inv.invoice.pr()),
+            settled_at: Some(Utc::now()),
+            amount_msat: inv.invoice.parsed_invoice.amount_milli_satoshis(),
+            memo: invoice_memo(&inv.invoice.parsed_invoice),
+        })
+    }
+
+    /// Mark an invoice as canceled and push [`InvoiceUpdate::Canceled`] to
+    /// subscribers.
+    pub fn cancel(&self, payment_hash: &[u8]) -> Result<()> {
+        self.transition(payment_hash, |hash, _| InvoiceUpdate::Canceled {
+            payment_hash: hash.to_string(),
+        })
+    }
+
+    /// Move an open invoice to a final state and broadcast the update.
+    fn transition(
+        &self,
+        payment_hash: &[u8],
+        update: impl FnOnce(&str, &MockInvoice) -> InvoiceUpdate,
+    ) -> Result<()> {
+        let hash = hex::encode(payment_hash);
+        let mut state = self.lock_state()?;
+        let inv = state
+            .invoices
+            .get_mut(&hash)
+            .ok_or_else(|| anyhow!("unknown invoice {}", hash))?;
+        if !matches!(inv.state, InvoiceUpdate::Created { .. }) {
+            bail!("invoice {} is already {:?}", hash, inv.state);
+        }
+        inv.state = update(&hash, inv);
+        let update = inv.state.clone();
+        state
+            .subscribers
+            .retain(|tx| tx.unbounded_send(update.clone()).is_ok());
+        Ok(())
+    }
+
+    fn lock_state(&self) -> Result<MutexGuard<'_, MockState>> {
+        self.state
+            .lock()
+            .map_err(|_| anyhow!("mock node state poisoned"))
+    }
+
+    /// Lock the state for a [`LightningNode`] call, failing if offline.
+    fn online_state(&self) -> Result<MutexGuard<'_, MockState>> {
+        let state = self.lock_state()?;
+        ensure!(!state.offline, "mock node is offline");
+        Ok(state)
+    }
+}
+
+#[async_trait]
+impl LightningNode for MockLightningNode {
+    async fn add_invoice(&self, req: AddInvoiceRequest) -> Result<AddInvoiceResponse> {
+        let mut state = self.online_state()?;
+        state.created += 1;
+        let mut preimage = [0u8; 32];
+        preimage[24..].copy_from_slice(&state.created.to_be_bytes());
+        let payment_hash = sha256::Hash::hash(&preimage);
+
+        let secp = Secp256k1::new();
+        let key = SecretKey::from_slice(&MOCK_NODE_KEY)?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let invoice = InvoiceBuilder::new(Currency::Regtest)
+            .description(req.memo.unwrap_or_default())
+            .payment_hash(payment_hash)
+            .payment_secret(PaymentSecret(preimage))
+            .duration_since_epoch(now)
+            .expiry_time(std::time::Duration::from_secs(
+                req.expire.unwrap_or(self.default_expiry_seconds) as u64,
+            ))
+            .min_final_cltv_expiry_delta(144)
+            .amount_milli_satoshis(req.amount)
+            .build_signed(|hash| secp.sign_ecdsa_recoverable(hash, &key))
+            .map_err(|e| anyhow!("failed to sign mock invoice: {:?}", e))?;
+
+        let rsp = AddInvoiceResponse::from_invoice(&invoice.to_string(), None)?;
+        let created = InvoiceUpdate::Created {
+            payment_hash: rsp.payment_hash(),
+            payment_request: rsp.pr(),
+        };
+        state
+            .subscribers
+            .retain(|tx| tx.unbounded_send(created.clone()).is_ok());
+        state.invoices.insert(
+            rsp.payment_hash(),
+            MockInvoice {
+                invoice: rsp.clone(),
+                preimage,
+                state: created,
+            },
+        );
+        Ok(rsp)
+    }
+
+    async fn cancel_invoice(&self, id: &[u8]) -> Result<()> {
+        drop(self.online_state()?);
+        self.cancel(id)
+    }
+
+    async fn lookup_invoice(&self, payment_hash: &[u8]) -> Result<Option<InvoiceUpdate>> {
+        let state = self.online_state()?;
+        Ok(state
+            .invoices
+            .get(&hex::encode(payment_hash))
+            .map(|i| i.state.clone()))
+    }
+
+    async fn pay_invoice(&self, _req: PayInvoiceRequest) -> Result<PayInvoiceResponse> {
+        bail!("mock node cannot pay invoices")
+    }
+
+    async fn subscribe_invoices(
+        &self,
+        _from_payment_hash: Option<Vec<u8>>,
+    ) -> Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>> {
+        let (tx, rx) = unbounded();
+        self.online_state()?.subscribers.push(tx);
+        Ok(Box::pin(rx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    fn request(amount: u64) -> AddInvoiceRequest {
+        AddInvoiceRequest {
+            amount,
+            memo: Some("test".to_string()),
+            expire: None,
+            idempotency_key: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_then_settle_yields_settled() {
+        let node = MockLightningNode::new();
+        let mut stream = node.subscribe_invoices(None).await.unwrap();
+
+        let inv = node.add_invoice(request(21_000)).await.unwrap();
+        assert_eq!(inv.parsed_invoice.amount_milli_satoshis(), Some(21_000));
+        let hash = hex::decode(inv.payment_hash()).unwrap();
+        node.settle(&hash).unwrap();
+        drop(node);
+
+        let updates: Vec<_> = stream.by_ref().collect().await;
+        let settled: Vec<_> = updates
+            .iter()
+            .filter(|u| matches!(u, InvoiceUpdate::Settled { .. }))
+            .collect();
+        assert_eq!(settled.len(), 1);
+        match settled[0] {
+            InvoiceUpdate::Settled {
+                payment_hash,
+                preimage: Some(preimage),
+                ..
+            } => {
+                assert_eq!(payment_hash, &inv.payment_hash());
+                let preimage = hex::decode(preimage).unwrap();
+                assert_eq!(sha256::Hash::hash(&preimage).to_byte_array().to_vec(), hash);
+            }
+            u => panic!("unexpected update {:?}", u),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_default_expiry() {
+        let node = MockLightningNode::new().with_default_expiry_seconds(60);
+        let inv = node.add_invoice(request(1_000)).await.unwrap();
+        assert_eq!(inv.parsed_invoice.expiry_time().as_secs(), 60);
+
+        let mut req = request(1_000);
+        req.expire = Some(600);
+        let inv = node.add_invoice(req).await.unwrap();
+        assert_eq!(inv.parsed_invoice.expiry_time().as_secs(), 600);
+    }
+
+    #[tokio::test]
+    async fn test_settle_rejects_unknown_and_final_invoices() {
+        let node = MockLightningNode::new();
+        assert!(node.settle(&[0; 32]).is_err());
+
+        let inv = node.add_invoice(request(1_000)).await.unwrap();
+        let hash = hex::decode(inv.payment_hash()).unwrap();
+        node.cancel_invoice(&hash).await.unwrap();
+        assert!(node.settle(&hash).is_err());
+        assert!(matches!(
+            node.lookup_invoice(&hash).await.unwrap(),
+            Some(InvoiceUpdate::Canceled { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_offline_node_fails_calls() {
+        let node = MockLightningNode::new();
+        let inv = node.add_invoice(request(1_000)).await.unwrap();
+        let hash = hex::decode(inv.payment_hash()).unwrap();
+
+        node.set_offline(true);
+        assert!(node.add_invoice(request(1_000)).await.is_err());
+        assert!(node.lookup_invoice(&hash).await.is_err());
+        assert!(node.subscribe_invoices(None).await.is_err());
+        // the test helpers still drive invoices
+        node.settle(&hash).unwrap();
+
+        node.set_offline(false);
+        assert!(matches!(
+            node.lookup_invoice(&hash).await.unwrap(),
+            Some(InvoiceUpdate::Settled { .. })
+        ));
+    }
+}
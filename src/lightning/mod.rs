@@ -7,6 +7,13 @@
 //!
 //! - **LND** (`method-lnd` feature) - Direct connection to Lightning Network Daemon
 //! - **Bitvora** (`method-bitvora` feature) - Custodial Lightning payment API
+//! - **BTCPay Server** (`method-btcpay` feature) - Lightning node of a BTCPay store
+//! - **Strike** (`method-strike` feature) - Custodial Lightning API with USD invoices
+//! - **Eclair** (`method-eclair` feature) - Eclair node HTTP API and event stream
+//! - **Mock** (`mock` feature) - In-memory node for downstream tests only
+//!
+//! WebSocket based backends can build their invoice subscription on
+//! [`ws_invoice_stream`] (`ws` feature).
 //!
 //! # Example
 //!
@@ -19,40 +26,108 @@
 //!     amount: 1000, // 1000 milli-satoshis
 //!     memo: Some("Coffee".to_string()),
 //!     expire: Some(3600),
+//!     idempotency_key: None,
 //! }).await?;
 //!
 //! println!("Payment request: {}", invoice.pr());
 //! ```
 
-use anyhow::{Result, anyhow};
+use crate::currency::{Currency, CurrencyAmount, CurrencyConverter};
+use anyhow::{Result, anyhow, bail};
 use async_trait::async_trait;
-use futures::Stream;
+use chrono::{DateTime, Utc};
+use futures::future::{BoxFuture, Shared};
+use futures::task::AtomicWaker;
+use futures::{FutureExt, Stream, TryFutureExt};
 use hex::ToHex;
-use lightning_invoice::Bolt11Invoice;
+use lightning_invoice::{Bolt11Invoice, Bolt11InvoiceDescriptionRef, ParseOrSemanticError};
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime};
 
 #[cfg(feature = "method-bitvora")]
 mod bitvora;
+#[cfg(feature = "method-btcpay")]
+mod btcpay;
+#[cfg(feature = "method-eclair")]
+mod eclair;
 #[cfg(feature = "method-lnd")]
 mod lnd;
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+#[cfg(feature = "qr")]
+mod qr;
+#[cfg(feature = "method-strike")]
+mod strike;
+#[cfg(feature = "ws")]
+mod ws;
 
 #[cfg(feature = "method-bitvora")]
 pub use bitvora::*;
+#[cfg(feature = "method-btcpay")]
+pub use btcpay::*;
+#[cfg(feature = "method-eclair")]
+pub use eclair::*;
 #[cfg(feature = "method-lnd")]
 pub use lnd::*;
+#[cfg(any(test, feature = "mock"))]
+pub use mock::*;
+#[cfg(feature = "qr")]
+pub use qr::*;
+#[cfg(feature = "method-strike")]
+pub use strike::*;
+#[cfg(feature = "ws")]
+pub use ws::*;
+
+/// Invoice expiry in seconds used when [`AddInvoiceRequest::expire`] is not
+/// set, unless the node is configured with another default.
+pub const DEFAULT_INVOICE_EXPIRY: u32 = 3600;
+
+/// Maximum number of invoices [`LightningNode::add_invoices`] creates at once.
+pub const ADD_INVOICES_CONCURRENCY: usize = 8;
 
 /// Trait for Lightning Network node implementations.
 ///
 /// Implement this trait to add support for additional Lightning providers.
-/// Both LND and Bitvora implement this trait.
+/// The LND, Bitvora, BTCPay, Eclair and Strike backends implement this trait,
+/// as does `MockLightningNode` for tests. [`IdempotentNode`] and
+/// [`FailoverNode`] wrap other implementations.
 #[async_trait]
 pub trait LightningNode: Send + Sync {
     /// Create a new invoice for receiving payments.
     async fn add_invoice(&self, req: AddInvoiceRequest) -> Result<AddInvoiceResponse>;
 
+    /// Create several invoices, running up to [`ADD_INVOICES_CONCURRENCY`]
+    /// creations at a time.
+    ///
+    /// Results are in the order of `reqs`, and a failed invoice does not stop
+    /// the others. Backends with a native batch endpoint can override this.
+    async fn add_invoices(&self, reqs: Vec<AddInvoiceRequest>) -> Vec<Result<AddInvoiceResponse>> {
+        use futures::StreamExt;
+
+        let mut results: Vec<_> = futures::stream::iter(reqs.into_iter().enumerate())
+            .map(|(i, req)| async move { (i, self.add_invoice(req).await) })
+            .buffer_unordered(ADD_INVOICES_CONCURRENCY)
+            .collect()
+            .await;
+        results.sort_by_key(|(i, _)| *i);
+        results.into_iter().map(|(_, r)| r).collect()
+    }
+
     /// Cancel an existing invoice by payment hash.
     async fn cancel_invoice(&self, id: &[u8]) -> Result<()>;
 
+    /// Look up the current state of an invoice by payment hash.
+    ///
+    /// The state is mapped into the matching [`InvoiceUpdate`] variant, so an
+    /// invoice that has been paid is returned as [`InvoiceUpdate::Settled`].
+    /// Returns `None` if the node does not know the invoice.
+    async fn lookup_invoice(&self, payment_hash: &[u8]) -> Result<Option<InvoiceUpdate>>;
+
     /// Pay a Lightning invoice.
     async fn pay_invoice(&self, req: PayInvoiceRequest) -> Result<PayInvoiceResponse>;
 
@@ -65,6 +140,53 @@ pub trait LightningNode: Send + Sync {
         &self,
         from_payment_hash: Option<Vec<u8>>,
     ) -> Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>>;
+
+    /// Subscribe to invoice updates for a known set of payment hashes only.
+    ///
+    /// Updates for any other invoice are dropped, see [`PaymentHashFilter`].
+    async fn subscribe_invoices_for(
+        &self,
+        payment_hashes: HashSet<Vec<u8>>,
+    ) -> Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>> {
+        let stream = self.subscribe_invoices(None).await?;
+        Ok(Box::pin(stream.only_payment_hashes(payment_hashes)))
+    }
+
+    /// Subscribe to invoice updates with a [`SubscriptionHandle`] to stop the
+    /// subscription, e.g. on shutdown.
+    ///
+    /// See [`Stoppable`] for what happens to in-flight events.
+    async fn subscribe_invoices_stoppable(
+        &self,
+        from_payment_hash: Option<Vec<u8>>,
+    ) -> Result<(
+        Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>,
+        SubscriptionHandle,
+    )> {
+        let (stream, handle) = self
+            .subscribe_invoices(from_payment_hash)
+            .await?
+            .stoppable();
+        Ok((Box::pin(stream), handle))
+    }
+
+    /// Derive a fresh on-chain address from the node's wallet, e.g. to offer
+    /// next to a BOLT11 invoice.
+    ///
+    /// Fails unless the backend has an on-chain wallet (currently LND).
+    async fn new_onchain_address(&self, kind: AddressType) -> Result<String> {
+        let _ = kind;
+        bail!("On-chain addresses are not supported by this node")
+    }
+}
+
+/// Type of on-chain address for [`LightningNode::new_onchain_address`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressType {
+    /// Native SegWit v0 (`bc1q...`)
+    P2wkh,
+    /// Taproot (`bc1p...`)
+    P2tr,
 }
 
 /// Request to create a new Lightning invoice.
@@ -74,8 +196,114 @@ pub struct AddInvoiceRequest {
     pub amount: u64,
     /// Optional memo/description for the invoice
     pub memo: Option<String>,
-    /// Expiration time in seconds (default: 3600)
+    /// Expiration time in seconds, defaults to the node's default expiry
+    /// ([`DEFAULT_INVOICE_EXPIRY`] unless configured otherwise)
     pub expire: Option<u32>,
+    /// Optional idempotency key (e.g. the order id).
+    ///
+    /// Wrap the node in an [`IdempotentNode`] to return the invoice created
+    /// first when the same key is used again within its window, so retrying
+    /// after a timeout cannot double-bill an order. Backends with native
+    /// idempotency (Strike) also forward the key to the provider.
+    pub idempotency_key: Option<String>,
+}
+
+impl AddInvoiceRequest {
+    /// Create a request for an amount in any currency.
+    ///
+    /// Bitcoin amounts are used directly; fiat amounts are converted to
+    /// milli-satoshis with `conv`.
+    pub fn from_currency(
+        amount: CurrencyAmount,
+        conv: &(impl CurrencyConverter + ?Sized),
+        memo: Option<String>,
+    ) -> Result<Self> {
+        let amount = match amount.currency() {
+            Currency::BTC => amount.value(),
+            _ => conv.convert(amount, Currency::BTC)?.value(),
+        };
+        Ok(Self {
+            amount,
+            memo,
+            expire: None,
+            idempotency_key: None,
+        })
+    }
+
+    /// Set the idempotency key, usually the order id.
+    pub fn with_idempotency_key(mut self, key: impl Into<String>) -> Self {
+        self.idempotency_key = Some(key.into());
+        self
+    }
+
+    /// Set the memo by rendering `template` with [`render_memo`].
+    pub fn with_memo_template(mut self, template: &str, vars: &HashMap<String, String>) -> Self {
+        self.memo = Some(render_memo(template, vars));
+        self
+    }
+}
+
+/// Express a settled Lightning amount in the currency the order was priced
+/// in, for reconciliation.
+///
+/// This is the inverse of [`AddInvoiceRequest::from_currency`]: pass a
+/// converter holding the rate quoted when the invoice was created (e.g. a
+/// [`StaticRateConverter`](crate::currency::StaticRateConverter)) to book the
+/// payment at that rate rather than the current one.
+pub fn settled_fiat_amount(
+    amount_msat: u64,
+    currency: Currency,
+    quote: &impl CurrencyConverter,
+) -> Result<CurrencyAmount> {
+    quote.convert(CurrencyAmount::millisats(amount_msat), currency)
+}
+
+/// Render an invoice memo from a template with `{name}` placeholders.
+///
+/// Each `{name}` is replaced with the matching entry from `vars`. Placeholders
+/// without a matching variable are left as-is, so a typo shows up in the memo
+/// instead of silently disappearing. Use `{{` and `}}` for literal braces.
+///
+/// ```
+/// use payments_rs::lightning::render_memo;
+/// use std::collections::HashMap;
+///
+/// let vars = HashMap::from([("order_id".to_string(), "123".to_string())]);
+/// assert_eq!(render_memo("Order #{order_id}", &vars), "Order #123");
+/// ```
+pub fn render_memo(template: &str, vars: &HashMap<String, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(pos) = rest.find(['{', '}']) {
+        out.push_str(&rest[..pos]);
+        let brace = &rest[pos..pos + 1];
+        let after = &rest[pos + 1..];
+        if after.starts_with(brace) {
+            // Escaped literal brace: `{{` or `}}`
+            out.push_str(brace);
+            rest = &after[1..];
+        } else if brace == "{" {
+            match after.find('}') {
+                Some(end) => {
+                    let name = &after[..end];
+                    match vars.get(name) {
+                        Some(value) => out.push_str(value),
+                        None => out.push_str(&rest[pos..pos + end + 2]),
+                    }
+                    rest = &after[end + 1..];
+                }
+                None => {
+                    out.push_str(&rest[pos..]);
+                    rest = "";
+                }
+            }
+        } else {
+            out.push('}');
+            rest = after;
+        }
+    }
+    out.push_str(rest);
+    out
 }
 
 /// Response from creating a Lightning invoice.
@@ -98,11 +326,23 @@ impl AddInvoiceResponse {
         self.parsed_invoice.payment_hash().encode_hex()
     }
 
+    /// When the invoice expires, its creation timestamp plus expiry.
+    pub fn expires_at(&self) -> SystemTime {
+        let invoice = &self.parsed_invoice;
+        let created = SystemTime::UNIX_EPOCH + invoice.duration_since_epoch();
+        created
+            .checked_add(invoice.expiry_time())
+            .unwrap_or_else(|| created + Duration::from_secs(u32::MAX as u64))
+    }
+
+    /// Whether the invoice has expired and can no longer be paid.
+    pub fn is_expired(&self) -> bool {
+        SystemTime::now() >= self.expires_at()
+    }
+
     /// Create an AddInvoiceResponse from a payment request string.
     pub fn from_invoice(pr: &str, external_id: Option<String>) -> Result<AddInvoiceResponse> {
-        let parsed = pr
-            .parse()
-            .map_err(|e| anyhow!("Failed to parse invoice {}", e))?;
+        let parsed = parse_invoice(pr)?;
         Ok(Self {
             parsed_invoice: parsed,
             external_id,
@@ -110,6 +350,241 @@ impl AddInvoiceResponse {
     }
 }
 
+/// An invoice string that could not be parsed as BOLT11.
+///
+/// Carries a truncated copy of the input and the specific parse error, so an
+/// operator can tell which field of a provider's invoice was malformed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidInvoice {
+    /// The offending invoice, truncated to [`InvalidInvoice::MAX_INPUT_LEN`]
+    /// characters
+    pub input: String,
+    /// Why the invoice was rejected
+    pub error: ParseOrSemanticError,
+}
+
+impl InvalidInvoice {
+    /// Maximum number of characters of the input kept in [`InvalidInvoice::input`].
+    pub const MAX_INPUT_LEN: usize = 64;
+
+    /// Record that `input` failed to parse with `error`.
+    pub fn new(input: &str, error: ParseOrSemanticError) -> Self {
+        let input = match input.char_indices().nth(Self::MAX_INPUT_LEN) {
+            Some((i, _)) => format!("{}...", &input[..i]),
+            None => input.to_string(),
+        };
+        Self { input, error }
+    }
+}
+
+impl Display for InvalidInvoice {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Failed to parse invoice {}: {}", self.input, self.error)
+    }
+}
+
+impl std::error::Error for InvalidInvoice {}
+
+/// Parse `input` as a BOLT11 invoice.
+pub fn parse_invoice(input: &str) -> std::result::Result<Bolt11Invoice, InvalidInvoice> {
+    input.parse().map_err(|e| InvalidInvoice::new(input, e))
+}
+
+/// An invoice creation shared by every caller using the same idempotency key.
+type SharedInvoice = Shared<BoxFuture<'static, std::result::Result<AddInvoiceResponse, String>>>;
+
+/// Invoice reserved for an idempotency key in an [`IdempotencyCache`].
+struct IdempotencyEntry {
+    created_at: Instant,
+    /// Amount, memo and expiry of the request, to tell a retry from a
+    /// different request reusing the key
+    request: (u64, Option<String>, Option<u32>),
+    invoice: SharedInvoice,
+}
+
+/// Short-lived map from idempotency key to the invoice created for it.
+///
+/// Used by [`IdempotentNode`], since backends such as LND have no native
+/// idempotency for invoice creation. Entries older than the window are dropped
+/// on access.
+#[derive(Clone)]
+pub struct IdempotencyCache {
+    window: Duration,
+    entries: Arc<Mutex<HashMap<String, IdempotencyEntry>>>,
+}
+
+impl IdempotencyCache {
+    /// Default time an invoice is remembered for its key.
+    pub const DEFAULT_WINDOW: Duration = Duration::from_secs(600);
+
+    /// Create a cache remembering invoices for `window`.
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Create the invoice for `req` with `create`, or return the invoice
+    /// already created for its [`idempotency_key`](AddInvoiceRequest::idempotency_key).
+    ///
+    /// The key is reserved before `create` runs, so concurrent calls and
+    /// retries with the same key wait for the first creation instead of
+    /// starting another one. This includes a retry after the first caller
+    /// gave up waiting (e.g. dropped the future on a timeout), which picks the
+    /// creation up where it was left. If creating the invoice fails the key is
+    /// released and the next call tries again.
+    ///
+    /// Reusing a key with a different amount, memo or expiry is an error.
+    /// Requests without a key are passed straight to `create`.
+    pub async fn get_or_create<F>(
+        &self,
+        req: AddInvoiceRequest,
+        create: F,
+    ) -> Result<AddInvoiceResponse>
+    where
+        F: FnOnce(AddInvoiceRequest) -> BoxFuture<'static, Result<AddInvoiceResponse>>,
+    {
+        let Some(key) = req.idempotency_key.clone() else {
+            return create(req).await;
+        };
+        let request = (req.amount, req.memo.clone(), req.expire);
+        let invoice = {
+            let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            entries.retain(|_, e| e.created_at.elapsed() < self.window);
+            match entries.get(&key) {
+                Some(entry) => {
+                    if entry.request != request {
+                        bail!(
+                            "Idempotency key {} was already used for a different invoice",
+                            key
+                        );
+                    }
+                    entry.invoice.clone()
+                }
+                None => {
+                    let invoice = create(req).map_err(|e| format!("{:#}", e)).boxed().shared();
+                    let entry = IdempotencyEntry {
+                        created_at: Instant::now(),
+                        request,
+                        invoice: invoice.clone(),
+                    };
+                    entries.insert(key.clone(), entry);
+                    invoice
+                }
+            }
+        };
+        match invoice.clone().await {
+            Ok(rsp) => Ok(rsp),
+            Err(e) => {
+                // release the key for the next attempt, unless it was
+                // reserved again in the meantime
+                let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+                if entries
+                    .get(&key)
+                    .is_some_and(|entry| entry.invoice.ptr_eq(&invoice))
+                {
+                    entries.remove(&key);
+                }
+                Err(anyhow!(e))
+            }
+        }
+    }
+}
+
+impl Default for IdempotencyCache {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_WINDOW)
+    }
+}
+
+/// A [`LightningNode`] honouring [`AddInvoiceRequest::idempotency_key`] on
+/// top of any backend.
+///
+/// Invoice creation goes through an [`IdempotencyCache`], so retrying
+/// [`add_invoice`](LightningNode::add_invoice) with the same key returns the
+/// invoice created first instead of double-billing an order. Everything else is
+/// passed through to the wrapped node.
+pub struct IdempotentNode {
+    node: Arc<dyn LightningNode>,
+    cache: IdempotencyCache,
+}
+
+impl IdempotentNode {
+    /// Wrap `node`, remembering invoices for [`IdempotencyCache::DEFAULT_WINDOW`].
+    pub fn new(node: Arc<dyn LightningNode>) -> Self {
+        Self {
+            node,
+            cache: IdempotencyCache::default(),
+        }
+    }
+
+    /// Remember invoices for `window` instead.
+    pub fn with_window(mut self, window: Duration) -> Self {
+        self.cache = IdempotencyCache::new(window);
+        self
+    }
+
+    /// The wrapped node.
+    pub fn inner(&self) -> &Arc<dyn LightningNode> {
+        &self.node
+    }
+}
+
+#[async_trait]
+impl LightningNode for IdempotentNode {
+    async fn add_invoice(&self, req: AddInvoiceRequest) -> Result<AddInvoiceResponse> {
+        let node = self.node.clone();
+        self.cache
+            .get_or_create(req, move |req| {
+                async move { node.add_invoice(req).await }.boxed()
+            })
+            .await
+    }
+
+    async fn cancel_invoice(&self, id: &[u8]) -> Result<()> {
+        self.node.cancel_invoice(id).await
+    }
+
+    async fn lookup_invoice(&self, payment_hash: &[u8]) -> Result<Option<InvoiceUpdate>> {
+        self.node.lookup_invoice(payment_hash).await
+    }
+
+    async fn pay_invoice(&self, req: PayInvoiceRequest) -> Result<PayInvoiceResponse> {
+        self.node.pay_invoice(req).await
+    }
+
+    async fn subscribe_invoices(
+        &self,
+        from_payment_hash: Option<Vec<u8>>,
+    ) -> Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>> {
+        self.node.subscribe_invoices(from_payment_hash).await
+    }
+
+    async fn subscribe_invoices_for(
+        &self,
+        payment_hashes: HashSet<Vec<u8>>,
+    ) -> Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>> {
+        self.node.subscribe_invoices_for(payment_hashes).await
+    }
+
+    async fn subscribe_invoices_stoppable(
+        &self,
+        from_payment_hash: Option<Vec<u8>>,
+    ) -> Result<(
+        Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>,
+        SubscriptionHandle,
+    )> {
+        self.node
+            .subscribe_invoices_stoppable(from_payment_hash)
+            .await
+    }
+
+    async fn new_onchain_address(&self, kind: AddressType) -> Result<String> {
+        self.node.new_onchain_address(kind).await
+    }
+}
+
 /// Request to pay a Lightning invoice.
 #[derive(Debug, Clone)]
 pub struct PayInvoiceRequest {
@@ -142,6 +617,8 @@ pub enum InvoiceUpdate {
     },
     /// An error occurred
     Error(String),
+    /// The provider reported an invoice that could not be parsed
+    InvalidInvoice(InvalidInvoice),
     /// Invoice was created
     Created {
         /// Payment hash as hex string
@@ -154,6 +631,15 @@ pub enum InvoiceUpdate {
         /// Payment hash as hex string
         payment_hash: String,
     },
+    /// HTLCs paying the invoice are locked in but not settled yet: a hold
+    /// invoice waiting for the merchant, or a multi-part payment in flight
+    Accepted {
+        /// Payment hash as hex string
+        payment_hash: String,
+        /// Amount of the accepted HTLCs in milli-satoshis, `None` if the
+        /// provider does not report it
+        amount_msat: Option<u64>,
+    },
     /// Invoice was paid/settled
     Settled {
         /// Payment hash as hex string
@@ -162,12 +648,943 @@ pub enum InvoiceUpdate {
         preimage: Option<String>,
         /// External ID from the provider
         external_id: Option<String>,
+        /// BOLT11 payment request of the settled invoice, if the provider
+        /// reports it
+        payment_request: Option<String>,
+        /// When the provider settled the invoice, `None` if it does not report
+        /// it (use the time the update was received instead)
+        settled_at: Option<DateTime<Utc>>,
+        /// Amount paid in milli-satoshis, `None` if the provider does not
+        /// report it
+        amount_msat: Option<u64>,
+        /// Memo (description) of the settled invoice, if known
+        memo: Option<String>,
     },
 }
 
+impl InvoiceUpdate {
+    /// Payment hash (hex) of the invoice this update is about, if any.
+    pub fn payment_hash(&self) -> Option<&str> {
+        match self {
+            InvoiceUpdate::Unknown { payment_hash }
+            | InvoiceUpdate::Created { payment_hash, .. }
+            | InvoiceUpdate::Canceled { payment_hash }
+            | InvoiceUpdate::Accepted { payment_hash, .. }
+            | InvoiceUpdate::Settled { payment_hash, .. } => Some(payment_hash),
+            InvoiceUpdate::Error(_) | InvoiceUpdate::InvalidInvoice(_) => None,
+        }
+    }
+
+    /// BOLT11 payment request carried by this update, if any.
+    pub fn payment_request(&self) -> Option<&str> {
+        match self {
+            InvoiceUpdate::Created {
+                payment_request, ..
+            } => Some(payment_request),
+            InvoiceUpdate::Settled {
+                payment_request, ..
+            } => payment_request.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Check that the invoice behind `update` commits to the description hash
+/// `expected`.
+///
+/// LNURL-pay invoices must carry the hash of the LNURL metadata, verifying it
+/// on settlement guards against an invoice being swapped for one with another
+/// description. Fails if the hash does not match, the invoice has a plain
+/// description, or the update does not carry the payment request (use
+/// [`LightningNode::lookup_invoice`] in that case).
+pub fn verify_description_hash(update: &InvoiceUpdate, expected: &[u8; 32]) -> Result<()> {
+    let pr = update
+        .payment_request()
+        .ok_or_else(|| anyhow!("Invoice update does not carry a payment request"))?;
+    let invoice = parse_invoice(pr).map_err(|e| anyhow!("{}", e))?;
+    if invoice.payment_hash().encode_hex::<String>() != update.payment_hash().unwrap_or_default() {
+        bail!("Payment request does not match the update payment hash");
+    }
+    match invoice.description() {
+        Bolt11InvoiceDescriptionRef::Hash(h) => {
+            let got: &[u8] = h.0.as_ref();
+            if got != expected {
+                bail!(
+                    "Description hash mismatch: expected {}, got {}",
+                    hex::encode(expected),
+                    hex::encode(got)
+                );
+            }
+            Ok(())
+        }
+        Bolt11InvoiceDescriptionRef::Direct(_) => {
+            bail!("Invoice has a description instead of a description hash")
+        }
+    }
+}
+
+/// The plain description of `invoice`, `None` if it is empty or only carries
+/// a description hash.
+#[cfg(any(test, feature = "method-bitvora", feature = "mock"))]
+pub(crate) fn invoice_memo(invoice: &Bolt11Invoice) -> Option<String> {
+    match invoice.description() {
+        Bolt11InvoiceDescriptionRef::Direct(d) => Some(d.to_string()).filter(|d| !d.is_empty()),
+        Bolt11InvoiceDescriptionRef::Hash(_) => None,
+    }
+}
+
+/// A [`LightningNode`] spreading work over several backends.
+///
+/// Invoices are created on the first node that succeeds, so an operator
+/// running e.g. LND plus a custodial fallback keeps taking payments when one
+/// backend is down. [`subscribe_invoices`](LightningNode::subscribe_invoices)
+/// merges the update streams of every node into one, dropping settlements
+/// already reported by another node (see [`DedupStream`]). Nodes that are down
+/// are logged and skipped by lookups and subscriptions.
+pub struct FailoverNode {
+    nodes: Vec<Box<dyn LightningNode>>,
+}
+
+impl FailoverNode {
+    /// Create a failover node trying `nodes` in order.
+    pub fn new(nodes: Vec<Box<dyn LightningNode>>) -> Self {
+        Self { nodes }
+    }
+
+    /// The wrapped nodes, in priority order.
+    pub fn nodes(&self) -> &[Box<dyn LightningNode>] {
+        &self.nodes
+    }
+}
+
+#[async_trait]
+impl LightningNode for FailoverNode {
+    async fn add_invoice(&self, req: AddInvoiceRequest) -> Result<AddInvoiceResponse> {
+        let mut last_err = anyhow!("No lightning nodes configured");
+        for node in &self.nodes {
+            match node.add_invoice(req.clone()).await {
+                Ok(rsp) => return Ok(rsp),
+                Err(e) => {
+                    log::warn!("Failed to create invoice, trying next node: {}", e);
+                    last_err = e;
+                }
+            }
+        }
+        Err(last_err)
+    }
+
+    /// Cancel the invoice on every node, succeeding if any node canceled it.
+    async fn cancel_invoice(&self, id: &[u8]) -> Result<()> {
+        let mut last_err = anyhow!("No lightning nodes configured");
+        let mut canceled = false;
+        for node in &self.nodes {
+            match node.cancel_invoice(id).await {
+                Ok(()) => canceled = true,
+                Err(e) => last_err = e,
+            }
+        }
+        if canceled { Ok(()) } else { Err(last_err) }
+    }
+
+    /// Return the state from the first node that knows the invoice.
+    ///
+    /// Nodes that fail are logged and skipped, the lookup only fails if every
+    /// node failed.
+    async fn lookup_invoice(&self, payment_hash: &[u8]) -> Result<Option<InvoiceUpdate>> {
+        let mut last_err = None;
+        let mut answered = self.nodes.is_empty();
+        for node in &self.nodes {
+            match node.lookup_invoice(payment_hash).await {
+                Ok(Some(update)) => return Ok(Some(update)),
+                Ok(None) => answered = true,
+                Err(e) => {
+                    log::warn!("Failed to look up invoice, trying next node: {}", e);
+                    last_err = Some(e);
+                }
+            }
+        }
+        match last_err {
+            Some(e) if !answered => Err(e),
+            _ => Ok(None),
+        }
+    }
+
+    /// Pay through the first node only.
+    ///
+    /// A failed payment attempt may still be in flight, so retrying on another
+    /// node could pay the invoice twice.
+    async fn pay_invoice(&self, req: PayInvoiceRequest) -> Result<PayInvoiceResponse> {
+        match self.nodes.first() {
+            Some(node) => node.pay_invoice(req).await,
+            None => Err(anyhow!("No lightning nodes configured")),
+        }
+    }
+
+    async fn subscribe_invoices(
+        &self,
+        from_payment_hash: Option<Vec<u8>>,
+    ) -> Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>> {
+        let mut streams = Vec::with_capacity(self.nodes.len());
+        let mut last_err = None;
+        for node in &self.nodes {
+            match node.subscribe_invoices(from_payment_hash.clone()).await {
+                Ok(stream) => streams.push(stream),
+                Err(e) => {
+                    log::warn!("Failed to subscribe to invoices, skipping node: {}", e);
+                    last_err = Some(e);
+                }
+            }
+        }
+        if streams.is_empty()
+            && let Some(e) = last_err
+        {
+            return Err(e);
+        }
+        Ok(Box::pin(
+            futures::stream::select_all(streams).dedup(DEFAULT_DEDUP_WINDOW),
+        ))
+    }
+}
+
+/// Default window used to suppress repeated settlement events.
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(600);
+
+/// Stream adapter dropping repeated [`InvoiceUpdate::Settled`] and
+/// [`InvoiceUpdate::Canceled`] events.
+///
+/// The same settlement can surface more than once, e.g. when several backends
+/// watch the shared webhook bridge or when merging node streams. An event is
+/// suppressed if the same kind of event was already seen for its payment hash
+/// within the window. All other updates pass through unchanged.
+///
+/// Created with [`InvoiceUpdateExt::dedup`].
+pub struct DedupStream<S> {
+    inner: S,
+    window: Duration,
+    seen: HashMap<(bool, String), Instant>,
+}
+
+impl<S> DedupStream<S> {
+    /// Wrap `inner`, suppressing repeats seen within `window`.
+    pub fn new(inner: S, window: Duration) -> Self {
+        Self {
+            inner,
+            window,
+            seen: HashMap::new(),
+        }
+    }
+
+    /// Record `update`, returning `true` if it repeats a recent event.
+    fn is_duplicate(&mut self, update: &InvoiceUpdate) -> bool {
+        let key = match update {
+            InvoiceUpdate::Settled { payment_hash, .. } => (true, payment_hash.clone()),
+            InvoiceUpdate::Canceled { payment_hash } => (false, payment_hash.clone()),
+            _ => return false,
+        };
+        let window = self.window;
+        self.seen.retain(|_, at| at.elapsed() < window);
+        self.seen.insert(key, Instant::now()).is_some()
+    }
+}
+
+impl<S> Stream for DedupStream<S>
+where
+    S: Stream<Item = InvoiceUpdate> + Unpin,
+{
+    type Item = InvoiceUpdate;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(update)) => {
+                    if !self.is_duplicate(&update) {
+                        return Poll::Ready(Some(update));
+                    }
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+/// Stream adapter yielding only updates for a set of payment hashes.
+///
+/// Updates without a payment hash ([`InvoiceUpdate::Error`] and
+/// [`InvoiceUpdate::InvalidInvoice`]) are passed through, as they may concern
+/// any invoice.
+///
+/// Created with [`InvoiceUpdateExt::only_payment_hashes`].
+pub struct PaymentHashFilter<S> {
+    inner: S,
+    payment_hashes: HashSet<String>,
+}
+
+impl<S> PaymentHashFilter<S> {
+    /// Wrap `inner`, keeping only updates for `payment_hashes`.
+    pub fn new(inner: S, payment_hashes: HashSet<Vec<u8>>) -> Self {
+        Self {
+            inner,
+            payment_hashes: payment_hashes.iter().map(hex::encode).collect(),
+        }
+    }
+}
+
+impl<S> Stream for PaymentHashFilter<S>
+where
+    S: Stream<Item = InvoiceUpdate> + Unpin,
+{
+    type Item = InvoiceUpdate;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match Pin::new(&mut self.inner).poll_next(cx) {
+                Poll::Ready(Some(update)) => {
+                    let wanted = update
+                        .payment_hash()
+                        .is_none_or(|h| self.payment_hashes.contains(&h.to_lowercase()));
+                    if wanted {
+                        return Poll::Ready(Some(update));
+                    }
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+/// Stops a [`Stoppable`] stream, see [`InvoiceUpdateExt::stoppable`].
+///
+/// Clones stop the same stream.
+#[derive(Clone, Default)]
+pub struct SubscriptionHandle {
+    stopped: Arc<AtomicBool>,
+    waker: Arc<AtomicWaker>,
+}
+
+impl SubscriptionHandle {
+    /// Stop the subscription, waking the task polling the stream so it sees
+    /// the end of the stream right away.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Release);
+        self.waker.wake();
+    }
+
+    /// Whether [`stop`](Self::stop) was called.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Acquire)
+    }
+}
+
+/// Stream adapter that ends when its [`SubscriptionHandle`] is stopped.
+///
+/// On the first poll after [`SubscriptionHandle::stop`] the inner stream is
+/// dropped, which closes the backend subscription (e.g. cancels the LND gRPC
+/// call), and the stream ends. Updates already returned are unaffected, but
+/// any the backend had buffered and not yet yielded are discarded: resume with
+/// `from_payment_hash` or [`LightningNode::lookup_invoice`] after a restart.
+///
+/// Created with [`InvoiceUpdateExt::stoppable`].
+pub struct Stoppable<S> {
+    inner: Option<S>,
+    handle: SubscriptionHandle,
+}
+
+impl<S> Stream for Stoppable<S>
+where
+    S: Stream<Item = InvoiceUpdate> + Unpin,
+{
+    type Item = InvoiceUpdate;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.handle.waker.register(cx.waker());
+        if self.handle.is_stopped() {
+            self.inner = None;
+        }
+        match self.inner.as_mut() {
+            Some(inner) => Pin::new(inner).poll_next(cx),
+            None => Poll::Ready(None),
+        }
+    }
+}
+
+/// Extension methods for streams of [`InvoiceUpdate`]s.
+pub trait InvoiceUpdateExt: Stream<Item = InvoiceUpdate> + Sized {
+    /// Suppress repeated settled/canceled events for a payment hash within
+    /// `window`. See [`DedupStream`].
+    fn dedup(self, window: Duration) -> DedupStream<Self> {
+        DedupStream::new(self, window)
+    }
+
+    /// Only yield updates for invoices in `payment_hashes`. See
+    /// [`PaymentHashFilter`].
+    fn only_payment_hashes(self, payment_hashes: HashSet<Vec<u8>>) -> PaymentHashFilter<Self> {
+        PaymentHashFilter::new(self, payment_hashes)
+    }
+
+    /// End the stream on demand with the returned handle. See [`Stoppable`].
+    fn stoppable(self) -> (Stoppable<Self>, SubscriptionHandle) {
+        let handle = SubscriptionHandle::default();
+        let stream = Stoppable {
+            inner: Some(self),
+            handle: handle.clone(),
+        };
+        (stream, handle)
+    }
+}
+
+impl<S: Stream<Item = InvoiceUpdate>> InvoiceUpdateExt for S {}
+
+/// Helpers shared by the Lightning tests across the crate.
+#[cfg(test)]
+pub(crate) mod test_util {
+    use super::*;
+    use bitcoin::hashes::{Hash, sha256};
+    use bitcoin::secp256k1::{Secp256k1, SecretKey};
+    use lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+    use std::time::UNIX_EPOCH;
+
+    /// Build a signed regtest-style BOLT11 invoice for `amount_msat` whose
+    /// payment hash is `[hash_byte; 32]`.
+    pub(crate) fn signed_invoice(amount_msat: u64, memo: &str, hash_byte: u8) -> Bolt11Invoice {
+        signed_invoice_at(amount_msat, memo, hash_byte, SystemTime::now())
+    }
+
+    /// Like [`signed_invoice`], but created at `timestamp` with a one hour
+    /// expiry.
+    pub(crate) fn signed_invoice_at(
+        amount_msat: u64,
+        memo: &str,
+        hash_byte: u8,
+        timestamp: SystemTime,
+    ) -> Bolt11Invoice {
+        let secp = Secp256k1::new();
+        let key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        InvoiceBuilder::new(Currency::Regtest)
+            .description(memo.to_string())
+            .payment_hash(sha256::Hash::from_byte_array([hash_byte; 32]))
+            .payment_secret(PaymentSecret([0x11; 32]))
+            .duration_since_epoch(timestamp.duration_since(UNIX_EPOCH).unwrap())
+            .expiry_time(Duration::from_secs(3600))
+            .min_final_cltv_expiry_delta(144)
+            .amount_milli_satoshis(amount_msat)
+            .build_signed(|hash| secp.sign_ecdsa_recoverable(hash, &key))
+            .unwrap()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use futures::StreamExt;
+    use std::sync::atomic::AtomicUsize;
+
+    fn request(amount: u64) -> AddInvoiceRequest {
+        AddInvoiceRequest {
+            amount,
+            memo: None,
+            expire: None,
+            idempotency_key: None,
+        }
+    }
+
+    fn settled(hash: &str) -> InvoiceUpdate {
+        InvoiceUpdate::Settled {
+            payment_hash: hash.to_string(),
+            preimage: None,
+            external_id: None,
+            payment_request: None,
+            settled_at: None,
+            amount_msat: None,
+            memo: None,
+        }
+    }
+
+    #[test]
+    fn test_settled_fiat_amount() {
+        use crate::currency::StaticRateConverter;
+
+        let quote =
+            StaticRateConverter::new().with_rate(Currency::USD, Currency::BTC, 1.0 / 50_000.0);
+        // 21k sats at 50k USD/BTC = $10.50
+        let usd = settled_fiat_amount(21_000_000, Currency::USD, &quote).unwrap();
+        assert_eq!(usd, CurrencyAmount::from_u64(Currency::USD, 1050));
+
+        assert!(settled_fiat_amount(21_000_000, Currency::EUR, &quote).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_add_invoice_idempotency_key_returns_same_invoice() {
+        let mock = MockLightningNode::new();
+        let node = IdempotentNode::new(Arc::new(mock.clone()));
+        let req = AddInvoiceRequest {
+            amount: 1000,
+            memo: None,
+            expire: None,
+            idempotency_key: None,
+        }
+        .with_idempotency_key("order-1");
+        let first = node.add_invoice(req.clone()).await.unwrap();
+        let second = node.add_invoice(req.clone()).await.unwrap();
+        assert_eq!(first.payment_hash(), second.payment_hash());
+
+        let other = node
+            .add_invoice(req.with_idempotency_key("order-2"))
+            .await
+            .unwrap();
+        assert_ne!(first.payment_hash(), other.payment_hash());
+        assert_eq!(mock.invoices().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_cache_expires_entries() {
+        let node =
+            IdempotentNode::new(Arc::new(MockLightningNode::new())).with_window(Duration::ZERO);
+        let req = AddInvoiceRequest {
+            amount: 1000,
+            memo: None,
+            expire: None,
+            idempotency_key: Some("order-1".to_string()),
+        };
+        let first = node.add_invoice(req.clone()).await.unwrap();
+        let second = node.add_invoice(req).await.unwrap();
+        assert_ne!(first.payment_hash(), second.payment_hash());
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key_reserved_before_invoice_is_created() {
+        let cache = IdempotencyCache::default();
+        let created = Arc::new(AtomicUsize::new(0));
+        let create = |req: AddInvoiceRequest| {
+            let created = created.clone();
+            async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let n = created.fetch_add(1, Ordering::SeqCst) as u8 + 1;
+                let invoice = test_util::signed_invoice(req.amount, "", n);
+                AddInvoiceResponse::from_invoice(&invoice.to_string(), None)
+            }
+            .boxed()
+        };
+        let req = AddInvoiceRequest {
+            amount: 1000,
+            memo: None,
+            expire: None,
+            idempotency_key: Some("order-1".to_string()),
+        };
+
+        // the first caller times out and retries while the invoice is created
+        let timed_out = tokio::time::timeout(
+            Duration::from_millis(10),
+            cache.get_or_create(req.clone(), create),
+        )
+        .await;
+        assert!(timed_out.is_err());
+        let (a, b) = tokio::join!(
+            cache.get_or_create(req.clone(), create),
+            cache.get_or_create(req.clone(), create)
+        );
+        assert_eq!(a.unwrap().payment_hash(), b.unwrap().payment_hash());
+        assert_eq!(created.load(Ordering::SeqCst), 1);
+
+        // the same key for another invoice is rejected
+        let mut other = req.clone();
+        other.amount = 2000;
+        let err = cache.get_or_create(other, create).await.unwrap_err();
+        assert!(err.to_string().contains("already used"));
+        assert_eq!(created.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key_released_when_creation_fails() {
+        let cache = IdempotencyCache::default();
+        let req = AddInvoiceRequest {
+            amount: 1000,
+            memo: None,
+            expire: None,
+            idempotency_key: Some("order-1".to_string()),
+        };
+        let err = cache
+            .get_or_create(req.clone(), |_| async { bail!("node is down") }.boxed())
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("node is down"));
+
+        let rsp = cache
+            .get_or_create(req, |req| {
+                async move {
+                    let invoice = test_util::signed_invoice(req.amount, "", 1);
+                    AddInvoiceResponse::from_invoice(&invoice.to_string(), None)
+                }
+                .boxed()
+            })
+            .await
+            .unwrap();
+        assert_eq!(rsp.parsed_invoice.amount_milli_satoshis(), Some(1000));
+    }
+
+    #[test]
+    fn test_invalid_invoice_truncates_input() {
+        let input = format!("lnbc1{}", "x".repeat(200));
+        let err = parse_invoice(&input).unwrap_err();
+        assert_eq!(err.input.len(), InvalidInvoice::MAX_INPUT_LEN + 3);
+        assert!(err.input.starts_with("lnbc1xxx"));
+        assert!(err.input.ends_with("..."));
+        assert!(matches!(err.error, ParseOrSemanticError::ParseError(_)));
+    }
+
+    #[test]
+    fn test_invalid_invoice_short_input_kept() {
+        let err = parse_invoice("not-an-invoice").unwrap_err();
+        assert_eq!(err.input, "not-an-invoice");
+        assert!(
+            err.to_string()
+                .starts_with("Failed to parse invoice not-an-invoice: ")
+        );
+    }
+
+    #[test]
+    fn test_add_invoice_response_from_invalid_invoice() {
+        let err = AddInvoiceResponse::from_invoice("lnbc1garbage", None).unwrap_err();
+        let invalid = err.downcast_ref::<InvalidInvoice>().unwrap();
+        assert_eq!(invalid.input, "lnbc1garbage");
+    }
+
+    #[test]
+    fn test_add_invoice_response_expiry() {
+        use super::test_util::signed_invoice_at;
+
+        let created = SystemTime::now() - Duration::from_secs(7200);
+        let invoice = signed_invoice_at(1_000, "old", 1, created);
+        let rsp = AddInvoiceResponse::from_invoice(&invoice.to_string(), None).unwrap();
+        let created = SystemTime::UNIX_EPOCH + invoice.duration_since_epoch();
+        assert_eq!(rsp.expires_at(), created + Duration::from_secs(3600));
+        assert!(rsp.is_expired());
+
+        let invoice = signed_invoice_at(1_000, "new", 2, SystemTime::now());
+        let rsp = AddInvoiceResponse::from_invoice(&invoice.to_string(), None).unwrap();
+        assert!(rsp.expires_at() > SystemTime::now());
+        assert!(!rsp.is_expired());
+    }
+
+    #[tokio::test]
+    async fn test_add_invoices_keeps_order_and_limits_concurrency() {
+        /// Creates invoices slower the smaller the amount, so they complete
+        /// out of order, and records the peak number of concurrent calls.
+        #[derive(Default)]
+        struct SlowNode {
+            inner: MockLightningNode,
+            in_flight: AtomicUsize,
+            peak: AtomicUsize,
+        }
+
+        #[async_trait]
+        impl LightningNode for SlowNode {
+            async fn add_invoice(&self, req: AddInvoiceRequest) -> Result<AddInvoiceResponse> {
+                let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(40 - req.amount / 1000)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                if req.amount == 5_000 {
+                    bail!("amount rejected");
+                }
+                self.inner.add_invoice(req).await
+            }
+
+            async fn cancel_invoice(&self, _id: &[u8]) -> Result<()> {
+                Ok(())
+            }
+
+            async fn lookup_invoice(&self, _payment_hash: &[u8]) -> Result<Option<InvoiceUpdate>> {
+                Ok(None)
+            }
+
+            async fn pay_invoice(&self, _req: PayInvoiceRequest) -> Result<PayInvoiceResponse> {
+                bail!("slow node cannot pay")
+            }
+
+            async fn subscribe_invoices(
+                &self,
+                _from_payment_hash: Option<Vec<u8>>,
+            ) -> Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>> {
+                Ok(Box::pin(futures::stream::empty()))
+            }
+        }
+
+        let node = SlowNode::default();
+        let reqs = (1..=20)
+            .map(|i| AddInvoiceRequest {
+                amount: i * 1000,
+                memo: None,
+                expire: None,
+                idempotency_key: None,
+            })
+            .collect();
+        let results = node.add_invoices(reqs).await;
+        assert_eq!(results.len(), 20);
+        for (i, res) in results.iter().enumerate() {
+            let amount = (i as u64 + 1) * 1000;
+            match res {
+                Ok(rsp) => assert_eq!(rsp.parsed_invoice.amount_milli_satoshis(), Some(amount)),
+                Err(_) => assert_eq!(amount, 5_000),
+            }
+        }
+        assert!(results[4].is_err());
+        assert_eq!(node.peak.load(Ordering::SeqCst), ADD_INVOICES_CONCURRENCY);
+    }
+
+    #[tokio::test]
+    async fn test_stop_ends_subscription_promptly() {
+        let (mut stream, handle) = futures::stream::pending::<InvoiceUpdate>().stoppable();
+        let stopper = handle.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            stopper.stop();
+        });
+        let next = tokio::time::timeout(Duration::from_secs(1), stream.next()).await;
+        assert!(matches!(next, Ok(None)));
+        assert!(handle.is_stopped());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_invoices_stoppable_drops_inner_stream() {
+        let node = MockLightningNode::new();
+        let (mut stream, handle) = node.subscribe_invoices_stoppable(None).await.unwrap();
+        node.add_invoice(request(1_000)).await.unwrap();
+        assert!(stream.next().await.is_some());
+        handle.stop();
+        assert!(stream.next().await.is_none());
+
+        // the inner stream is released on stop, not when the wrapper is dropped
+        let guard = Arc::new(());
+        let inner = futures::stream::pending::<InvoiceUpdate>().map({
+            let guard = guard.clone();
+            move |u| {
+                let _ = &guard;
+                u
+            }
+        });
+        let (mut stream, handle) = inner.stoppable();
+        handle.stop();
+        assert!(stream.next().await.is_none());
+        assert_eq!(Arc::strong_count(&guard), 1);
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_invoices_for_payment_hashes() {
+        let node = MockLightningNode::new();
+        let mut hashes = vec![];
+        for _ in 0..3 {
+            let inv = node.add_invoice(request(1_000)).await.unwrap();
+            hashes.push(hex::decode(inv.payment_hash()).unwrap());
+        }
+        let stream = node
+            .subscribe_invoices_for(HashSet::from([hashes[0].clone(), hashes[2].clone()]))
+            .await
+            .unwrap();
+        node.settle(&hashes[0]).unwrap();
+        node.settle(&hashes[1]).unwrap();
+        node.cancel(&hashes[2]).unwrap();
+        drop(node);
+
+        let updates: Vec<_> = stream.collect().await;
+        assert_eq!(updates.len(), 2);
+        assert!(
+            matches!(&updates[0], InvoiceUpdate::Settled { payment_hash, .. } if *payment_hash == hex::encode(&hashes[0]))
+        );
+        assert!(
+            matches!(&updates[1], InvoiceUpdate::Canceled { payment_hash } if *payment_hash == hex::encode(&hashes[2]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_payment_hash_filter_keeps_errors_and_ignores_case() {
+        let updates: Vec<_> = futures::stream::iter(vec![
+            settled("bb"),
+            InvoiceUpdate::Error("stream hiccup".to_string()),
+            InvoiceUpdate::Canceled {
+                payment_hash: "AA".to_string(),
+            },
+        ])
+        .only_payment_hashes(HashSet::from([vec![0xaa]]))
+        .collect()
+        .await;
+        assert_eq!(updates.len(), 2);
+        assert!(matches!(&updates[0], InvoiceUpdate::Error(_)));
+        assert!(matches!(&updates[1], InvoiceUpdate::Canceled { .. }));
+    }
+
+    #[tokio::test]
+    async fn test_dedup_suppresses_repeated_settlements() {
+        let updates = futures::stream::iter(vec![
+            settled("aa"),
+            settled("aa"),
+            InvoiceUpdate::Canceled {
+                payment_hash: "bb".to_string(),
+            },
+            settled("bb"),
+            InvoiceUpdate::Canceled {
+                payment_hash: "bb".to_string(),
+            },
+            InvoiceUpdate::Error("boom".to_string()),
+            InvoiceUpdate::Error("boom".to_string()),
+            settled("aa"),
+        ]);
+        let out: Vec<InvoiceUpdate> = updates.dedup(Duration::from_secs(60)).collect().await;
+        assert_eq!(out.len(), 5);
+        assert!(
+            matches!(&out[0], InvoiceUpdate::Settled { payment_hash, .. } if payment_hash == "aa")
+        );
+        assert!(
+            matches!(&out[1], InvoiceUpdate::Canceled { payment_hash } if payment_hash == "bb")
+        );
+        assert!(
+            matches!(&out[2], InvoiceUpdate::Settled { payment_hash, .. } if payment_hash == "bb")
+        );
+        assert!(matches!(out[3], InvoiceUpdate::Error(_)));
+        assert!(matches!(out[4], InvoiceUpdate::Error(_)));
+    }
+
+    #[tokio::test]
+    async fn test_dedup_window_expires() {
+        let updates = futures::stream::iter(vec![settled("aa"), settled("aa")]);
+        let out: Vec<InvoiceUpdate> = updates.dedup(Duration::ZERO).collect().await;
+        assert_eq!(out.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failover_node_dedups_merged_streams() {
+        let (a, b) = (MockLightningNode::new(), MockLightningNode::new());
+        let node = FailoverNode::new(vec![Box::new(a.clone()), Box::new(b.clone())]);
+        let stream = node.subscribe_invoices(None).await.unwrap();
+        // both mocks derive the same payment hash for their first invoice
+        for mock in [&a, &b] {
+            let inv = mock.add_invoice(request(1_000)).await.unwrap();
+            mock.settle(&hex::decode(inv.payment_hash()).unwrap())
+                .unwrap();
+        }
+        drop((node, a, b));
+
+        let out: Vec<InvoiceUpdate> = stream.collect().await;
+        let settled = out
+            .iter()
+            .filter(|u| matches!(u, InvoiceUpdate::Settled { .. }))
+            .count();
+        assert_eq!(settled, 1);
+    }
+
+    #[tokio::test]
+    async fn test_failover_node_add_invoice_skips_failing_node() {
+        let (down, up) = (MockLightningNode::new(), MockLightningNode::new());
+        down.set_offline(true);
+        let node = FailoverNode::new(vec![Box::new(down), Box::new(up.clone())]);
+        let rsp = node.add_invoice(request(5000)).await.unwrap();
+        assert_eq!(rsp.parsed_invoice.amount_milli_satoshis(), Some(5000));
+        assert_eq!(up.invoices().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_failover_node_all_failing() {
+        let down = MockLightningNode::new();
+        down.set_offline(true);
+        let node = FailoverNode::new(vec![Box::new(down)]);
+        let err = node.add_invoice(request(5000)).await.unwrap_err();
+        assert!(err.to_string().contains("mock node is offline"));
+
+        let err = FailoverNode::new(vec![])
+            .add_invoice(request(5000))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("No lightning nodes"));
+    }
+
+    #[tokio::test]
+    async fn test_failover_node_cancel_dispatches_to_all() {
+        let (first, down, last) = (
+            MockLightningNode::new(),
+            MockLightningNode::new(),
+            MockLightningNode::new(),
+        );
+        // the same payment hash exists on the first and last node
+        let hash = first.add_invoice(request(1_000)).await.unwrap();
+        last.add_invoice(request(1_000)).await.unwrap();
+        let hash = hex::decode(hash.payment_hash()).unwrap();
+        down.set_offline(true);
+        let node = FailoverNode::new(vec![
+            Box::new(first.clone()),
+            Box::new(down.clone()),
+            Box::new(last.clone()),
+        ]);
+        node.cancel_invoice(&hash).await.unwrap();
+        for mock in [&first, &last] {
+            assert!(matches!(
+                mock.invoice(&hash).unwrap().state,
+                InvoiceUpdate::Canceled { .. }
+            ));
+        }
+
+        let down = FailoverNode::new(vec![Box::new(down)]);
+        assert!(down.cancel_invoice(&hash).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failover_node_lookup_skips_failing_node() {
+        let (down, up) = (MockLightningNode::new(), MockLightningNode::new());
+        let inv = up.add_invoice(request(1_000)).await.unwrap();
+        let hash = hex::decode(inv.payment_hash()).unwrap();
+        down.set_offline(true);
+        let node = FailoverNode::new(vec![Box::new(down.clone()), Box::new(up)]);
+        assert!(matches!(
+            node.lookup_invoice(&hash).await.unwrap(),
+            Some(InvoiceUpdate::Created { .. })
+        ));
+        assert!(node.lookup_invoice(&[0; 32]).await.unwrap().is_none());
+
+        let down = FailoverNode::new(vec![Box::new(down)]);
+        assert!(down.lookup_invoice(&hash).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failover_node_subscribe_skips_failing_node() {
+        let (down, up) = (MockLightningNode::new(), MockLightningNode::new());
+        down.set_offline(true);
+        let node = FailoverNode::new(vec![Box::new(down.clone()), Box::new(up.clone())]);
+        let stream = node.subscribe_invoices(None).await.unwrap();
+        let inv = up.add_invoice(request(1_000)).await.unwrap();
+        drop((node, up));
+        let out: Vec<InvoiceUpdate> = stream.collect().await;
+        assert_eq!(out.len(), 1);
+        assert_eq!(out[0].payment_hash(), Some(inv.payment_hash().as_str()));
+
+        let down = FailoverNode::new(vec![Box::new(down)]);
+        assert!(down.subscribe_invoices(None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failover_node_merges_streams() {
+        let (a, b) = (MockLightningNode::new(), MockLightningNode::new());
+        let node = FailoverNode::new(vec![Box::new(a.clone()), Box::new(b.clone())]);
+        let stream = node.subscribe_invoices(None).await.unwrap();
+        let mut expected = vec![];
+        for mock in [&a, &a, &b] {
+            expected.push(
+                mock.add_invoice(request(1_000))
+                    .await
+                    .unwrap()
+                    .payment_hash(),
+            );
+        }
+        drop((node, a, b));
+
+        let mut hashes: Vec<String> = stream
+            .map(|u| match u {
+                InvoiceUpdate::Created { payment_hash, .. } => payment_hash,
+                other => panic!("Unexpected update {:?}", other),
+            })
+            .collect()
+            .await;
+        hashes.sort();
+        expected.sort();
+        assert_eq!(hashes, expected);
+    }
 
     #[test]
     fn test_add_invoice_request_clone() {
@@ -175,6 +1592,7 @@ mod tests {
             amount: 1000,
             memo: Some("Test payment".to_string()),
             expire: Some(3600),
+            idempotency_key: None,
         };
         let cloned = req.clone();
         assert_eq!(cloned.amount, 1000);
@@ -188,11 +1606,97 @@ mod tests {
             amount: 1000,
             memo: None,
             expire: None,
+            idempotency_key: None,
         };
         let debug_str = format!("{:?}", req);
         assert!(debug_str.contains("1000"));
     }
 
+    #[test]
+    fn test_render_memo_substitution() {
+        let vars = HashMap::from([
+            ("order_id".to_string(), "123".to_string()),
+            ("description".to_string(), "VPS".to_string()),
+        ]);
+        assert_eq!(
+            render_memo("Order #{order_id}: {description}", &vars),
+            "Order #123: VPS"
+        );
+    }
+
+    #[test]
+    fn test_render_memo_missing_variable_left_as_is() {
+        let vars = HashMap::from([("order_id".to_string(), "123".to_string())]);
+        assert_eq!(render_memo("{order_id} {missing}", &vars), "123 {missing}");
+        // An unterminated placeholder is kept verbatim
+        assert_eq!(render_memo("{order_id", &vars), "{order_id");
+    }
+
+    #[test]
+    fn test_render_memo_escaped_braces() {
+        let vars = HashMap::from([("order_id".to_string(), "123".to_string())]);
+        assert_eq!(
+            render_memo("{{order_id}} = {order_id}", &vars),
+            "{order_id} = 123"
+        );
+        assert_eq!(render_memo("a } b", &vars), "a } b");
+    }
+
+    #[test]
+    fn test_add_invoice_request_from_currency_fiat() {
+        use crate::currency::StaticRateConverter;
+
+        let conv = StaticRateConverter::new().with_rate(Currency::BTC, Currency::USD, 50_000.0);
+        let req = AddInvoiceRequest::from_currency(
+            CurrencyAmount::from_u64(Currency::USD, 1000),
+            &conv,
+            Some("Order #1".to_string()),
+        )
+        .unwrap();
+        // $10.00 at 50k USD/BTC = 20k sats
+        assert_eq!(req.amount, 20_000_000);
+        assert_eq!(req.memo, Some("Order #1".to_string()));
+        assert_eq!(req.expire, None);
+    }
+
+    #[test]
+    fn test_add_invoice_request_from_currency_btc() {
+        use crate::currency::StaticRateConverter;
+
+        let req = AddInvoiceRequest::from_currency(
+            CurrencyAmount::millisats(5000),
+            &StaticRateConverter::new(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(req.amount, 5000);
+    }
+
+    #[test]
+    fn test_add_invoice_request_from_currency_missing_rate() {
+        use crate::currency::StaticRateConverter;
+
+        let result = AddInvoiceRequest::from_currency(
+            CurrencyAmount::from_u64(Currency::EUR, 1000),
+            &StaticRateConverter::new(),
+            None,
+        );
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_add_invoice_request_with_memo_template() {
+        let vars = HashMap::from([("order_id".to_string(), "42".to_string())]);
+        let req = AddInvoiceRequest {
+            amount: 1000,
+            memo: None,
+            expire: None,
+            idempotency_key: None,
+        }
+        .with_memo_template("Order #{order_id}", &vars);
+        assert_eq!(req.memo, Some("Order #42".to_string()));
+    }
+
     #[test]
     fn test_add_invoice_response_from_invoice_invalid() {
         let result = AddInvoiceResponse::from_invoice("invalid_invoice", None);
@@ -271,17 +1775,83 @@ mod tests {
         }
     }
 
+    fn description_hash_invoice(hash: [u8; 32]) -> String {
+        use bitcoin::hashes::{Hash, sha256};
+        use bitcoin::secp256k1::{Secp256k1, SecretKey};
+        use lightning_invoice::{Currency, InvoiceBuilder, PaymentSecret};
+
+        let secp = Secp256k1::new();
+        let key = SecretKey::from_slice(&[0x42; 32]).unwrap();
+        InvoiceBuilder::new(Currency::Regtest)
+            .description_hash(sha256::Hash::from_byte_array(hash))
+            .payment_hash(sha256::Hash::from_byte_array([7; 32]))
+            .payment_secret(PaymentSecret([0x11; 32]))
+            .duration_since_epoch(
+                std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap(),
+            )
+            .min_final_cltv_expiry_delta(144)
+            .amount_milli_satoshis(1_000)
+            .build_signed(|h| secp.sign_ecdsa_recoverable(h, &key))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_verify_description_hash() {
+        let expected = [0xab; 32];
+        let settled = |pr: Option<String>| InvoiceUpdate::Settled {
+            payment_hash: hex::encode([7; 32]),
+            preimage: None,
+            external_id: None,
+            payment_request: pr,
+            settled_at: None,
+            amount_msat: None,
+            memo: None,
+        };
+
+        let update = settled(Some(description_hash_invoice(expected)));
+        assert!(verify_description_hash(&update, &expected).is_ok());
+
+        let swapped = settled(Some(description_hash_invoice([0xcd; 32])));
+        let err = verify_description_hash(&swapped, &expected).unwrap_err();
+        assert!(err.to_string().contains("mismatch"));
+
+        // plain description, missing invoice and foreign invoice all fail
+        let plain = settled(Some(
+            test_util::signed_invoice(1_000, "memo", 7).to_string(),
+        ));
+        assert!(verify_description_hash(&plain, &expected).is_err());
+        assert!(verify_description_hash(&settled(None), &expected).is_err());
+        let other = InvoiceUpdate::Settled {
+            payment_hash: hex::encode([8; 32]),
+            preimage: None,
+            external_id: None,
+            payment_request: Some(description_hash_invoice(expected)),
+            settled_at: None,
+            amount_msat: None,
+            memo: None,
+        };
+        assert!(verify_description_hash(&other, &expected).is_err());
+    }
+
     #[test]
     fn test_invoice_update_settled() {
         let update = InvoiceUpdate::Settled {
             payment_hash: "abc123".to_string(),
             preimage: Some("preimage456".to_string()),
             external_id: Some("ext789".to_string()),
+            payment_request: None,
+            settled_at: None,
+            amount_msat: None,
+            memo: None,
         };
         if let InvoiceUpdate::Settled {
             payment_hash,
             preimage,
             external_id,
+            ..
         } = update
         {
             assert_eq!(payment_hash, "abc123");
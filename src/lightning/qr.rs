@@ -0,0 +1,108 @@
+//! QR code rendering for payment requests (`qr` feature).
+
+use anyhow::{Result, anyhow, bail};
+use qrcode::render::svg;
+use qrcode::{Color, EcLevel, QrCode};
+
+/// Pixels per QR module in the PNG output
+const MODULE_PX: usize = 8;
+/// Blank modules around the code, as required by the QR spec
+const QUIET_ZONE: usize = 4;
+
+fn encode(pr: &str) -> Result<QrCode> {
+    let pr = pr.trim();
+    if pr.is_empty() {
+        bail!("Cannot create QR code for an empty payment request");
+    }
+    // bech32 is case-insensitive; uppercase lets the encoder use the denser
+    // alphanumeric mode instead of byte mode.
+    QrCode::with_error_correction_level(pr.to_uppercase(), EcLevel::M)
+        .map_err(|e| anyhow!("Failed to encode QR code: {}", e))
+}
+
+/// Render a payment request (bolt11, LNURL, `lightning:` URI) as a PNG QR code.
+pub fn invoice_qr_png(pr: &str) -> Result<Vec<u8>> {
+    let code = encode(pr)?;
+    let modules = code.width();
+    let colors = code.to_colors();
+    let size = (modules + QUIET_ZONE * 2) * MODULE_PX;
+
+    let mut pixels = vec![0xffu8; size * size];
+    for (i, color) in colors.iter().enumerate() {
+        if *color != Color::Dark {
+            continue;
+        }
+        let x0 = (i % modules + QUIET_ZONE) * MODULE_PX;
+        let y0 = (i / modules + QUIET_ZONE) * MODULE_PX;
+        for y in y0..y0 + MODULE_PX {
+            pixels[y * size + x0..y * size + x0 + MODULE_PX].fill(0);
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, size as u32, size as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(out)
+}
+
+/// Render a payment request as an SVG QR code.
+pub fn invoice_qr_svg(pr: &str) -> Result<String> {
+    let code = encode(pr)?;
+    Ok(code
+        .render::<svg::Color>()
+        .quiet_zone(true)
+        .min_dimensions(256, 256)
+        .build())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lightning::test_util::signed_invoice;
+
+    #[test]
+    fn test_invoice_qr_png() {
+        let pr = signed_invoice(1_000, "qr", 1).to_string();
+        let png = invoice_qr_png(&pr).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+
+        let decoder = png::Decoder::new(png.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!(info.width, info.height);
+        assert_eq!(info.width as usize % MODULE_PX, 0);
+        // the quiet zone is blank and the finder pattern corner is dark
+        let corner = QUIET_ZONE * MODULE_PX;
+        assert_eq!(buf[0], 0xff);
+        assert_eq!(buf[corner * info.width as usize + corner], 0);
+    }
+
+    #[test]
+    fn test_invoice_qr_uppercase_matches_lowercase() {
+        let pr = signed_invoice(1_000, "qr", 2).to_string();
+        assert_eq!(
+            invoice_qr_png(&pr).unwrap(),
+            invoice_qr_png(&pr.to_uppercase()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invoice_qr_svg() {
+        let pr = signed_invoice(1_000, "qr", 3).to_string();
+        let svg = invoice_qr_svg(&pr).unwrap();
+        assert!(svg.contains("<svg"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn test_invoice_qr_empty_input() {
+        assert!(invoice_qr_png("").is_err());
+        assert!(invoice_qr_png("   ").is_err());
+        assert!(invoice_qr_svg("").is_err());
+    }
+}
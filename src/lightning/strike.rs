@@ -0,0 +1,566 @@
+//! Strike Lightning payment provider integration.
+//!
+//! Invoices are created in two steps: a Strike invoice holding the amount
+//! (in USD or BTC), then a quote for it which carries the BOLT11 invoice. For
+//! fiat amounts the quote locks the exchange rate only briefly (about 30
+//! seconds for USD), so use [`StrikeNode::create_invoice`] with a fiat
+//! [`CurrencyAmount`] when the customer pays right away.
+//!
+//! Strike webhooks only carry the id of a changed invoice, so
+//! [`LightningNode::subscribe_invoices`] fetches the invoice state for each
+//! event and reports invoices created by this client.
+
+use crate::currency::{Currency, CurrencyAmount};
+use crate::json_api::{JsonApi, JsonApiError};
+use crate::lightning::{
+    AddInvoiceRequest, AddInvoiceResponse, DEFAULT_DEDUP_WINDOW, InvoiceUpdate, InvoiceUpdateExt,
+    LightningNode, PayInvoiceRequest, PayInvoiceResponse,
+};
+use crate::webhook::{
+    WEBHOOK_BRIDGE, WebhookMessage, WebhookPathMatcher, WebhookVerifier, verify_hmac_sha256,
+};
+use anyhow::{Result, anyhow, bail, ensure};
+use async_trait::async_trait;
+use futures::{Stream, StreamExt};
+use log::{info, warn};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+
+/// Strike Lightning payment node client.
+#[derive(Clone)]
+pub struct StrikeNode {
+    api: JsonApi,
+    webhook_secret: String,
+    /// Matches the request path of Strike webhooks
+    webhook_path: WebhookPathMatcher,
+    /// Invoices created by this client by Strike invoice id
+    invoices: Arc<Mutex<HashMap<String, StrikeInvoiceRef>>>,
+}
+
+/// Payment hash and BOLT11 of a Strike invoice
+#[derive(Debug, Clone)]
+struct StrikeInvoiceRef {
+    payment_hash: String,
+    payment_request: String,
+}
+
+/// Configuration of a [`StrikeNode`].
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StrikeConfig {
+    /// API base url, defaults to `https://api.strike.me/`
+    pub url: Option<String>,
+    /// Strike API key
+    pub api_key: String,
+    /// Secret of the webhook subscription, for verifying signatures
+    pub webhook_secret: String,
+    /// The URL path where webhooks will be received
+    pub webhook_path: String,
+    /// Accept invalid TLS certificates, e.g. of a local sandbox
+    #[serde(default)]
+    pub allow_invalid_certs: bool,
+}
+
+impl StrikeNode {
+    /// Create a new Strike node client.
+    ///
+    /// # Arguments
+    ///
+    /// * `api_key` - Strike API key
+    /// * `webhook_secret` - Secret of the webhook subscription
+    /// * `webhook_path` - The URL path where webhooks will be received
+    pub fn new(api_key: &str, webhook_secret: &str, webhook_path: &str) -> Result<Self> {
+        Self::from_config(StrikeConfig {
+            url: None,
+            api_key: api_key.to_string(),
+            webhook_secret: webhook_secret.to_string(),
+            webhook_path: webhook_path.to_string(),
+            allow_invalid_certs: false,
+        })
+    }
+
+    /// Create a new Strike node client from `config`.
+    ///
+    /// Fails if the url or API key are invalid.
+    pub fn from_config(config: StrikeConfig) -> Result<Self> {
+        const DEFAULT_URL: &str = "https://api.strike.me/";
+
+        let url = config.url.as_deref().unwrap_or(DEFAULT_URL);
+        let auth = format!("Bearer {}", config.api_key);
+        Ok(Self {
+            api: JsonApi::token(url, &auth, config.allow_invalid_certs)
+                .map_err(|e| anyhow!("Invalid Strike config {}: {}", url, e))?,
+            webhook_secret: config.webhook_secret,
+            webhook_path: config.webhook_path.as_str().into(),
+            invoices: Arc::new(Mutex::new(HashMap::new())),
+        })
+    }
+
+    /// Match webhook requests with `matcher` instead of the exact
+    /// `webhook_path`.
+    pub fn with_webhook_path(mut self, matcher: WebhookPathMatcher) -> Self {
+        self.webhook_path = matcher;
+        self
+    }
+
+    /// Create an invoice for `amount` in USD or BTC.
+    ///
+    /// Fiat amounts are passed to Strike as is and converted when quoted, so
+    /// the BOLT11 amount follows Strike's exchange rate.
+    /// `correlation_id` is an optional unique reference, e.g. an order id.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn create_invoice(
+        &self,
+        amount: CurrencyAmount,
+        description: Option<String>,
+        correlation_id: Option<String>,
+    ) -> Result<AddInvoiceResponse> {
+        let invoice: StrikeInvoice = self
+            .api
+            .post(
+                "/v1/invoices",
+                CreateInvoiceRequest {
+                    correlation_id,
+                    description,
+                    amount: StrikeAmount::try_from(&amount)?,
+                },
+            )
+            .await?;
+        let quote: StrikeQuote = self
+            .api
+            .post(
+                &format!("/v1/invoices/{}/quote", invoice.invoice_id),
+                serde_json::json!({}),
+            )
+            .await?;
+        let rsp = AddInvoiceResponse::from_invoice(&quote.ln_invoice, Some(invoice.invoice_id))?;
+        if let Some(id) = &rsp.external_id {
+            self.invoices.lock().unwrap().insert(
+                id.clone(),
+                StrikeInvoiceRef {
+                    payment_hash: rsp.payment_hash(),
+                    payment_request: rsp.pr(),
+                },
+            );
+        }
+        Ok(rsp)
+    }
+
+    /// Get an invoice by its Strike id, `None` if it doesn't exist
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    pub async fn get_invoice(&self, invoice_id: &str) -> Result<Option<StrikeInvoice>> {
+        match self
+            .api
+            .req::<_, ()>(Method::GET, &format!("/v1/invoices/{}", invoice_id), None)
+            .await
+        {
+            Err(JsonApiError::Http { status: 404, .. }) => Ok(None),
+            r => Ok(Some(r?)),
+        }
+    }
+
+    /// Strike id of an invoice created by this client
+    fn invoice_id(&self, payment_hash: &[u8]) -> Option<String> {
+        let payment_hash = hex::encode(payment_hash);
+        self.invoices
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, r)| r.payment_hash == payment_hash)
+            .map(|(id, _)| id.clone())
+    }
+
+    /// Map an invoice created by this client onto the matching [`InvoiceUpdate`]
+    fn invoice_update(&self, invoice: StrikeInvoice) -> InvoiceUpdate {
+        let Some(r) = self
+            .invoices
+            .lock()
+            .unwrap()
+            .get(&invoice.invoice_id)
+            .cloned()
+        else {
+            return InvoiceUpdate::Unknown {
+                payment_hash: String::new(),
+            };
+        };
+        invoice.into_update(r)
+    }
+}
+
+#[async_trait]
+impl LightningNode for StrikeNode {
+    /// Create a BTC invoice, the amount must be in whole satoshis.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn add_invoice(&self, req: AddInvoiceRequest) -> Result<AddInvoiceResponse> {
+        self.create_invoice(
+            CurrencyAmount::millisats(req.amount),
+            req.memo,
+            req.idempotency_key,
+        )
+        .await
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn cancel_invoice(&self, id: &[u8]) -> Result<()> {
+        let Some(invoice_id) = self.invoice_id(id) else {
+            bail!("Unknown invoice {}", hex::encode(id));
+        };
+        let _: StrikeInvoice = self
+            .api
+            .req(
+                Method::PATCH,
+                &format!("/v1/invoices/{}/cancel", invoice_id),
+                None::<()>,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Only invoices created by this client can be looked up by payment hash.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn lookup_invoice(&self, payment_hash: &[u8]) -> Result<Option<InvoiceUpdate>> {
+        let Some(invoice_id) = self.invoice_id(payment_hash) else {
+            return Ok(None);
+        };
+        Ok(self
+            .get_invoice(&invoice_id)
+            .await?
+            .map(|i| self.invoice_update(i)))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn pay_invoice(&self, _req: PayInvoiceRequest) -> Result<PayInvoiceResponse> {
+        bail!("Paying invoices is not supported by the Strike backend")
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip_all))]
+    async fn subscribe_invoices(
+        &self,
+        _from_payment_hash: Option<Vec<u8>>,
+    ) -> Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>> {
+        let rx = WEBHOOK_BRIDGE.listen_path(self.webhook_path.clone());
+        let node = self.clone();
+        let mapped = rx.then(move |r| {
+            let node = node.clone();
+            async move {
+                info!("Received webhook {}", String::from_utf8_lossy(&r.body));
+                if let Err(e) = verify_webhook(&node.webhook_secret, &r) {
+                    return InvoiceUpdate::Error(e.to_string());
+                }
+                let event: StrikeWebhookEvent = match serde_json::from_slice(&r.body) {
+                    Ok(e) => e,
+                    Err(e) => return InvoiceUpdate::Error(e.to_string()),
+                };
+                if !event.event_type.starts_with("invoice.") {
+                    return InvoiceUpdate::Unknown {
+                        payment_hash: String::new(),
+                    };
+                }
+                match node.get_invoice(&event.data.entity_id).await {
+                    Ok(Some(invoice)) => node.invoice_update(invoice),
+                    Ok(None) => {
+                        InvoiceUpdate::Error(format!("Invoice {} not found", event.data.entity_id))
+                    }
+                    Err(e) => InvoiceUpdate::Error(e.to_string()),
+                }
+            }
+        });
+        // webhook providers redeliver events, only surface each settlement once
+        Ok(Box::pin(Box::pin(mapped).dedup(DEFAULT_DEDUP_WINDOW)))
+    }
+}
+
+/// An amount as sent to and returned by Strike
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StrikeAmount {
+    /// Decimal amount in the standard unit, e.g. `"10.50"`
+    pub amount: String,
+    pub currency: String,
+}
+
+impl TryFrom<&CurrencyAmount> for StrikeAmount {
+    type Error = anyhow::Error;
+
+    fn try_from(amount: &CurrencyAmount) -> Result<Self> {
+        let value = match amount.currency() {
+            Currency::BTC => {
+                ensure!(
+                    amount.value().is_multiple_of(1000),
+                    "Strike BTC amounts must be whole satoshis"
+                );
+                let sats = amount.value() / 1000;
+                format!("{}.{:08}", sats / 100_000_000, sats % 100_000_000)
+            }
+            Currency::USD => amount.to_decimal_string(),
+            c => bail!("Strike does not support {} invoices", c),
+        };
+        Ok(Self {
+            amount: value,
+            currency: amount.currency().to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateInvoiceRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub amount: StrikeAmount,
+}
+
+/// A Strike invoice
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StrikeInvoice {
+    pub invoice_id: String,
+    pub amount: StrikeAmount,
+    pub state: StrikeInvoiceState,
+    #[serde(default)]
+    pub correlation_id: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+impl StrikeInvoice {
+    fn into_update(self, r: StrikeInvoiceRef) -> InvoiceUpdate {
+        match self.state {
+            StrikeInvoiceState::Paid => InvoiceUpdate::Settled {
+                payment_hash: r.payment_hash,
+                preimage: None,
+                external_id: Some(self.invoice_id),
+                payment_request: Some(r.payment_request),
+                settled_at: None,
+                amount_msat: None,
+                memo: self.description,
+            },
+            StrikeInvoiceState::Cancelled => InvoiceUpdate::Canceled {
+                payment_hash: r.payment_hash,
+            },
+            StrikeInvoiceState::Unpaid | StrikeInvoiceState::Pending => InvoiceUpdate::Created {
+                payment_hash: r.payment_hash,
+                payment_request: r.payment_request,
+            },
+            StrikeInvoiceState::Unknown => InvoiceUpdate::Unknown {
+                payment_hash: r.payment_hash,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum StrikeInvoiceState {
+    Unpaid,
+    Pending,
+    Paid,
+    Cancelled,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StrikeQuote {
+    #[allow(dead_code)]
+    pub quote_id: String,
+    pub ln_invoice: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StrikeWebhookEvent {
+    /// e.g. `invoice.created` or `invoice.updated`
+    pub event_type: String,
+    pub data: StrikeWebhookData,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StrikeWebhookData {
+    pub entity_id: String,
+}
+
+impl WebhookVerifier for StrikeNode {
+    fn verify(&self, msg: &WebhookMessage) -> Result<()> {
+        verify_webhook(&self.webhook_secret, msg)
+    }
+}
+
+fn verify_webhook(secret: &str, msg: &WebhookMessage) -> Result<()> {
+    let sig = msg
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("x-webhook-signature"))
+        .map(|(_, v)| v)
+        .ok_or_else(|| anyhow!("Missing x-webhook-signature header"))?;
+
+    verify_hmac_sha256(secret.as_bytes(), &msg.body, sig)
+        .inspect_err(|_| warn!("Invalid signature found for webhook"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hmac::{Hmac, Mac};
+
+    type HmacSha256 = Hmac<sha2::Sha256>;
+    use crate::lightning::test_util::signed_invoice;
+    use std::time::Duration;
+    use wiremock::matchers::{body_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn node(url: &str) -> StrikeNode {
+        StrikeNode::from_config(StrikeConfig {
+            url: Some(url.to_string()),
+            api_key: "key1".to_string(),
+            webhook_secret: "secret".to_string(),
+            webhook_path: "/webhooks/strike".to_string(),
+            allow_invalid_certs: false,
+        })
+        .unwrap()
+    }
+
+    fn invoice_json(state: &str, amount: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "invoiceId": "inv-1",
+            "amount": amount,
+            "state": state,
+            "description": "coffee"
+        })
+    }
+
+    #[test]
+    fn test_strike_amount() {
+        let usd = StrikeAmount::try_from(&CurrencyAmount::from_u64(Currency::USD, 1050)).unwrap();
+        assert_eq!(usd.amount, "10.50");
+        assert_eq!(usd.currency, "USD");
+        let btc = StrikeAmount::try_from(&CurrencyAmount::millisats(21_000_000)).unwrap();
+        assert_eq!(btc.amount, "0.00021000");
+        assert_eq!(btc.currency, "BTC");
+
+        assert!(StrikeAmount::try_from(&CurrencyAmount::millisats(1_500)).is_err());
+        assert!(StrikeAmount::try_from(&CurrencyAmount::from_u64(Currency::EUR, 100)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_strike_create_invoice_and_quote() {
+        let invoice = signed_invoice(21_000, "coffee", 7).to_string();
+        let amount = serde_json::json!({"amount": "10.50", "currency": "USD"});
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/invoices"))
+            .and(header("authorization", "Bearer key1"))
+            .and(body_json(serde_json::json!({
+                "correlationId": "order-1",
+                "description": "coffee",
+                "amount": amount
+            })))
+            .respond_with(
+                ResponseTemplate::new(201).set_body_json(invoice_json("UNPAID", amount.clone())),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v1/invoices/inv-1/quote"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({
+                "quoteId": "q-1",
+                "lnInvoice": invoice,
+                "expirationInSec": 30
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let node = node(&server.uri());
+        let rsp = node
+            .create_invoice(
+                CurrencyAmount::from_u64(Currency::USD, 1050),
+                Some("coffee".to_string()),
+                Some("order-1".to_string()),
+            )
+            .await
+            .unwrap();
+        assert_eq!(rsp.external_id.as_deref(), Some("inv-1"));
+        assert_eq!(rsp.pr(), invoice);
+        assert_eq!(node.invoice_id(&[7; 32]).as_deref(), Some("inv-1"));
+    }
+
+    #[tokio::test]
+    async fn test_strike_subscribe_invoices() {
+        let invoice = signed_invoice(21_000, "coffee", 7).to_string();
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path("/v1/invoices/inv-1"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(invoice_json(
+                "PAID",
+                serde_json::json!({"amount": "0.00000021", "currency": "BTC"}),
+            )))
+            .mount(&server)
+            .await;
+
+        let node = node(&server.uri()).with_webhook_path("/webhooks/strike-test".into());
+        node.invoices.lock().unwrap().insert(
+            "inv-1".to_string(),
+            StrikeInvoiceRef {
+                payment_hash: "07".repeat(32),
+                payment_request: invoice,
+            },
+        );
+        let mut updates = node.subscribe_invoices(None).await.unwrap();
+
+        let body = br#"{"id":"evt-1","eventType":"invoice.updated","webhookVersion":"v1","data":{"entityId":"inv-1","changes":["state"]}}"#;
+        let mut mac = HmacSha256::new_from_slice(b"secret").unwrap();
+        mac.update(body);
+        let sig = hex::encode_upper(mac.finalize().into_bytes());
+        WEBHOOK_BRIDGE.send(WebhookMessage {
+            endpoint: "/webhooks/strike-test".to_string(),
+            query: None,
+            body: body.to_vec(),
+            headers: HashMap::from([("X-Webhook-Signature".to_string(), sig)]),
+        });
+
+        let update = tokio::time::timeout(Duration::from_secs(5), updates.next())
+            .await
+            .unwrap()
+            .unwrap();
+        match update {
+            InvoiceUpdate::Settled {
+                payment_hash,
+                external_id,
+                ..
+            } => {
+                assert_eq!(payment_hash, "07".repeat(32));
+                assert_eq!(external_id.as_deref(), Some("inv-1"));
+            }
+            u => panic!("unexpected update {:?}", u),
+        }
+    }
+
+    #[test]
+    fn test_strike_verify_webhook() {
+        let node = node("https://api.strike.me/");
+        let body = b"{}".to_vec();
+        let mut msg = WebhookMessage {
+            endpoint: "/webhooks/strike".to_string(),
+            query: None,
+            body: body.clone(),
+            headers: HashMap::from([("x-webhook-signature".to_string(), "00".to_string())]),
+        };
+        assert!(node.verify(&msg).is_err());
+
+        let mut mac = HmacSha256::new_from_slice(b"secret").unwrap();
+        mac.update(&body);
+        msg.headers.insert(
+            "x-webhook-signature".to_string(),
+            hex::encode(mac.finalize().into_bytes()),
+        );
+        assert!(node.verify(&msg).is_ok());
+    }
+}
@@ -0,0 +1,338 @@
+//! Invoice updates from WebSocket event streams.
+//!
+//! [`ws_invoice_stream`] connects to a backend's event stream, maps each
+//! message to an [`InvoiceUpdate`] and reconnects with backoff whenever the
+//! connection drops, so WebSocket based backends only need to parse their
+//! events. Enabled by the `ws` feature.
+
+use crate::lightning::InvoiceUpdate;
+use anyhow::{Result, anyhow};
+use futures::{Stream, StreamExt};
+use log::{debug, warn};
+use reqwest::Url;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use tokio_tungstenite::tungstenite::http::header::AUTHORIZATION;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+use tokio_tungstenite::{Connector, MaybeTlsStream, WebSocketStream};
+
+#[cfg(not(any(feature = "tls-ring", feature = "tls-aws")))]
+compile_error!(
+    "WebSocket event streams require a TLS crypto provider: enable either the \
+     `tls-ring` (default) or `tls-aws` feature."
+);
+
+/// Largest WebSocket message accepted from an event stream
+const MAX_MESSAGE_SIZE: usize = 1024 * 1024;
+
+/// Delay before the first reconnect attempt, doubled after each failure
+const RECONNECT_MIN_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest delay between reconnect attempts
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(60);
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Subscribe to the WebSocket event stream at `url`.
+///
+/// `auth` is sent as the `Authorization` header of each connection, `map`
+/// turns a text message into an update, `None` skips it. `ws://` and `wss://`
+/// urls are accepted as well as their `http(s)://` equivalents.
+///
+/// Fails if the first connection cannot be opened. After that the stream
+/// never ends: it reconnects with exponential backoff when the connection
+/// closes or fails, and events sent while disconnected are lost.
+pub async fn ws_invoice_stream<F>(
+    url: Url,
+    auth: Option<String>,
+    map: F,
+) -> Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>>
+where
+    F: Fn(&str) -> Option<InvoiceUpdate> + Send + 'static,
+{
+    ws_invoice_stream_with_tls(url, auth, false, map).await
+}
+
+/// [`ws_invoice_stream`] optionally accepting invalid TLS certificates, e.g.
+/// of a self-signed proxy.
+pub async fn ws_invoice_stream_with_tls<F>(
+    mut url: Url,
+    auth: Option<String>,
+    allow_invalid_certs: bool,
+    map: F,
+) -> Result<Pin<Box<dyn Stream<Item = InvoiceUpdate> + Send>>>
+where
+    F: Fn(&str) -> Option<InvoiceUpdate> + Send + 'static,
+{
+    let scheme = match url.scheme() {
+        "http" => Some("ws"),
+        "https" => Some("wss"),
+        _ => None,
+    };
+    if let Some(scheme) = scheme {
+        url.set_scheme(scheme)
+            .map_err(|_| anyhow!("Invalid WebSocket url {}", url))?;
+    }
+    let connector = Connector::Rustls(Arc::new(tls_config(allow_invalid_certs)?));
+    let socket = connect(&url, auth.as_deref(), &connector).await?;
+    let state = StreamState {
+        url,
+        auth,
+        connector,
+        map,
+        socket: Some(socket),
+        delay: RECONNECT_MIN_DELAY,
+    };
+    Ok(Box::pin(futures::stream::unfold(
+        state,
+        |mut st| async move {
+            loop {
+                let socket = match st.socket.as_mut() {
+                    Some(socket) => socket,
+                    None => match connect(&st.url, st.auth.as_deref(), &st.connector).await {
+                        Ok(socket) => {
+                            debug!("Reconnected to {}", st.url);
+                            st.delay = RECONNECT_MIN_DELAY;
+                            st.socket.insert(socket)
+                        }
+                        Err(e) => {
+                            warn!("Failed to reconnect to {}: {}", st.url, e);
+                            st.backoff().await;
+                            continue;
+                        }
+                    },
+                };
+                // pings and the close handshake are answered by the socket
+                // itself while it is being read
+                let update = match socket.next().await {
+                    Some(Ok(Message::Text(msg))) => (st.map)(msg.as_str()),
+                    Some(Ok(Message::Binary(msg))) => match std::str::from_utf8(&msg) {
+                        Ok(msg) => (st.map)(msg),
+                        Err(e) => {
+                            warn!("Invalid WebSocket message from {}: {}", st.url, e);
+                            None
+                        }
+                    },
+                    Some(Ok(_)) => None,
+                    Some(Err(e)) => {
+                        warn!("WebSocket {} failed, reconnecting: {}", st.url, e);
+                        st.socket = None;
+                        st.backoff().await;
+                        None
+                    }
+                    None => {
+                        warn!("WebSocket {} closed, reconnecting", st.url);
+                        st.socket = None;
+                        st.backoff().await;
+                        None
+                    }
+                };
+                if let Some(update) = update {
+                    return Some((update, st));
+                }
+            }
+        },
+    )))
+}
+
+struct StreamState<F> {
+    url: Url,
+    auth: Option<String>,
+    connector: Connector,
+    map: F,
+    socket: Option<Socket>,
+    /// Wait before the next reconnect attempt
+    delay: Duration,
+}
+
+impl<F> StreamState<F> {
+    async fn backoff(&mut self) {
+        tokio::time::sleep(self.delay).await;
+        self.delay = (self.delay * 2).min(RECONNECT_MAX_DELAY);
+    }
+}
+
+/// Open a WebSocket connection to `url`
+async fn connect(url: &Url, auth: Option<&str>, connector: &Connector) -> Result<Socket> {
+    let mut req = url.as_str().into_client_request()?;
+    if let Some(auth) = auth {
+        req.headers_mut()
+            .insert(AUTHORIZATION, HeaderValue::from_str(auth)?);
+    }
+    let config = WebSocketConfig::default().max_message_size(Some(MAX_MESSAGE_SIZE));
+    let (socket, _) = tokio_tungstenite::connect_async_tls_with_config(
+        req,
+        Some(config),
+        false,
+        Some(connector.clone()),
+    )
+    .await?;
+    Ok(socket)
+}
+
+/// TLS settings for `wss://` connections, using the same crypto provider as
+/// the LND backend.
+fn tls_config(allow_invalid_certs: bool) -> Result<ClientConfig> {
+    #[cfg(feature = "tls-ring")]
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    #[cfg(all(feature = "tls-aws", not(feature = "tls-ring")))]
+    let provider = Arc::new(rustls::crypto::aws_lc_rs::default_provider());
+
+    let builder = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+    let config = if allow_invalid_certs {
+        builder
+            .dangerous()
+            .with_custom_certificate_verifier(Arc::new(AcceptAnyCert(provider)))
+            .with_no_client_auth()
+    } else {
+        let roots = RootCertStore {
+            roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+        };
+        builder.with_root_certificates(roots).with_no_client_auth()
+    };
+    Ok(config)
+}
+
+/// Accepts any server certificate, signatures are still checked so the
+/// handshake itself stays valid.
+#[derive(Debug)]
+struct AcceptAnyCert(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::SinkExt;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Bytes;
+    use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+
+    #[allow(clippy::result_large_err)]
+    fn check_auth(req: &Request, rsp: Response) -> std::result::Result<Response, ErrorResponse> {
+        assert_eq!(req.headers().get(AUTHORIZATION).unwrap(), "Bearer token");
+        Ok(rsp)
+    }
+
+    /// Accept a WebSocket connection on `listener`, checking the auth header
+    async fn accept(listener: &TcpListener) -> WebSocketStream<TcpStream> {
+        let (conn, _) = listener.accept().await.unwrap();
+        tokio_tungstenite::accept_hdr_async(conn, check_auth)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ws_invoice_stream_maps_and_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/events", listener.local_addr().unwrap())).unwrap();
+        let server = tokio::spawn(async move {
+            // the first connection sends an unrelated event and closes, the
+            // client must complete the close handshake
+            let mut ws = accept(&listener).await;
+            ws.send(Message::text("hello")).await.unwrap();
+            ws.send(Message::Ping(Bytes::from_static(b"hi")))
+                .await
+                .unwrap();
+            ws.close(None).await.unwrap();
+            let mut pong = false;
+            while let Some(msg) = ws.next().await {
+                match msg.unwrap() {
+                    Message::Pong(data) => pong = data.as_ref() == b"hi",
+                    Message::Close(_) => {}
+                    m => panic!("unexpected message {:?}", m),
+                }
+            }
+            assert!(pong, "ping was not answered");
+            drop(ws);
+            // the reconnect gets the payment
+            let mut ws = accept(&listener).await;
+            ws.send(Message::text("paid 0a0b")).await.unwrap();
+            ws
+        });
+
+        let mut stream = ws_invoice_stream(url, Some("Bearer token".to_string()), |msg| {
+            Some(InvoiceUpdate::Settled {
+                payment_hash: msg.strip_prefix("paid ")?.to_string(),
+                preimage: None,
+                external_id: None,
+                payment_request: None,
+                settled_at: None,
+                amount_msat: None,
+                memo: None,
+            })
+        })
+        .await
+        .unwrap();
+
+        let update = tokio::time::timeout(Duration::from_secs(5), stream.next())
+            .await
+            .unwrap();
+        match update {
+            Some(InvoiceUpdate::Settled { payment_hash, .. }) => {
+                assert_eq!(payment_hash, "0a0b")
+            }
+            u => panic!("unexpected update {:?}", u),
+        }
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn test_tls_config() {
+        assert!(tls_config(false).is_ok());
+        assert!(tls_config(true).is_ok());
+    }
+}
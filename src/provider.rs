@@ -0,0 +1,240 @@
+//! A single trait for creating payments on any rail.
+//!
+//! [`PaymentProvider`] hides whether a payment is made over Lightning or with a
+//! fiat processor: callers pass a [`PaymentRequest`] and get back a
+//! [`PaymentHandle`] telling them what to show the payer. Wrap a
+//! [`LightningNode`](crate::lightning::LightningNode) in a [`LightningProvider`]
+//! or a [`FiatPaymentService`](crate::fiat::FiatPaymentService) in a
+//! [`FiatProvider`] to use it as a provider.
+//!
+//! # Example
+//!
+//! ```rust,ignore
+//! use payments_rs::provider::{FiatProvider, PaymentProvider, PaymentRequest};
+//! use payments_rs::currency::{Currency, CurrencyAmount};
+//!
+//! let provider = FiatProvider::new(stripe);
+//! let handle = provider
+//!     .create_payment(PaymentRequest::new(
+//!         CurrencyAmount::from_f32(Currency::USD, 5.0),
+//!         "Coffee",
+//!     ))
+//!     .await?;
+//! println!("Payment {} -> {:?}", handle.id, handle.pay_to);
+//! ```
+
+use crate::currency::CurrencyAmount;
+use anyhow::Result;
+use async_trait::async_trait;
+
+#[cfg(feature = "lightning")]
+use crate::currency::CurrencyConverter;
+#[cfg(feature = "fiat")]
+use crate::fiat::FiatPaymentService;
+#[cfg(feature = "lightning")]
+use crate::lightning::{AddInvoiceRequest, LightningNode};
+#[cfg(feature = "lightning")]
+use anyhow::bail;
+#[cfg(feature = "lightning")]
+use std::sync::Arc;
+
+/// Request to create a payment with a [`PaymentProvider`].
+#[derive(Debug, Clone)]
+pub struct PaymentRequest {
+    /// Amount to charge
+    pub amount: CurrencyAmount,
+    /// Human-readable description shown to the payer
+    pub description: String,
+}
+
+impl PaymentRequest {
+    /// Create a request for `amount` with the given description.
+    pub fn new(amount: CurrencyAmount, description: impl Into<String>) -> Self {
+        Self {
+            amount,
+            description: description.into(),
+        }
+    }
+}
+
+/// What the payer needs in order to pay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaymentTarget {
+    /// A BOLT11 payment request to pay from a Lightning wallet
+    Bolt11(String),
+    /// A hosted checkout page to send the payer to
+    CheckoutUrl(String),
+}
+
+/// A payment created by a [`PaymentProvider`].
+#[derive(Debug, Clone)]
+pub struct PaymentHandle {
+    /// ID of the payment at the provider: the order ID for fiat payments, the
+    /// provider's invoice ID or else the hex payment hash for Lightning
+    pub id: String,
+    /// What to show the payer, `None` if the provider returned nothing to
+    /// redirect to (e.g. client-side card flows)
+    pub pay_to: Option<PaymentTarget>,
+}
+
+/// Creates payments independent of the payment rail.
+#[async_trait]
+pub trait PaymentProvider: Send + Sync {
+    /// Create a payment for `req`.
+    async fn create_payment(&self, req: PaymentRequest) -> Result<PaymentHandle>;
+}
+
+/// [`PaymentProvider`] that charges with Lightning invoices.
+///
+/// Bitcoin amounts are invoiced directly. Fiat amounts need a converter, see
+/// [`LightningProvider::with_converter`].
+#[cfg(feature = "lightning")]
+pub struct LightningProvider<N: LightningNode> {
+    node: N,
+    converter: Option<Arc<dyn CurrencyConverter + Send + Sync>>,
+}
+
+#[cfg(feature = "lightning")]
+impl<N: LightningNode> LightningProvider<N> {
+    /// Create a provider that invoices through `node`.
+    pub fn new(node: N) -> Self {
+        Self {
+            node,
+            converter: None,
+        }
+    }
+
+    /// Convert fiat amounts to Bitcoin with `converter` before invoicing.
+    pub fn with_converter(mut self, converter: Arc<dyn CurrencyConverter + Send + Sync>) -> Self {
+        self.converter = Some(converter);
+        self
+    }
+
+    /// The wrapped Lightning node.
+    pub fn node(&self) -> &N {
+        &self.node
+    }
+}
+
+#[cfg(feature = "lightning")]
+#[async_trait]
+impl<N: LightningNode> PaymentProvider for LightningProvider<N> {
+    async fn create_payment(&self, req: PaymentRequest) -> Result<PaymentHandle> {
+        let memo = Some(req.description);
+        let invoice_req = match &self.converter {
+            Some(conv) => AddInvoiceRequest::from_currency(req.amount, conv.as_ref(), memo)?,
+            None => {
+                if !req.amount.currency().is_crypto() {
+                    bail!(
+                        "Cannot invoice {} without a currency converter",
+                        req.amount.currency()
+                    );
+                }
+                AddInvoiceRequest {
+                    amount: req.amount.value(),
+                    memo,
+                    expire: None,
+                    idempotency_key: None,
+                }
+            }
+        };
+        let rsp = self.node.add_invoice(invoice_req).await?;
+        Ok(PaymentHandle {
+            id: rsp
+                .external_id
+                .clone()
+                .unwrap_or_else(|| rsp.payment_hash()),
+            pay_to: Some(PaymentTarget::Bolt11(rsp.pr())),
+        })
+    }
+}
+
+/// [`PaymentProvider`] that charges with fiat orders.
+#[cfg(feature = "fiat")]
+pub struct FiatProvider<S: FiatPaymentService> {
+    service: S,
+}
+
+#[cfg(feature = "fiat")]
+impl<S: FiatPaymentService> FiatProvider<S> {
+    /// Create a provider that creates orders with `service`.
+    pub fn new(service: S) -> Self {
+        Self { service }
+    }
+
+    /// The wrapped fiat payment service.
+    pub fn service(&self) -> &S {
+        &self.service
+    }
+}
+
+#[cfg(feature = "fiat")]
+#[async_trait]
+impl<S: FiatPaymentService> PaymentProvider for FiatProvider<S> {
+    async fn create_payment(&self, req: PaymentRequest) -> Result<PaymentHandle> {
+        let info = self
+            .service
+            .create_order(&req.description, req.amount, None)
+            .await?;
+        Ok(PaymentHandle {
+            id: info.external_id,
+            pay_to: info.checkout_url.map(PaymentTarget::CheckoutUrl),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::currency::Currency;
+
+    #[cfg(feature = "lightning")]
+    #[tokio::test]
+    async fn test_lightning_provider_returns_bolt11() {
+        use crate::currency::StaticRateConverter;
+        use crate::lightning::MockLightningNode;
+
+        let node = MockLightningNode::new();
+        let provider = LightningProvider::new(node.clone());
+        let handle = provider
+            .create_payment(PaymentRequest::new(
+                CurrencyAmount::millisats(21_000),
+                "Coffee",
+            ))
+            .await
+            .unwrap();
+        let Some(PaymentTarget::Bolt11(pr)) = &handle.pay_to else {
+            panic!("Expected a BOLT11 target, got {:?}", handle.pay_to);
+        };
+        assert!(pr.starts_with("lnbcrt"));
+        assert_eq!(handle.id, node.invoices()[0].invoice.payment_hash());
+
+        let usd = PaymentRequest::new(CurrencyAmount::from_u64(Currency::USD, 500), "Coffee");
+        assert!(provider.create_payment(usd.clone()).await.is_err());
+        let conv = StaticRateConverter::new().with_rate(Currency::BTC, Currency::USD, 50_000.0);
+        let provider = provider.with_converter(Arc::new(conv));
+        let handle = provider.create_payment(usd).await.unwrap();
+        assert!(matches!(handle.pay_to, Some(PaymentTarget::Bolt11(_))));
+    }
+
+    #[cfg(feature = "fiat")]
+    #[tokio::test]
+    async fn test_fiat_provider_returns_checkout_url() {
+        use crate::fiat::test_util::FakeFiat;
+
+        let handle = FiatProvider::new(FakeFiat)
+            .create_payment(PaymentRequest::new(
+                CurrencyAmount::from_u64(Currency::USD, 500),
+                "Coffee",
+            ))
+            .await
+            .unwrap();
+        assert_eq!(handle.id, "order_1");
+        assert_eq!(
+            handle.pay_to,
+            Some(PaymentTarget::CheckoutUrl(
+                "https://pay.example.com/order_1".to_string()
+            ))
+        );
+    }
+}
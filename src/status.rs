@@ -0,0 +1,155 @@
+//! Payment status shared by all payment rails.
+//!
+//! [`PaymentStatus`] lets applications that accept both Lightning and fiat
+//! drive one state machine from [`InvoiceUpdate`]s and [`FiatOrderStatus`]es.
+//!
+//! [`InvoiceUpdate`]: crate::lightning::InvoiceUpdate
+//! [`FiatOrderStatus`]: crate::fiat::FiatOrderStatus
+
+#[cfg(feature = "fiat")]
+use crate::fiat::FiatOrderStatus;
+#[cfg(feature = "lightning")]
+use crate::lightning::InvoiceUpdate;
+
+/// Provider and rail agnostic state of a payment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PaymentStatus {
+    /// Waiting for the customer to pay, or payment in progress
+    Pending,
+    /// Paid
+    Paid,
+    /// Canceled or expired before payment
+    Cancelled,
+    /// Payment failed
+    Failed,
+    /// Paid and refunded
+    Refunded,
+    /// The provider reported something this crate does not understand
+    Unknown,
+}
+
+impl PaymentStatus {
+    /// Whether the payment can no longer change (except by refunding)
+    pub fn is_final(&self) -> bool {
+        !matches!(self, PaymentStatus::Pending | PaymentStatus::Unknown)
+    }
+}
+
+#[cfg(feature = "lightning")]
+impl From<&InvoiceUpdate> for PaymentStatus {
+    fn from(update: &InvoiceUpdate) -> Self {
+        match update {
+            // Funds of a hold invoice are only locked in until it is settled
+            InvoiceUpdate::Created { .. } | InvoiceUpdate::Accepted { .. } => {
+                PaymentStatus::Pending
+            }
+            InvoiceUpdate::Settled { .. } => PaymentStatus::Paid,
+            InvoiceUpdate::Canceled { .. } => PaymentStatus::Cancelled,
+            // An invoice that cannot be parsed cannot be paid safely
+            InvoiceUpdate::InvalidInvoice(_) => PaymentStatus::Failed,
+            // A stream error says nothing about the invoice itself
+            InvoiceUpdate::Unknown { .. } | InvoiceUpdate::Error(_) => PaymentStatus::Unknown,
+        }
+    }
+}
+
+#[cfg(feature = "lightning")]
+impl From<InvoiceUpdate> for PaymentStatus {
+    fn from(update: InvoiceUpdate) -> Self {
+        PaymentStatus::from(&update)
+    }
+}
+
+#[cfg(feature = "fiat")]
+impl From<FiatOrderStatus> for PaymentStatus {
+    fn from(status: FiatOrderStatus) -> Self {
+        match status {
+            FiatOrderStatus::Pending => PaymentStatus::Pending,
+            FiatOrderStatus::Completed => PaymentStatus::Paid,
+            FiatOrderStatus::Cancelled => PaymentStatus::Cancelled,
+            FiatOrderStatus::Failed => PaymentStatus::Failed,
+            FiatOrderStatus::Refunded => PaymentStatus::Refunded,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "lightning")]
+    #[test]
+    fn test_invoice_update_status() {
+        use crate::lightning::parse_invoice;
+
+        let hash = || "00".repeat(32);
+        let cases = [
+            (
+                InvoiceUpdate::Created {
+                    payment_hash: hash(),
+                    payment_request: "lnbc1".to_string(),
+                },
+                PaymentStatus::Pending,
+            ),
+            (
+                InvoiceUpdate::Settled {
+                    payment_hash: hash(),
+                    preimage: None,
+                    external_id: None,
+                    payment_request: None,
+                    settled_at: None,
+                    amount_msat: None,
+                    memo: None,
+                },
+                PaymentStatus::Paid,
+            ),
+            (
+                InvoiceUpdate::Canceled {
+                    payment_hash: hash(),
+                },
+                PaymentStatus::Cancelled,
+            ),
+            (
+                InvoiceUpdate::Accepted {
+                    payment_hash: hash(),
+                    amount_msat: None,
+                },
+                PaymentStatus::Pending,
+            ),
+            (
+                InvoiceUpdate::InvalidInvoice(parse_invoice("garbage").unwrap_err()),
+                PaymentStatus::Failed,
+            ),
+            (
+                InvoiceUpdate::Unknown {
+                    payment_hash: hash(),
+                },
+                PaymentStatus::Unknown,
+            ),
+            (
+                InvoiceUpdate::Error("stream closed".to_string()),
+                PaymentStatus::Unknown,
+            ),
+        ];
+        for (update, expected) in cases {
+            assert_eq!(PaymentStatus::from(&update), expected, "{:?}", update);
+            assert_eq!(PaymentStatus::from(update), expected);
+        }
+    }
+
+    #[cfg(feature = "fiat")]
+    #[test]
+    fn test_fiat_order_status() {
+        for (status, expected) in [
+            (FiatOrderStatus::Pending, PaymentStatus::Pending),
+            (FiatOrderStatus::Completed, PaymentStatus::Paid),
+            (FiatOrderStatus::Cancelled, PaymentStatus::Cancelled),
+            (FiatOrderStatus::Failed, PaymentStatus::Failed),
+            (FiatOrderStatus::Refunded, PaymentStatus::Refunded),
+        ] {
+            assert_eq!(PaymentStatus::from(status), expected);
+            assert_eq!(status.is_final(), expected.is_final());
+        }
+        assert!(!PaymentStatus::Unknown.is_final());
+    }
+}
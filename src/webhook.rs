@@ -2,7 +2,8 @@
 //!
 //! This module provides a global webhook bridge for routing incoming webhook
 //! messages from payment providers (Stripe, Revolut, Bitvora) to their respective
-//! handlers.
+//! handlers. Apps hosting several providers behind one endpoint can instead
+//! verify and dispatch messages directly with a [`WebhookRouter`].
 //!
 //! # Example
 //!
@@ -12,6 +13,7 @@
 //! // In your webhook endpoint handler:
 //! let msg = WebhookMessage {
 //!     endpoint: "/webhooks/stripe".to_string(),
+//!     query: None,
 //!     body: request_body,
 //!     headers: request_headers,
 //! };
@@ -24,6 +26,8 @@
 //! }
 //! ```
 
+use futures::{Stream, StreamExt};
+use hmac::{Hmac, Mac};
 use log::warn;
 #[cfg(feature = "rocket")]
 use rocket::Data;
@@ -33,10 +37,15 @@ use rocket::data::FromData;
 use rocket::data::ToByteUnit;
 #[cfg(feature = "rocket")]
 use rocket::http::Status;
+use sha2::Sha256;
 use std::collections::HashMap;
-use std::sync::LazyLock;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tokio::sync::broadcast;
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::wrappers::errors::BroadcastStreamRecvError;
 
 /// Global webhook message bridge.
 ///
@@ -67,17 +76,96 @@ pub(crate) fn verify_timestamp_within(
     Ok(())
 }
 
+/// Verify a hex encoded HMAC-SHA256 signature of `message` under `secret`.
+///
+/// The comparison runs in constant time. Surrounding whitespace in
+/// `provided_sig_hex` is ignored, and upper or lower case hex is accepted.
+/// Returns an error if the signature is not valid hex or does not match.
+pub fn verify_hmac_sha256(
+    secret: &[u8],
+    message: &[u8],
+    provided_sig_hex: &str,
+) -> anyhow::Result<()> {
+    let provided = hex::decode(provided_sig_hex.trim())
+        .map_err(|_| anyhow::anyhow!("Invalid signature encoding"))?;
+    // HMAC accepts keys of any length, so `new_from_slice` cannot fail.
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(message);
+    mac.verify_slice(&provided)
+        .map_err(|_| anyhow::anyhow!("No valid signature found!"))
+}
+
+/// Provider-specific webhook signature verification.
+///
+/// Implemented by the provider clients that receive signed webhooks, so
+/// dispatch code can check a [`WebhookMessage`] without knowing which
+/// provider's header format and signing scheme applies.
+pub trait WebhookVerifier {
+    /// Verify the signature of `msg`, returning an error if it is missing or
+    /// invalid.
+    fn verify(&self, msg: &WebhookMessage) -> anyhow::Result<()>;
+}
+
+/// How a backend recognises its webhooks by [`WebhookMessage::endpoint`].
+///
+/// The endpoint is the full request path, which depends on where the app is
+/// mounted, so backends can match loosely instead of by exact string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookPathMatcher {
+    /// The endpoint equals the path
+    Exact(String),
+    /// The endpoint ends with the path on a segment boundary, e.g.
+    /// `/api/webhooks/bitvora` for `/webhooks/bitvora`
+    Suffix(String),
+    /// The endpoint equals the path once `prefix` is stripped from it
+    StripPrefix { prefix: String, path: String },
+}
+
+impl WebhookPathMatcher {
+    /// Whether `endpoint` is the configured path
+    pub fn matches(&self, endpoint: &str) -> bool {
+        match self {
+            WebhookPathMatcher::Exact(path) => endpoint == path,
+            WebhookPathMatcher::Suffix(path) => {
+                endpoint.strip_suffix(path.as_str()).is_some_and(|rest| {
+                    rest.is_empty() || path.starts_with('/') || rest.ends_with('/')
+                })
+            }
+            WebhookPathMatcher::StripPrefix { prefix, path } => endpoint
+                .strip_prefix(prefix.trim_end_matches('/'))
+                .is_some_and(|rest| rest == path),
+        }
+    }
+}
+
+impl From<&str> for WebhookPathMatcher {
+    fn from(path: &str) -> Self {
+        WebhookPathMatcher::Exact(path.to_string())
+    }
+}
+
 /// A webhook message received from a payment provider.
 #[derive(Debug, Clone)]
 pub struct WebhookMessage {
     /// The endpoint path that received the webhook
     pub endpoint: String,
+    /// Raw query string of the request (without `?`), if any
+    pub query: Option<String>,
     /// Raw request body
     pub body: Vec<u8>,
     /// HTTP headers (used for signature verification)
     pub headers: HashMap<String, String>,
 }
 
+impl WebhookMessage {
+    /// First value of the query parameter `key`, percent-decoded
+    pub fn query_param(&self, key: &str) -> Option<String> {
+        form_urlencoded::parse(self.query.as_deref()?.as_bytes())
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.into_owned())
+    }
+}
+
 #[cfg(feature = "rocket")]
 #[rocket::async_trait]
 impl<'r> FromData<'r> for WebhookMessage {
@@ -92,23 +180,160 @@ impl<'r> FromData<'r> for WebhookMessage {
             .iter()
             .map(|v| (v.name.to_string(), v.value.to_string()))
             .collect();
-        let body = if let Ok(d) = data.open(4.megabytes()).into_bytes().await {
-            d
-        } else {
-            return rocket::data::Outcome::Error((Status::BadRequest, ()));
+        let limit = req
+            .rocket()
+            .state::<WebhookPayloadConfig>()
+            .copied()
+            .unwrap_or_default()
+            .limit;
+        let body = match data.open(limit.bytes()).into_bytes().await {
+            Ok(d) if d.is_complete() => d,
+            Ok(_) => return rocket::data::Outcome::Error((Status::PayloadTooLarge, ())),
+            Err(_) => return rocket::data::Outcome::Error((Status::BadRequest, ())),
         };
         let msg = WebhookMessage {
             endpoint: req.uri().path().to_string(),
+            query: req.uri().query().map(|q| q.to_string()),
             headers: header,
             body: body.value.to_vec(),
         };
         rocket::data::Outcome::Success(msg)
     }
 }
+
+/// A [`WebhookMessage`] which was also published to [`WEBHOOK_BRIDGE`] when
+/// it was extracted, so the Rocket handler only has to acknowledge it.
+///
+/// ```rust,ignore
+/// use payments_rs::webhook::BridgedWebhookMessage;
+///
+/// #[rocket::post("/webhooks/<_provider>", data = "<_msg>")]
+/// fn webhook(_provider: &str, _msg: BridgedWebhookMessage) -> &'static str {
+///     "ok"
+/// }
+/// ```
+#[cfg(feature = "rocket")]
+#[derive(Debug, Clone)]
+pub struct BridgedWebhookMessage(pub WebhookMessage);
+
+#[cfg(feature = "rocket")]
+impl BridgedWebhookMessage {
+    /// The extracted message, which has already been forwarded to the bridge
+    pub fn into_inner(self) -> WebhookMessage {
+        self.0
+    }
+}
+
+#[cfg(feature = "rocket")]
+impl std::ops::Deref for BridgedWebhookMessage {
+    type Target = WebhookMessage;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(feature = "rocket")]
+#[rocket::async_trait]
+impl<'r> FromData<'r> for BridgedWebhookMessage {
+    type Error = ();
+
+    async fn from_data(
+        req: &'r rocket::Request<'_>,
+        data: Data<'r>,
+    ) -> rocket::data::Outcome<'r, Self, Self::Error> {
+        WebhookMessage::from_data(req, data).await.map(|msg| {
+            WEBHOOK_BRIDGE.send(msg.clone());
+            BridgedWebhookMessage(msg)
+        })
+    }
+}
+
+/// Body size limit for the actix-web and Rocket [`WebhookMessage`]
+/// extractors.
+///
+/// Register it with `App::app_data` (actix-web) or `Rocket::manage` (Rocket)
+/// to override the default of 4MB; larger bodies are rejected with
+/// `413 Payload Too Large`.
+#[cfg(any(feature = "actix", feature = "rocket"))]
+#[derive(Debug, Clone, Copy)]
+pub struct WebhookPayloadConfig {
+    /// Maximum body size in bytes
+    pub limit: usize,
+}
+
+#[cfg(any(feature = "actix", feature = "rocket"))]
+impl Default for WebhookPayloadConfig {
+    fn default() -> Self {
+        Self { limit: 4_000_000 }
+    }
+}
+
+/// Extract a [`WebhookMessage`] in an actix-web handler.
+///
+/// ```rust,ignore
+/// use actix_web::{HttpResponse, post};
+/// use payments_rs::webhook::{WEBHOOK_BRIDGE, WebhookMessage};
+///
+/// #[post("/webhooks/{provider}")]
+/// async fn webhook(msg: WebhookMessage) -> HttpResponse {
+///     WEBHOOK_BRIDGE.send(msg);
+///     HttpResponse::Ok().finish()
+/// }
+/// ```
+#[cfg(feature = "actix")]
+impl actix_web::FromRequest for WebhookMessage {
+    type Error = actix_web::Error;
+    type Future = std::pin::Pin<Box<dyn std::future::Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(
+        req: &actix_web::HttpRequest,
+        payload: &mut actix_web::dev::Payload,
+    ) -> Self::Future {
+        let limit = req
+            .app_data::<WebhookPayloadConfig>()
+            .copied()
+            .unwrap_or_default()
+            .limit;
+        let endpoint = req.uri().path().to_string();
+        let query = req.uri().query().map(|q| q.to_string());
+        let headers = req
+            .headers()
+            .iter()
+            .filter_map(|(k, v)| Some((k.to_string(), v.to_str().ok()?.to_string())))
+            .collect();
+        let mut payload = payload.take();
+        Box::pin(async move {
+            let mut body = Vec::new();
+            while let Some(chunk) = payload.next().await {
+                let chunk = chunk?;
+                if body.len() + chunk.len() > limit {
+                    return Err(actix_web::error::PayloadError::Overflow.into());
+                }
+                body.extend_from_slice(&chunk);
+            }
+            Ok(WebhookMessage {
+                endpoint,
+                query,
+                body,
+                headers,
+            })
+        })
+    }
+}
+
 /// Broadcast bridge for routing webhook messages to handlers.
+///
+/// Messages are buffered in a ring of [`WebhookBridge::capacity`] entries. A
+/// subscriber that falls more than that many messages behind skips the oldest
+/// ones and receives `Lagged(n)` from its receiver instead; those messages are
+/// lost to it. Subscribers created with [`WebhookBridge::stream`] add skipped
+/// messages to [`WebhookBridge::lagged_count`] so drops can be monitored.
 #[derive(Debug)]
 pub struct WebhookBridge {
     tx: broadcast::Sender<WebhookMessage>,
+    capacity: usize,
+    lagged: Arc<AtomicU64>,
 }
 
 impl Default for WebhookBridge {
@@ -118,10 +343,37 @@ impl Default for WebhookBridge {
 }
 
 impl WebhookBridge {
+    /// Default number of messages buffered per subscriber.
+    pub const DEFAULT_CAPACITY: usize = 100;
+
     /// Create a new webhook bridge with a buffer of 100 messages.
     pub fn new() -> Self {
-        let (tx, _rx) = broadcast::channel(100);
-        Self { tx }
+        Self::with_capacity(Self::DEFAULT_CAPACITY)
+    }
+
+    /// Create a new webhook bridge buffering up to `capacity` messages.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let (tx, _rx) = broadcast::channel(capacity);
+        Self {
+            tx,
+            capacity,
+            lagged: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Number of messages buffered before slow subscribers start lagging.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Total number of messages skipped by lagging [`WebhookBridge::stream`]
+    /// subscribers.
+    pub fn lagged_count(&self) -> u64 {
+        self.lagged.load(Ordering::Relaxed)
     }
 
     /// Send a webhook message to all listeners.
@@ -139,12 +391,434 @@ impl WebhookBridge {
     pub fn listen(&self) -> broadcast::Receiver<WebhookMessage> {
         self.tx.subscribe()
     }
+
+    /// Subscribe to receive webhook messages as a stream.
+    ///
+    /// Skipped messages are logged and added to
+    /// [`WebhookBridge::lagged_count`] before the `Lagged` error is yielded.
+    pub fn stream(
+        &self,
+    ) -> impl Stream<Item = Result<WebhookMessage, BroadcastStreamRecvError>> + Send + Unpin + 'static
+    {
+        let lagged = self.lagged.clone();
+        BroadcastStream::new(self.listen()).inspect(move |r| {
+            if let Err(BroadcastStreamRecvError::Lagged(n)) = r {
+                warn!("Webhook subscriber lagged, {} messages dropped", n);
+                lagged.fetch_add(*n, Ordering::Relaxed);
+            }
+        })
+    }
+
+    /// Subscribe to webhook messages received on `path` only.
+    ///
+    /// Messages for other endpoints are skipped, so each provider can listen on
+    /// its own path of a shared bridge. Lagged messages are counted as for
+    /// [`WebhookBridge::stream`] and otherwise skipped.
+    pub fn listen_path(
+        &self,
+        path: impl Into<WebhookPathMatcher>,
+    ) -> impl Stream<Item = WebhookMessage> + Send + Unpin + 'static {
+        let path = path.into();
+        self.stream().filter_map(move |r| {
+            let msg = r.ok().filter(|m| path.matches(&m.endpoint));
+            std::future::ready(msg)
+        })
+    }
+}
+
+/// Remembers processed webhook event ids so redelivered events are handled
+/// only once.
+///
+/// Providers retry deliveries that were not acknowledged in time, so the same
+/// event can arrive several times. Ids are forgotten after `ttl`, which should
+/// exceed the provider's retry period.
+#[derive(Debug)]
+pub struct WebhookDeduplicator {
+    ttl: Duration,
+    seen: Mutex<HashMap<String, Instant>>,
+}
+
+impl Default for WebhookDeduplicator {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_TTL)
+    }
+}
+
+impl WebhookDeduplicator {
+    /// Default time an event id is remembered (24 hours).
+    pub const DEFAULT_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Create a deduplicator remembering event ids for `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            seen: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record `event_id`, returning `true` if it was not seen within the TTL
+    /// (i.e. the event should be processed).
+    pub fn check_and_record(&self, event_id: &str) -> bool {
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let ttl = self.ttl;
+        seen.retain(|_, at| at.elapsed() < ttl);
+        seen.insert(event_id.to_string(), Instant::now()).is_none()
+    }
+
+    /// Forget `event_id`, e.g. because processing it failed and a redelivery
+    /// should be handled again.
+    pub fn forget(&self, event_id: &str) {
+        self.seen
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(event_id);
+    }
+}
+
+/// Why [`WebhookRouter::dispatch`] did not produce an event.
+#[derive(Debug)]
+pub enum WebhookRouteError {
+    /// No route is registered for the endpoint (e.g. answer `404`)
+    NoRoute(String),
+    /// The signature check failed, the handler was not called (e.g. answer
+    /// `401`)
+    Verification(anyhow::Error),
+    /// The handler rejected the verified message (e.g. answer `400`)
+    Handler(anyhow::Error),
+}
+
+impl Display for WebhookRouteError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WebhookRouteError::NoRoute(endpoint) => {
+                write!(f, "No webhook route for {}", endpoint)
+            }
+            WebhookRouteError::Verification(e) => {
+                write!(f, "Webhook verification failed: {}", e)
+            }
+            WebhookRouteError::Handler(e) => write!(f, "Webhook handler failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for WebhookRouteError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WebhookRouteError::NoRoute(_) => None,
+            WebhookRouteError::Verification(e) | WebhookRouteError::Handler(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+/// Handler turning a verified [`WebhookMessage`] into an event.
+pub type WebhookHandler<E> = Box<dyn Fn(&WebhookMessage) -> anyhow::Result<E> + Send + Sync>;
+
+struct WebhookRoute<E> {
+    path: WebhookPathMatcher,
+    verifier: Arc<dyn WebhookVerifier + Send + Sync>,
+    handler: WebhookHandler<E>,
+}
+
+/// Routes webhooks of several providers sharing one endpoint to their
+/// handlers, verifying each signature first.
+///
+/// `E` is the event type all handlers normalize to, e.g.
+/// [`VerifiedEvent`](crate::fiat::VerifiedEvent) or an application enum.
+/// Routes are tried in registration order and the first whose path matches
+/// handles the message.
+///
+/// ```rust,ignore
+/// use payments_rs::webhook::{WebhookPathMatcher, WebhookRouter};
+/// use std::sync::Arc;
+///
+/// let stripe = Arc::new(stripe);
+/// let router = WebhookRouter::new().route(
+///     WebhookPathMatcher::Suffix("/webhooks/stripe".to_string()),
+///     stripe.clone(),
+///     move |msg| stripe.verify_webhook(msg),
+/// );
+/// let event = router.dispatch(&msg)?;
+/// ```
+pub struct WebhookRouter<E> {
+    routes: Vec<WebhookRoute<E>>,
+}
+
+impl<E> Default for WebhookRouter<E> {
+    fn default() -> Self {
+        Self { routes: Vec::new() }
+    }
+}
+
+impl<E> WebhookRouter<E> {
+    /// Create a router without routes.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Route messages for `path` to `handler` once `verifier` accepts them.
+    pub fn route(
+        mut self,
+        path: impl Into<WebhookPathMatcher>,
+        verifier: Arc<dyn WebhookVerifier + Send + Sync>,
+        handler: impl Fn(&WebhookMessage) -> anyhow::Result<E> + Send + Sync + 'static,
+    ) -> Self {
+        self.routes.push(WebhookRoute {
+            path: path.into(),
+            verifier,
+            handler: Box::new(handler),
+        });
+        self
+    }
+
+    /// Verify `msg` with the verifier of its route and pass it to the route's
+    /// handler.
+    pub fn dispatch(&self, msg: &WebhookMessage) -> Result<E, WebhookRouteError> {
+        let route = self
+            .routes
+            .iter()
+            .find(|r| r.path.matches(&msg.endpoint))
+            .ok_or_else(|| WebhookRouteError::NoRoute(msg.endpoint.clone()))?;
+        route
+            .verifier
+            .verify(msg)
+            .map_err(WebhookRouteError::Verification)?;
+        (route.handler)(msg).map_err(WebhookRouteError::Handler)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Accepts messages carrying `x-signature: <secret>`.
+    struct HeaderVerifier(&'static str);
+
+    impl WebhookVerifier for HeaderVerifier {
+        fn verify(&self, msg: &WebhookMessage) -> anyhow::Result<()> {
+            match msg.headers.get("x-signature") {
+                Some(sig) if sig == self.0 => Ok(()),
+                _ => anyhow::bail!("bad signature"),
+            }
+        }
+    }
+
+    fn signed_message(endpoint: &str, sig: &str) -> WebhookMessage {
+        WebhookMessage {
+            endpoint: endpoint.to_string(),
+            query: None,
+            body: b"{}".to_vec(),
+            headers: HashMap::from([("x-signature".to_string(), sig.to_string())]),
+        }
+    }
+
+    fn router(calls: Arc<AtomicU64>) -> WebhookRouter<String> {
+        WebhookRouter::new()
+            .route("/webhooks/a", Arc::new(HeaderVerifier("secret-a")), {
+                let calls = calls.clone();
+                move |_| {
+                    calls.fetch_add(1, Ordering::Relaxed);
+                    Ok("a".to_string())
+                }
+            })
+            .route(
+                WebhookPathMatcher::Suffix("/webhooks/b".to_string()),
+                Arc::new(HeaderVerifier("secret-b")),
+                move |msg| {
+                    calls.fetch_add(1, Ordering::Relaxed);
+                    Ok(format!("b {}", String::from_utf8_lossy(&msg.body)))
+                },
+            )
+    }
+
+    #[test]
+    fn test_webhook_router_dispatches_by_path() {
+        let calls = Arc::new(AtomicU64::new(0));
+        let router = router(calls.clone());
+        let event = router
+            .dispatch(&signed_message("/webhooks/a", "secret-a"))
+            .unwrap();
+        assert_eq!(event, "a");
+        let event = router
+            .dispatch(&signed_message("/api/webhooks/b", "secret-b"))
+            .unwrap();
+        assert_eq!(event, "b {}");
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+
+        let err = router
+            .dispatch(&signed_message("/webhooks/c", "secret-a"))
+            .unwrap_err();
+        assert!(matches!(err, WebhookRouteError::NoRoute(ref e) if e == "/webhooks/c"));
+    }
+
+    #[test]
+    fn test_webhook_router_verification_failure_skips_handler() {
+        let calls = Arc::new(AtomicU64::new(0));
+        let router = router(calls.clone());
+        // signed for the other route
+        let err = router
+            .dispatch(&signed_message("/webhooks/a", "secret-b"))
+            .unwrap_err();
+        assert!(matches!(err, WebhookRouteError::Verification(_)));
+        assert!(err.to_string().contains("bad signature"));
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_verify_hmac_sha256() {
+        // RFC 4231 test case 2
+        let sig = "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+        assert!(verify_hmac_sha256(b"Jefe", b"what do ya want for nothing?", sig).is_ok());
+        let upper = format!(" {} ", sig.to_uppercase());
+        assert!(verify_hmac_sha256(b"Jefe", b"what do ya want for nothing?", &upper).is_ok());
+
+        let err = verify_hmac_sha256(b"wrong", b"what do ya want for nothing?", sig).unwrap_err();
+        assert!(err.to_string().contains("No valid signature found"));
+        assert!(verify_hmac_sha256(b"Jefe", b"what do ya want for something?", sig).is_err());
+        // a truncated signature must not match its prefix
+        assert!(verify_hmac_sha256(b"Jefe", b"what do ya want for nothing?", &sig[..32]).is_err());
+
+        let err = verify_hmac_sha256(b"Jefe", b"", "not-hex").unwrap_err();
+        assert!(err.to_string().contains("Invalid signature encoding"));
+        assert!(verify_hmac_sha256(b"Jefe", b"", "abc").is_err());
+    }
+
+    #[cfg(feature = "actix")]
+    #[tokio::test]
+    async fn test_actix_extract_webhook_message() {
+        use actix_web::FromRequest;
+        use actix_web::test::TestRequest;
+
+        let (req, mut payload) = TestRequest::post()
+            .uri("/webhooks/stripe?foo=bar")
+            .insert_header(("stripe-signature", "t=1,v1=abc"))
+            .set_payload(b"{\"id\":\"evt_1\"}".as_slice())
+            .to_http_parts();
+        let msg = WebhookMessage::from_request(&req, &mut payload)
+            .await
+            .unwrap();
+        assert_eq!(msg.endpoint, "/webhooks/stripe");
+        assert_eq!(msg.query.as_deref(), Some("foo=bar"));
+        assert_eq!(msg.body, b"{\"id\":\"evt_1\"}");
+        assert_eq!(
+            msg.headers.get("stripe-signature").map(String::as_str),
+            Some("t=1,v1=abc")
+        );
+    }
+
+    #[cfg(feature = "actix")]
+    #[tokio::test]
+    async fn test_actix_extract_webhook_message_too_large() {
+        use actix_web::FromRequest;
+        use actix_web::test::TestRequest;
+
+        let (req, mut payload) = TestRequest::post()
+            .uri("/webhooks/stripe")
+            .app_data(WebhookPayloadConfig { limit: 4 })
+            .set_payload(b"too large".as_slice())
+            .to_http_parts();
+        let err = WebhookMessage::from_request(&req, &mut payload)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            actix_web::http::StatusCode::PAYLOAD_TOO_LARGE
+        );
+    }
+
+    #[cfg(feature = "rocket")]
+    #[rocket::post("/webhooks/<_provider>", data = "<msg>")]
+    fn rocket_webhook(_provider: &str, msg: WebhookMessage) -> Vec<u8> {
+        msg.body
+    }
+
+    #[cfg(feature = "rocket")]
+    #[tokio::test]
+    async fn test_rocket_webhook_message_limit() {
+        use rocket::http::Status;
+        use rocket::local::asynchronous::Client;
+
+        let rocket = rocket::build()
+            .manage(WebhookPayloadConfig { limit: 8 })
+            .mount("/", rocket::routes![rocket_webhook]);
+        let client = Client::untracked(rocket).await.unwrap();
+
+        let rsp = client
+            .post("/webhooks/stripe")
+            .body("{\"id\":1}")
+            .dispatch()
+            .await;
+        assert_eq!(rsp.status(), Status::Ok);
+        assert_eq!(rsp.into_string().await.as_deref(), Some("{\"id\":1}"));
+
+        let rsp = client
+            .post("/webhooks/stripe")
+            .body("{\"id\":100}")
+            .dispatch()
+            .await;
+        assert_eq!(rsp.status(), Status::PayloadTooLarge);
+    }
+
+    #[cfg(feature = "rocket")]
+    #[rocket::post("/bridged/<_provider>", data = "<msg>")]
+    fn rocket_bridged_webhook(_provider: &str, msg: BridgedWebhookMessage) -> String {
+        msg.endpoint.clone()
+    }
+
+    #[cfg(feature = "rocket")]
+    #[tokio::test]
+    async fn test_rocket_bridged_webhook_message() {
+        use rocket::local::asynchronous::Client;
+
+        // the global bridge is shared with other tests, only look at this path
+        let mut rx = Box::pin(WEBHOOK_BRIDGE.listen_path("/bridged/stripe"));
+        let rocket = rocket::build().mount("/", rocket::routes![rocket_bridged_webhook]);
+        let client = Client::untracked(rocket).await.unwrap();
+        let rsp = client
+            .post("/bridged/stripe?foo=bar")
+            .body("{\"id\":\"evt_1\"}")
+            .dispatch()
+            .await;
+        assert_eq!(rsp.into_string().await.as_deref(), Some("/bridged/stripe"));
+
+        let msg = tokio::time::timeout(Duration::from_secs(5), rx.next())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(msg.query.as_deref(), Some("foo=bar"));
+        assert_eq!(msg.body, b"{\"id\":\"evt_1\"}");
+    }
+
+    #[test]
+    fn test_webhook_message_query_param() {
+        let msg = WebhookMessage {
+            endpoint: "/webhooks/square".to_string(),
+            query: Some("ref=order%2042&sig=a+b&empty=&ref=second".to_string()),
+            body: vec![],
+            headers: HashMap::new(),
+        };
+        assert_eq!(msg.query_param("ref").as_deref(), Some("order 42"));
+        assert_eq!(msg.query_param("sig").as_deref(), Some("a b"));
+        assert_eq!(msg.query_param("empty").as_deref(), Some(""));
+        assert_eq!(msg.query_param("missing"), None);
+
+        let no_query = WebhookMessage { query: None, ..msg };
+        assert_eq!(no_query.query_param("ref"), None);
+    }
+
+    #[test]
+    fn test_webhook_deduplicator() {
+        let dedup = WebhookDeduplicator::default();
+        assert!(dedup.check_and_record("evt_1"));
+        assert!(!dedup.check_and_record("evt_1"));
+        assert!(dedup.check_and_record("evt_2"));
+        dedup.forget("evt_1");
+        assert!(dedup.check_and_record("evt_1"));
+
+        let expired = WebhookDeduplicator::new(Duration::ZERO);
+        assert!(expired.check_and_record("evt_1"));
+        assert!(expired.check_and_record("evt_1"));
+    }
+
     #[test]
     fn test_webhook_bridge_new() {
         let bridge = WebhookBridge::new();
@@ -152,6 +826,113 @@ mod tests {
         let _rx = bridge.listen();
     }
 
+    #[test]
+    fn test_webhook_bridge_capacity() {
+        assert_eq!(WebhookBridge::new().capacity(), 100);
+        assert_eq!(WebhookBridge::with_capacity(8).capacity(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_bridge_reports_lag() {
+        let bridge = WebhookBridge::with_capacity(2);
+        let mut rx = Box::pin(bridge.stream());
+        for i in 0..5u8 {
+            bridge.send(WebhookMessage {
+                endpoint: "/test".to_string(),
+                query: None,
+                body: vec![i],
+                headers: HashMap::new(),
+            });
+        }
+
+        assert!(matches!(
+            rx.next().await,
+            Some(Err(BroadcastStreamRecvError::Lagged(3)))
+        ));
+        assert_eq!(bridge.lagged_count(), 3);
+        // The newest messages are still delivered
+        assert_eq!(rx.next().await.unwrap().unwrap().body, vec![3]);
+        assert_eq!(rx.next().await.unwrap().unwrap().body, vec![4]);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_bridge_listen_path() {
+        let bridge = WebhookBridge::new();
+        let mut stripe = Box::pin(bridge.listen_path("/webhooks/stripe"));
+        let mut revolut = Box::pin(bridge.listen_path("/webhooks/revolut"));
+        for (endpoint, body) in [
+            ("/webhooks/stripe", b"s1"),
+            ("/webhooks/revolut", b"r1"),
+            ("/webhooks/other", b"o1"),
+            ("/webhooks/stripe", b"s2"),
+        ] {
+            bridge.send(WebhookMessage {
+                endpoint: endpoint.to_string(),
+                query: None,
+                body: body.to_vec(),
+                headers: HashMap::new(),
+            });
+        }
+        drop(bridge);
+
+        let stripe_bodies: Vec<_> = stripe.by_ref().map(|m| m.body).collect().await;
+        assert_eq!(stripe_bodies, vec![b"s1".to_vec(), b"s2".to_vec()]);
+        let revolut_bodies: Vec<_> = revolut.by_ref().map(|m| m.body).collect().await;
+        assert_eq!(revolut_bodies, vec![b"r1".to_vec()]);
+    }
+
+    #[test]
+    fn test_webhook_path_matcher_exact() {
+        let m = WebhookPathMatcher::from("/webhooks/bitvora");
+        assert!(m.matches("/webhooks/bitvora"));
+        assert!(!m.matches("/api/webhooks/bitvora"));
+        assert!(!m.matches("/webhooks/bitvora/"));
+    }
+
+    #[test]
+    fn test_webhook_path_matcher_suffix() {
+        let m = WebhookPathMatcher::Suffix("/webhooks/bitvora".to_string());
+        assert!(m.matches("/webhooks/bitvora"));
+        assert!(m.matches("/api/v1/webhooks/bitvora"));
+        assert!(!m.matches("/webhooks/bitvora2"));
+
+        let m = WebhookPathMatcher::Suffix("bitvora".to_string());
+        assert!(m.matches("/webhooks/bitvora"));
+        assert!(m.matches("bitvora"));
+        assert!(!m.matches("/webhooks/notbitvora"));
+    }
+
+    #[test]
+    fn test_webhook_path_matcher_strip_prefix() {
+        let m = WebhookPathMatcher::StripPrefix {
+            prefix: "/api/".to_string(),
+            path: "/webhooks/bitvora".to_string(),
+        };
+        assert!(m.matches("/api/webhooks/bitvora"));
+        assert!(!m.matches("/webhooks/bitvora"));
+        assert!(!m.matches("/api/webhooks/stripe"));
+        assert!(!m.matches("/other/webhooks/bitvora"));
+    }
+
+    #[tokio::test]
+    async fn test_webhook_bridge_listen_path_matcher() {
+        let bridge = WebhookBridge::new();
+        let rx = Box::pin(
+            bridge.listen_path(WebhookPathMatcher::Suffix("/webhooks/bitvora".to_string())),
+        );
+        for endpoint in ["/staging/webhooks/bitvora", "/webhooks/stripe"] {
+            bridge.send(WebhookMessage {
+                endpoint: endpoint.to_string(),
+                query: None,
+                body: vec![],
+                headers: HashMap::new(),
+            });
+        }
+        drop(bridge);
+        let endpoints: Vec<_> = rx.map(|m| m.endpoint).collect().await;
+        assert_eq!(endpoints, vec!["/staging/webhooks/bitvora".to_string()]);
+    }
+
     #[test]
     fn test_webhook_bridge_default() {
         let bridge = WebhookBridge::default();
@@ -164,6 +945,7 @@ mod tests {
         // Should not panic when sending without listeners
         bridge.send(WebhookMessage {
             endpoint: "/test".to_string(),
+            query: None,
             body: vec![1, 2, 3],
             headers: HashMap::new(),
         });
@@ -176,6 +958,7 @@ mod tests {
 
         let msg = WebhookMessage {
             endpoint: "/webhooks/test".to_string(),
+            query: None,
             body: b"test body".to_vec(),
             headers: HashMap::from([("Content-Type".to_string(), "application/json".to_string())]),
         };
@@ -195,6 +978,7 @@ mod tests {
     fn test_webhook_message_clone() {
         let msg = WebhookMessage {
             endpoint: "/test".to_string(),
+            query: None,
             body: vec![1, 2, 3],
             headers: HashMap::new(),
         };
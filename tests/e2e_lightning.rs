@@ -31,6 +31,7 @@ async fn add_invoice_against_real_lnd() {
             amount: 100_000, // 100k msat = 100 sats
             memo: Some("integration test".to_string()),
             expire: Some(3600),
+            idempotency_key: None,
         })
         .await
         .expect("create invoice");